@@ -0,0 +1,52 @@
+// times gathering the candidate hits of the camera rays through the seeded
+// random scene a 2x2 block of pixels at a time, walking the tree once for
+// all four rays against walking it once per ray, run with
+// cargo bench --bench packet
+use std::hint::black_box;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use raytracer::boundingvolume::{tree_filter, tree_filter_packet};
+use raytracer::geometry::DEFAULT_EPSILON;
+use raytracer::ray::Ray;
+use raytracer::scenegen;
+
+fn main() {
+    let scene = scenegen::gen_scene_from(&mut StdRng::seed_from_u64(405));
+    let tree = scene.tree.as_ref().expect("the random scene is all boxed");
+    let cam = scenegen::default_camera();
+    let ray = |i: u32, j: u32| Ray::new(cam.lookfrom, cam.get_pixel_centre(i, j) - cam.lookfrom);
+    let packets: Vec<[Ray; 4]> = (0..cam.vert_res / 2)
+        .flat_map(|j| (0..cam.horiz_res / 2).map(move |i| (2 * i, 2 * j)))
+        .map(|(i, j)| [ray(i, j), ray(i + 1, j), ray(i, j + 1), ray(i + 1, j + 1)])
+        .collect();
+    println!("{} packets of four camera rays", packets.len());
+
+    let mut subscenes: [Vec<_>; 4] = Default::default();
+    for round in 0..3 {
+        let start = Instant::now();
+        for rays in &packets {
+            for (subscene, ray) in subscenes.iter_mut().zip(black_box(rays)) {
+                subscene.clear();
+                tree_filter(tree, subscene, ray, DEFAULT_EPSILON);
+            }
+            black_box(&subscenes);
+        }
+        let scalar = start.elapsed();
+
+        let start = Instant::now();
+        for rays in &packets {
+            subscenes.iter_mut().for_each(Vec::clear);
+            tree_filter_packet(tree, &mut subscenes, black_box(rays), DEFAULT_EPSILON);
+            black_box(&subscenes);
+        }
+        let packet = start.elapsed();
+        println!(
+            "round {}: four tree_filter calls {:>7.1}ms, tree_filter_packet {:>7.1}ms",
+            round,
+            scalar.as_secs_f64() * 1000.0,
+            packet.as_secs_f64() * 1000.0
+        );
+    }
+}
@@ -0,0 +1,84 @@
+// counts the allocations and times the nearest hits of the camera rays
+// through the seeded random scene, gathering the candidates into a fresh
+// Vec per ray, into one buffer cleared between rays, and walking the tree
+// for the nearest alone, run with cargo bench --bench scratch
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use raytracer::boundingvolume::CoveredScene;
+use raytracer::geometry::DEFAULT_EPSILON;
+use raytracer::ray::Ray;
+use raytracer::{cmp_intersection, scenegen, Hittable};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn nearest_of(subscene: &[(&Hittable, Option<f64>)]) -> Option<f64> {
+    subscene
+        .iter()
+        .map(|(_, param)| *param)
+        .min_by(|x, y| cmp_intersection(*x, *y))
+        .flatten()
+}
+
+fn measure<F: FnMut(&Ray) -> Option<f64>>(label: &str, rays: &[Ray], mut nearest: F) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for ray in rays {
+        black_box(nearest(black_box(ray)));
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "{:<24} {:>8} allocations {:>8.1}ms",
+        label,
+        allocations,
+        elapsed.as_secs_f64() * 1000.0
+    );
+}
+
+fn main() {
+    let scene: CoveredScene = scenegen::gen_scene_from(&mut StdRng::seed_from_u64(405));
+    let cam = scenegen::default_camera();
+    let rays: Vec<Ray> = (0..cam.vert_res)
+        .flat_map(|j| (0..cam.horiz_res).map(move |i| (i, j)))
+        .map(|(i, j)| Ray::new(cam.lookfrom, cam.get_pixel_centre(i, j) - cam.lookfrom))
+        .collect();
+    println!("{} camera rays", rays.len());
+
+    measure("fresh buffer per ray", &rays, |ray| {
+        let mut subscene = Vec::new();
+        scene.filter(&mut subscene, ray, DEFAULT_EPSILON);
+        nearest_of(&subscene)
+    });
+    let mut scratch = Vec::new();
+    measure("reused buffer", &rays, |ray| {
+        scratch.clear();
+        scene.filter(&mut scratch, ray, DEFAULT_EPSILON);
+        nearest_of(&scratch)
+    });
+    measure("nearest hit", &rays, |ray| {
+        scene
+            .nearest_hit(ray, DEFAULT_EPSILON)
+            .map(|(_, param)| param)
+    });
+}
@@ -0,0 +1,41 @@
+// times the Vec3 norm, dot and cross products over a few million pairs of
+// vectors, run with cargo bench --bench vector
+use std::hint::black_box;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use raytracer::vector::Vec3;
+
+const PAIRS: usize = 1 << 12;
+const ROUNDS: usize = 1 << 10;
+
+fn measure<F: FnMut(&Vec3, &Vec3) -> f64>(label: &str, pairs: &[(Vec3, Vec3)], mut product: F) {
+    // written out rather than summed so the adds don't chain the calls
+    let mut products = vec![0.0; pairs.len()];
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for ((u, v), out) in black_box(pairs).iter().zip(&mut products) {
+            *out = product(u, v);
+        }
+        black_box(&mut products);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let calls = (PAIRS * ROUNDS) as f64;
+    println!(
+        "{:<8} {:>8.2}ns per call {:>8.0}M calls/s",
+        label,
+        1.0e9 * elapsed / calls,
+        calls / elapsed / 1.0e6
+    );
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(432);
+    let mut random_vec3 = || Vec3([rng.gen(), rng.gen(), rng.gen()]);
+    let pairs: Vec<(Vec3, Vec3)> = (0..PAIRS).map(|_| (random_vec3(), random_vec3())).collect();
+
+    measure("norm", &pairs, |u, _| u.norm());
+    measure("dotprod", &pairs, |u, v| u.dotprod(v));
+    measure("cross", &pairs, |u, v| u.cross(v)[0]);
+}
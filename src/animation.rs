@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::boundingvolume::CoveredScene;
+use crate::camera::Camera;
+use crate::config::SetupCamera;
+use crate::settings::RenderSettings;
+use crate::vector::{lerp_vec3, slerp_vec3_around};
+
+// the camera a fraction t of the way from one keyframe to the next, the
+// lens moves in a straight line while the view direction and up turn
+// steadily, the resolution is that of the first keyframe, a view turned
+// right round pans about up and an up turned over rolls about the view
+pub fn interpolate_camera(from: &Camera, to: &Camera, t: f64) -> Camera {
+    let lerp = |p: f64, q: f64| (1.0 - t) * p + t * q;
+    let lookfrom = lerp_vec3(from.lookfrom, to.lookfrom, t);
+    let from_direction = (from.lookat - from.lookfrom).normalize();
+    let direction = slerp_vec3_around(
+        from_direction,
+        (to.lookat - to.lookfrom).normalize(),
+        t,
+        from.lookup,
+    );
+    let lookup = slerp_vec3_around(
+        from.lookup.normalize(),
+        to.lookup.normalize(),
+        t,
+        from_direction,
+    );
+    Camera::build_with_up(
+        lookfrom + lerp(from.focal_distance, to.focal_distance) * direction,
+        lookfrom,
+        lookup,
+        lerp(from.inv_focal_length, to.inv_focal_length),
+        lerp(from.aperture, to.aperture),
+        from.horiz_res,
+        from.vert_res,
+    )
+    .with_aperture_shape(from.aperture_shape)
+}
+
+// frames_per_segment cameras from each keyframe up to the next, ending on
+// the last keyframe itself
+pub fn keyframe_cameras(keyframes: &[SetupCamera], frames_per_segment: u32) -> Vec<Camera> {
+    let mut keyframes: Vec<Camera> = keyframes.iter().map(SetupCamera::setup).collect();
+    let mut cameras: Vec<Camera> = keyframes
+        .windows(2)
+        .flat_map(|segment| {
+            (0..frames_per_segment).map(move |frame| {
+                let t = f64::from(frame) / f64::from(frames_per_segment);
+                interpolate_camera(&segment[0], &segment[1], t)
+            })
+        })
+        .collect();
+    cameras.extend(keyframes.pop());
+    cameras
+}
+
+// renders the flythrough as frame_0000.ppm, frame_0001.ppm, ... in out_dir,
+// returning the paths written
+pub fn render_animation(
+    keyframes: &[SetupCamera],
+    frames_per_segment: u32,
+    scene: &CoveredScene,
+    settings: &RenderSettings,
+    spp: u32,
+    out_dir: &Path,
+) -> io::Result<Vec<PathBuf>> {
+    let cameras = keyframe_cameras(keyframes, frames_per_segment);
+    let mut written = Vec::with_capacity(cameras.len());
+    for (frame, cam) in cameras.iter().enumerate() {
+        info!("Rendering frame {} of {}", frame + 1, cameras.len());
+        let path = out_dir.join(format!("frame_{:04}.ppm", frame));
+        let mut file = BufWriter::new(File::create(&path)?);
+        crate::accel_render_into_file(&mut file, cam, scene, settings, spp);
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::Vec3;
+
+    fn keyframe(lookat: [f64; 3], lookfrom: [f64; 3]) -> SetupCamera {
+        SetupCamera::LookAt {
+            lookat: Vec3(lookat),
+            lookfrom: Vec3(lookfrom),
+            inv_focal_length: 0.5,
+            aperture: 0.0,
+            horiz_res: 4,
+            vert_res: 3,
+            aperture_shape: Default::default(),
+        }
+    }
+
+    #[test]
+    fn keyframe_cameras_test() {
+        let keyframes = [
+            keyframe([0.0, 0.0, 0.0], [0.0, 0.0, -4.0]),
+            keyframe([0.0, 0.0, 0.0], [4.0, 0.0, 0.0]),
+        ];
+        let cameras = keyframe_cameras(&keyframes, 2);
+        assert_eq!(cameras.len(), 3);
+
+        let close = |p: Vec3, q: Vec3| (p - q).norm() < 1.0e-12;
+        assert!(close(cameras[0].lookfrom, Vec3([0.0, 0.0, -4.0])));
+        assert!(close(cameras[0].lookat, Vec3([0.0, 0.0, 0.0])));
+        assert!(close(cameras[2].lookfrom, Vec3([4.0, 0.0, 0.0])));
+        assert!(close(cameras[2].lookat, Vec3([0.0, 0.0, 0.0])));
+
+        // halfway the lens is midway along the line and looks halfway round
+        let halfway = &cameras[1];
+        assert!(close(halfway.lookfrom, Vec3([2.0, 0.0, -2.0])));
+        let direction = (halfway.lookat - halfway.lookfrom).normalize();
+        let expected = Vec3([-1.0, 0.0, 1.0]).normalize();
+        assert!(close(direction, expected));
+        assert!((halfway.focal_distance - 4.0).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn turn_around_test() {
+        let keyframes = [
+            keyframe([0.0, 0.0, 0.0], [0.0, 0.0, -4.0]),
+            keyframe([0.0, 0.0, -8.0], [0.0, 0.0, -4.0]),
+        ];
+        let halfway = &keyframe_cameras(&keyframes, 2)[1];
+        // panning about up, so looking off to the side and still level
+        let direction = (halfway.lookat - halfway.lookfrom).normalize();
+        assert!((direction[0].abs() - 1.0).abs() < 1.0e-9);
+        let arms = [halfway.horiz_arm, halfway.vert_arm];
+        assert!(arms
+            .iter()
+            .flat_map(|arm| arm.iter())
+            .all(|coord| coord.is_finite()));
+        assert!(halfway.horiz_arm[1].abs() < 1.0e-9 && halfway.vert_arm[1].abs() > 0.0);
+    }
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+// what a ray sees once it escapes the scene
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "BackgroundRepr")]
+pub enum Background {
+    // the white to blue sky blended on the ray direction along axis, white
+    // facing away from it and blue facing along it
+    Gradient { axis: Vec3 },
+    Solid(Color),
+    // black like Solid, but meaning the scene is lit only by its emitters
+    None,
+}
+
+// a Background can be written in a scene file as {"Gradient": {"axis"}},
+// {"Solid": color} or "None", or as the plain "Gradient" of the sky up +y
+// that scene files had before the axis could be set
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BackgroundRepr {
+    Plain(PlainBackground),
+    Tagged(TaggedBackground),
+}
+
+#[derive(Deserialize)]
+enum PlainBackground {
+    Gradient,
+}
+
+#[derive(Deserialize)]
+enum TaggedBackground {
+    Gradient {
+        #[serde(default = "up_axis")]
+        axis: Vec3,
+    },
+    Solid(Color),
+    None,
+}
+
+impl From<BackgroundRepr> for Background {
+    fn from(repr: BackgroundRepr) -> Background {
+        match repr {
+            BackgroundRepr::Plain(PlainBackground::Gradient) => Background::default(),
+            BackgroundRepr::Tagged(TaggedBackground::Gradient { axis }) => {
+                Background::Gradient { axis }
+            }
+            BackgroundRepr::Tagged(TaggedBackground::Solid(color)) => Background::Solid(color),
+            BackgroundRepr::Tagged(TaggedBackground::None) => Background::None,
+        }
+    }
+}
+
+fn up_axis() -> Vec3 {
+    Vec3([0.0, 1.0, 0.0])
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Gradient { axis: up_axis() }
+    }
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Background::Gradient { axis } => {
+                let t = 0.5 * (ray.dir.dotprod(&axis.normalize()) + 1.0);
+                (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+            }
+            Background::Solid(color) => *color,
+            Background::None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradient_axis_test() {
+        let color_along = |background: &Background, dir: [f64; 3]| {
+            background
+                .color(&Ray::new(Vec3([0.0, 0.0, 0.0]), Vec3(dir)))
+                .r
+        };
+        // the default sky still blends from the bottom up
+        let sky = Background::default();
+        assert_eq!(color_along(&sky, [0.0, 1.0, 0.0]), 0.5);
+        assert_eq!(color_along(&sky, [0.0, -1.0, 0.0]), 1.0);
+        assert_eq!(color_along(&sky, [1.0, 0.0, 0.0]), 0.75);
+
+        let horizontal: Background =
+            serde_json::from_str(r#"{"Gradient": {"axis": [2.0, 0.0, 0.0]}}"#)
+                .expect("background should deserialize");
+        assert_eq!(color_along(&horizontal, [1.0, 0.0, 0.0]), 0.5);
+        assert_eq!(color_along(&horizontal, [-1.0, 0.0, 0.0]), 1.0);
+        // straight up and down are both halfway across
+        assert_eq!(color_along(&horizontal, [0.0, 1.0, 0.0]), 0.75);
+        assert_eq!(color_along(&horizontal, [0.0, -1.0, 0.0]), 0.75);
+
+        let no_axis: Background = serde_json::from_str(r#"{"Gradient": {}}"#).unwrap();
+        assert_eq!(color_along(&no_axis, [0.0, 1.0, 0.0]), 0.5);
+        // as scene files wrote the sky before it had an axis
+        let plain: Background = serde_json::from_str(r#""Gradient""#).unwrap();
+        assert_eq!(color_along(&plain, [0.0, 1.0, 0.0]), 0.5);
+        assert_eq!(color_along(&plain, [0.0, -1.0, 0.0]), 1.0);
+    }
+
+    #[test]
+    fn background_round_trip_test() {
+        for background in [
+            Background::Gradient {
+                axis: Vec3([0.0, 0.0, 1.0]),
+            },
+            Background::Solid(Color::new(0.1, 0.2, 0.3)),
+            Background::None,
+        ] {
+            let written = serde_json::to_string(&background).unwrap();
+            let reread: Background = serde_json::from_str(&written).unwrap();
+            let dir = Ray::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 0.0, 1.0]));
+            assert_eq!(reread.color(&dir).g, background.color(&dir).g);
+        }
+        assert!(serde_json::from_str::<Background>(r#""Sky""#).is_err());
+    }
+}
@@ -5,6 +5,7 @@ use std::ops::{Deref, DerefMut};
 use crate::interval;
 
 use crate::geometry;
+use crate::intervals;
 use crate::intervals::{cover, get_larger, intersection, Interval};
 use crate::ray::Ray;
 use crate::vector::Vec3;
@@ -17,6 +18,8 @@ use crate::Hittable;
 pub struct BoundingBox {
     dims: [Interval; 3],
     boxed: Option<Hittable>,
+    // the position of the boxed hittable in the list the scene was made from
+    source: usize,
 }
 
 impl Default for BoundingBox {
@@ -24,6 +27,7 @@ impl Default for BoundingBox {
         BoundingBox {
             dims: [interval!(0.0, 0.0); 3],
             boxed: None,
+            source: 0,
         }
     }
 }
@@ -54,11 +58,16 @@ impl BoundingBox {
         BoundingBox {
             dims: [interval!(0.0, 0.0); 3],
             boxed: None,
+            source: 0,
         }
     }
 
     fn with_dims(dims: [Interval; 3]) -> BoundingBox {
-        BoundingBox { dims, boxed: None }
+        BoundingBox {
+            dims,
+            boxed: None,
+            source: 0,
+        }
     }
 
     fn dims_copy(&self) -> BoundingBox {
@@ -68,6 +77,12 @@ impl BoundingBox {
     // the function should return true if there exists some time parameter
     // for which (ray.orig + t * ray.dir) is in the BoundingBox
     pub fn check_intersection(&self, ray: &Ray) -> bool {
+        self.slab_overlap(ray).is_some()
+    }
+
+    // the parameters along the ray's line, behind its origin as well as in
+    // front, over which it is inside the box
+    fn slab_overlap(&self, ray: &Ray) -> Option<Interval> {
         // the times are generated from the bbox.dims and ray.orig, ray.dir
         // which is difficult to zip [(interval, orig, dir)]
         let mut times = [interval!(0.0, 0.0); 3];
@@ -88,10 +103,8 @@ impl BoundingBox {
             }
         }
 
-        let Some(xy) = intersection(&times[0], &times[1]) else {
-            return false;
-        };
-        return intersection(&xy, &times[2]).is_some();
+        let xy = intersection(&times[0], &times[1])?;
+        intersection(&xy, &times[2])
     }
 
     // for use in the node split by longest axis
@@ -125,6 +138,7 @@ fn make_cover_of(bbox1: &BoundingBox, bbox2: &BoundingBox) -> BoundingBox {
             cover(&bbox1.dims[2], &bbox2.dims[2]),
         ],
         boxed: None,
+        source: 0,
     }
 }
 
@@ -175,6 +189,10 @@ pub struct CoveringTree {
     pub cover: BoundingBox,
     pub left: Option<Box<CoveringTree>>,
     pub right: Option<Box<CoveringTree>>,
+    // the hittables of a leaf holding several, tested one after another
+    // once a ray enters its cover, which then boxes none itself
+    pub bundle: Vec<Hittable>,
+    bundle_sources: Vec<usize>,
 }
 
 impl CoveringTree {
@@ -183,18 +201,121 @@ impl CoveringTree {
             cover: boxes.make_all_covering(),
             left: None,
             right: None,
+            bundle: Vec::new(),
+            bundle_sources: Vec::new(),
         }
     }
+
+    // the number of levels, a single leaf has depth 1
+    pub fn depth(&self) -> usize {
+        let left_depth = self.left.as_ref().map_or(0, |left| left.depth());
+        let right_depth = self.right.as_ref().map_or(0, |right| right.depth());
+        1 + left_depth.max(right_depth)
+    }
+
+    pub fn node_count(&self) -> usize {
+        let left_count = self.left.as_ref().map_or(0, |left| left.node_count());
+        let right_count = self.right.as_ref().map_or(0, |right| right.node_count());
+        1 + left_count + right_count
+    }
+
+    // updates the covers bottom-up once the boxed hittables have moved,
+    // boxes gives their new extents in the order collect_boxed finds them,
+    // which is the order make_coveringtree left its slice in,
+    // the shape of the tree is kept so nothing can be added or removed
+    pub fn refit(&mut self, boxes: &[BoundingBox]) {
+        let refitted = self.refit_leaves(boxes);
+        debug_assert_eq!(refitted, boxes.len(), "refit with a box per hittable");
+    }
+
+    // pushes the boxed hittables of the leaves from left to right
+    fn collect_boxed<'a>(&'a self, hittables: &mut Vec<&'a Hittable>) {
+        if let Some(hittable) = &self.cover.boxed {
+            hittables.push(hittable);
+        }
+        hittables.extend(self.bundle.iter());
+        for child in [&self.left, &self.right].into_iter().flatten() {
+            child.collect_boxed(hittables);
+        }
+    }
+
+    // the boxed hittables in the order collect_boxed pushes them, each with
+    // its position in the list the tree was made from, only the path down
+    // to the current node being held on to
+    fn boxed_with_sources(&self) -> impl Iterator<Item = (usize, &Hittable)> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            // the right child goes on first to come off after the left
+            stack.extend(
+                [&node.right, &node.left]
+                    .into_iter()
+                    .flatten()
+                    .map(|child| &**child),
+            );
+            Some(node)
+        })
+        .flat_map(|node| {
+            let boxed = node
+                .cover
+                .boxed
+                .as_ref()
+                .map(|hittable| (node.cover.source, hittable));
+            boxed
+                .into_iter()
+                .chain(node.bundle_sources.iter().copied().zip(&node.bundle))
+        })
+    }
+
+    // the number of boxes refitted from the front of boxes
+    fn refit_leaves(&mut self, boxes: &[BoundingBox]) -> usize {
+        if self.left.is_none() && self.right.is_none() {
+            let count = self.bundle.len().max(1);
+            self.cover.dims = tight_dims(&boxes[..count]);
+            return count;
+        }
+        let mut refitted = 0;
+        let mut cover: Option<[Interval; 3]> = None;
+        for child in [&mut self.left, &mut self.right].into_iter().flatten() {
+            refitted += child.refit_leaves(&boxes[refitted..]);
+            let child_dims = child.cover.dims;
+            cover = Some(match cover {
+                Some(dims) => [0, 1, 2].map(|i| intervals::cover(&dims[i], &child_dims[i])),
+                None => child_dims,
+            });
+        }
+        self.cover.dims = cover.unwrap();
+        refitted
+    }
 }
 
 // there is a problem in the allocation...
 // Box is still dropped at the end of the function...
 pub fn make_coveringtree(boxes: &mut [BoundingBox]) -> Box<CoveringTree> {
+    make_coveringtree_to_depth(boxes, MAX_DEPTH)
+}
+
+// as make_coveringtree, but once the tree is max_depth levels deep the
+// boxes left are bundled into one leaf however many there are, rather
+// than split on for boxes that no split can separate
+pub fn make_coveringtree_to_depth(
+    boxes: &mut [BoundingBox],
+    max_depth: usize,
+) -> Box<CoveringTree> {
     if boxes.len() > 1 {
         let mut tree = CoveringTree::make_from(boxes);
+        if max_depth <= 1 {
+            bundle_boxes(&mut tree, boxes);
+            return Box::new(tree);
+        }
+        let count = boxes.len();
         let (left_half, right_half) = split_on_covering(boxes);
-        tree.left = Some(make_coveringtree(left_half));
-        tree.right = Some(make_coveringtree(right_half));
+        if count <= MAX_BUNDLE && !split_pays(left_half, right_half) {
+            bundle_boxes(&mut tree, boxes);
+            return Box::new(tree);
+        }
+        tree.left = Some(make_coveringtree_to_depth(left_half, max_depth - 1));
+        tree.right = Some(make_coveringtree_to_depth(right_half, max_depth - 1));
 
         Box::new(tree)
     } else {
@@ -203,10 +324,58 @@ pub fn make_coveringtree(boxes: &mut [BoundingBox]) -> Box<CoveringTree> {
             cover: std::mem::take(boxes.first_mut().unwrap()),
             left: None,
             right: None,
+            bundle: Vec::new(),
+            bundle_sources: Vec::new(),
         })
     }
 }
 
+// moves the hittables of boxes into the bundle of the leaf tree
+fn bundle_boxes(tree: &mut CoveringTree, boxes: &mut [BoundingBox]) {
+    for bbox in boxes.iter_mut() {
+        if let Some(hittable) = bbox.boxed.take() {
+            tree.bundle.push(hittable);
+            tree.bundle_sources.push(bbox.source);
+        }
+    }
+}
+
+// the most hittables a leaf holds when splitting them further doesn't pay
+const MAX_BUNDLE: usize = 4;
+
+// the depth make_coveringtree stops splitting at, far beyond the depth of
+// halving any scene that fits in memory
+pub const MAX_DEPTH: usize = 64;
+
+// whether a split is expected to take fewer tests than the leaf testing
+// every hittable, a ray through the boxes entering each child with the
+// chance of their ratio of surface areas and testing the child's box first,
+// the areas all being of tight dims as the cover stretches to the origin
+fn split_pays(left: &[BoundingBox], right: &[BoundingBox]) -> bool {
+    let (left_dims, right_dims) = (tight_dims(left), tight_dims(right));
+    let area = surface_area(&[0, 1, 2].map(|i| intervals::cover(&left_dims[i], &right_dims[i])));
+    if area <= 0.0 {
+        return true;
+    }
+    let child_cost =
+        |dims: &[Interval; 3], count: usize| surface_area(dims) / area * (1.0 + count as f64);
+    child_cost(&left_dims, left.len()) + child_cost(&right_dims, right.len())
+        < (left.len() + right.len()) as f64
+}
+
+// the dims covering a non-empty slice of boxes, unlike make_all_covering
+// this doesn't stretch them to reach the origin
+fn tight_dims(boxes: &[BoundingBox]) -> [Interval; 3] {
+    boxes[1..].iter().fold(boxes[0].dims, |dims, bbox| {
+        [0, 1, 2].map(|i| intervals::cover(&dims[i], &bbox.dims[i]))
+    })
+}
+
+fn surface_area(dims: &[Interval; 3]) -> f64 {
+    let [x, y, z] = dims.map(|interval| interval.size());
+    2.0 * (x * y + y * z + z * x)
+}
+
 /* a traversal method on the CoveringTree is needed
 which tests for intersection and then on its children if true
 until no more children to test, whereupon it tests on the BoundingBox boxed Hittable
@@ -215,24 +384,172 @@ pub fn tree_filter<'a>(
     root: &'a Box<CoveringTree>,
     subscene: &mut Vec<(&'a Hittable, Option<f64>)>,
     ray: &Ray,
+    t_min: f64,
 ) {
     if root.cover.check_intersection(ray) {
         if let Some(hittable) = &root.cover.boxed {
-            let possible_param = hittable.shape.intersect(ray);
+            let possible_param = hittable.shape.intersect_beyond(ray, t_min);
             subscene.push((&hittable, possible_param));
         }
+        for hittable in &root.bundle {
+            subscene.push((hittable, hittable.shape.intersect_beyond(ray, t_min)));
+        }
         if let Some(left_root) = &root.left {
-            tree_filter(left_root, subscene, ray);
+            tree_filter(left_root, subscene, ray, t_min);
         }
         if let Some(right_root) = &root.right {
-            tree_filter(right_root, subscene, ray);
+            tree_filter(right_root, subscene, ray, t_min);
+        }
+    }
+}
+
+// the nearest hit beyond t_min in the tree, without pushing every candidate
+// as tree_filter does, skipping the nodes the ray only enters beyond the
+// nearest hit found so far and so searching the child it enters first first
+pub fn tree_nearest<'a>(
+    root: &'a CoveringTree,
+    ray: &Ray,
+    t_min: f64,
+) -> Option<(&'a Hittable, f64)> {
+    let entry = root.cover.slab_overlap(ray)?.start;
+    let mut nearest = None;
+    nearest_below(root, entry, ray, t_min, &mut nearest);
+    nearest
+}
+
+fn nearest_below<'a>(
+    root: &'a CoveringTree,
+    entry: f64,
+    ray: &Ray,
+    t_min: f64,
+    nearest: &mut Option<(&'a Hittable, f64)>,
+) {
+    if nearest.is_some_and(|(_, param)| entry > param) {
+        return;
+    }
+    for hittable in root.cover.boxed.iter().chain(&root.bundle) {
+        if let Some(param) = hittable.shape.intersect_beyond(ray, t_min) {
+            if nearest.is_none_or(|(_, nearest_param)| param < nearest_param) {
+                *nearest = Some((hittable, param));
+            }
         }
     }
+    let entered = |child: &'a Option<Box<CoveringTree>>| {
+        let child = child.as_deref()?;
+        Some((child, child.cover.slab_overlap(ray)?.start))
+    };
+    let mut children = [entered(&root.left), entered(&root.right)];
+    if let [Some((_, left_entry)), Some((_, right_entry))] = children {
+        if right_entry < left_entry {
+            children.swap(0, 1);
+        }
+    }
+    for (child, child_entry) in children.into_iter().flatten() {
+        nearest_below(child, child_entry, ray, t_min, nearest);
+    }
+}
+
+// whether the ray hits any hittable in the tree but target within
+// [t_min, t_max], stopping at the first one found rather than looking for
+// the nearest
+pub fn tree_any_hit(
+    root: &CoveringTree,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+    target: Option<&Hittable>,
+) -> bool {
+    if !root.cover.check_intersection(ray) {
+        return false;
+    }
+    root.cover
+        .boxed
+        .iter()
+        .chain(&root.bundle)
+        .filter(|hittable| target.is_none_or(|target| !std::ptr::eq(*hittable, target)))
+        .any(|hittable| hittable.shape.intersect_any(ray, t_min, t_max))
+        || [&root.left, &root.right]
+            .into_iter()
+            .flatten()
+            .any(|child| tree_any_hit(child, ray, t_min, t_max, target))
+}
+
+// as tree_filter for four rays at once, such as the primary rays of
+// neighbouring pixels, each node's box is tested against the rays that
+// reached it and the tree is walked once while any of them still hits,
+// on the random scene benches/packet.rs times it at about 223ms against
+// 221ms for four tree_filter calls over the 512x512 camera rays, no faster
+// yet as the box tests themselves are still done one ray at a time
+pub fn tree_filter_packet<'a>(
+    root: &'a CoveringTree,
+    subscenes: &mut [Vec<(&'a Hittable, Option<f64>)>; 4],
+    rays: &[Ray; 4],
+    t_min: f64,
+) {
+    packet_filter(root, subscenes, rays, [true; 4], t_min);
+}
+
+fn packet_filter<'a>(
+    root: &'a CoveringTree,
+    subscenes: &mut [Vec<(&'a Hittable, Option<f64>)>; 4],
+    rays: &[Ray; 4],
+    active: [bool; 4],
+    t_min: f64,
+) {
+    let mut active = active;
+    for (is_active, ray) in active.iter_mut().zip(rays) {
+        *is_active = *is_active && root.cover.check_intersection(ray);
+    }
+    if !active.contains(&true) {
+        return;
+    }
+    for hittable in root.cover.boxed.iter().chain(&root.bundle) {
+        for ((subscene, ray), _) in subscenes
+            .iter_mut()
+            .zip(rays)
+            .zip(active)
+            .filter(|(_, is_active)| *is_active)
+        {
+            subscene.push((hittable, hittable.shape.intersect_beyond(ray, t_min)));
+        }
+    }
+    for child in [&root.left, &root.right].into_iter().flatten() {
+        packet_filter(child, subscenes, rays, active, t_min);
+    }
+}
+
+// an axis-aligned disc has a flat box which the slab test in
+// check_intersection never enters, so flat axes are given some thickness,
+// this much in a scene of unit scale
+const MIN_HALF_THICKNESS: f64 = 1.0e-4;
+
+fn thicken(interval: Interval, half_thickness: f64) -> Interval {
+    if interval.size() < 2.0 * half_thickness {
+        let midpoint = interval.midpoint();
+        return interval!(midpoint - half_thickness, midpoint + half_thickness);
+    }
+    interval
 }
 
-pub trait Cover {
+pub trait Cover: Sized {
     // need to move the Hittable into the BoundingBox
-    fn make_covering(self) -> BoundingBox;
+    // or hand it back when there is no bounding box for it
+    fn try_make_covering(self) -> Result<BoundingBox, Self> {
+        self.try_make_covering_to_scale(1.0)
+    }
+
+    // as try_make_covering with flat boxes as thick as suits the scene_scale
+    fn try_make_covering_to_scale(self, scene_scale: f64) -> Result<BoundingBox, Self>;
+
+    // for the tests, which only cover what has a bounding box
+    #[cfg(test)]
+    fn make_covering(self) -> BoundingBox
+    where
+        Self: std::fmt::Debug,
+    {
+        self.try_make_covering()
+            .expect("hittable has no bounding box")
+    }
 }
 
 trait EachCover {
@@ -240,24 +557,178 @@ trait EachCover {
 }
 
 impl Cover for Hittable {
-    fn make_covering(self) -> BoundingBox {
+    fn try_make_covering_to_scale(self, scene_scale: f64) -> Result<BoundingBox, Self> {
         match &self.shape {
             geometry::Shape::Sphere(sphere) => {
+                // a negative radius only flips the normals, the extent is the same
+                let radius = sphere.radius.abs();
                 let dims: [Interval; 3] = sphere
                     .centre
-                    .map(|centre| interval!(centre - sphere.radius, centre + sphere.radius));
-                BoundingBox {
+                    .map(|centre| interval!(centre - radius, centre + radius));
+                Ok(BoundingBox {
                     dims,
                     boxed: Some(self),
-                }
+                    source: 0,
+                })
             }
-            _ => {
-                unimplemented!()
+            geometry::Shape::Disc(_)
+            | geometry::Shape::Quad(_)
+            | geometry::Shape::Triangle(_)
+            | geometry::Shape::Cuboid(_)
+            | geometry::Shape::Cylinder(_)
+            | geometry::Shape::SphereSection(_)
+            | geometry::Shape::Instance(_)
+                if self.shape.is_bounded() =>
+            {
+                let half_thickness = MIN_HALF_THICKNESS * scene_scale;
+                let dims: [Interval; 3] = geometry::BoundBox::surround(&self.shape)
+                    .map(|interval| thicken(interval, half_thickness));
+                Ok(BoundingBox {
+                    dims,
+                    boxed: Some(self),
+                    source: 0,
+                })
             }
+            _ => Err(self),
         }
     }
 }
 
+// a covering tree together with the hittables that have no bounding box,
+// which are tested against every ray instead
+pub struct CoveredScene {
+    pub tree: Option<Box<CoveringTree>>,
+    pub uncovered: Vec<Hittable>,
+    // the position of each of uncovered in the list the scene was made from
+    uncovered_sources: Vec<usize>,
+}
+
+impl CoveredScene {
+    pub fn new(hittables: Vec<Hittable>) -> CoveredScene {
+        CoveredScene::with_scale(hittables, 1.0)
+    }
+
+    // as new for a scene about scene_scale units across
+    pub fn with_scale(hittables: Vec<Hittable>, scene_scale: f64) -> CoveredScene {
+        let mut boxes = Vec::<BoundingBox>::new();
+        let mut uncovered = Vec::<Hittable>::new();
+        let mut uncovered_sources = Vec::new();
+        for (source, hittable) in hittables.into_iter().enumerate() {
+            match hittable.try_make_covering_to_scale(scene_scale) {
+                Ok(bbox) => boxes.push(BoundingBox { source, ..bbox }),
+                Err(hittable) => {
+                    uncovered.push(hittable);
+                    uncovered_sources.push(source);
+                }
+            }
+        }
+        if !uncovered.is_empty() {
+            warn!(
+                "{} hittables have no bounding box and are tested against every ray",
+                uncovered.len()
+            );
+        }
+        debug!("number of BoundingBox: {}", boxes.len());
+
+        let tree = if boxes.is_empty() {
+            None
+        } else {
+            Some(make_coveringtree(&mut boxes))
+        };
+        CoveredScene {
+            tree,
+            uncovered,
+            uncovered_sources,
+        }
+    }
+
+    // every hittable in the scene, those in the tree first, each with its
+    // position in the list the scene was made from so they can be picked
+    // out by their position in the scene file
+    pub fn hittables_with_sources(&self) -> impl Iterator<Item = (usize, &Hittable)> {
+        let in_tree = self.tree.iter().flat_map(|tree| tree.boxed_with_sources());
+        in_tree.chain(self.uncovered_sources.iter().copied().zip(&self.uncovered))
+    }
+
+    // every hittable in the scene, those in the tree first
+    pub fn hittables(&self) -> Vec<&Hittable> {
+        let mut hittables = Vec::new();
+        if let Some(tree) = &self.tree {
+            tree.collect_boxed(&mut hittables);
+        }
+        hittables.extend(self.uncovered.iter());
+        hittables
+    }
+
+    // as crate::is_occluded, only testing the hittables whose boxes the
+    // segment enters
+    pub fn is_occluded(
+        &self,
+        from: Vec3,
+        to: Vec3,
+        epsilon: f64,
+        target: Option<&Hittable>,
+    ) -> bool {
+        let ray = Ray::new(from, to - from);
+        let t_max = (to - from).norm() - epsilon;
+        self.tree
+            .as_ref()
+            .is_some_and(|tree| tree_any_hit(tree, &ray, epsilon, t_max, target))
+            || crate::is_occluded_along(&self.uncovered, &ray, epsilon, t_max, target)
+    }
+
+    // as scene_all_hits, only testing the hittables whose boxes the ray enters
+    pub fn all_hits(&self, ray: &Ray, t_min: f64) -> Vec<(&Hittable, f64)> {
+        let mut subscene = Vec::<(&Hittable, Option<f64>)>::new();
+        self.filter(&mut subscene, ray, t_min);
+        crate::sorted_hits(
+            subscene
+                .into_iter()
+                .filter(|(_, param)| param.is_some())
+                .map(|(hittable, _)| hittable),
+            ray,
+            t_min,
+        )
+    }
+
+    // the nearest hit beyond t_min, found without gathering the candidates
+    pub fn nearest_hit(&self, ray: &Ray, t_min: f64) -> Option<(&Hittable, f64)> {
+        let in_tree = self
+            .tree
+            .as_ref()
+            .and_then(|tree| tree_nearest(tree, ray, t_min));
+        let uncovered = self
+            .uncovered
+            .iter()
+            .map(|hittable| (hittable, hittable.shape.intersect_beyond(ray, t_min)));
+        crate::nearest_of(
+            in_tree
+                .map(|(hittable, param)| (hittable, Some(param)))
+                .into_iter()
+                .chain(uncovered),
+        )
+    }
+
+    // pushes every hittable the ray may hit along with its intersection
+    // beyond t_min
+    pub fn filter<'a>(
+        &'a self,
+        subscene: &mut Vec<(&'a Hittable, Option<f64>)>,
+        ray: &Ray,
+        t_min: f64,
+    ) {
+        if let Some(tree) = &self.tree {
+            tree_filter(tree, subscene, ray, t_min);
+        }
+        subscene.extend(
+            self.uncovered
+                .iter()
+                .map(|hittable| (hittable, hittable.shape.intersect_beyond(ray, t_min))),
+        );
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
     use crate::cmp_intersection;
@@ -275,17 +746,20 @@ mod tests {
         let ray = Ray {
             orig: Vec3([1.5, 0.5, 0.5]),
             dir: Vec3([1.0, 0.0, 0.0]),
+            spread: 0.0,
         };
         assert!(bbox.check_intersection(&ray));
         let reverseray = Ray {
             orig: Vec3([1.5, 0.5, 0.5]),
             dir: Vec3([-1.0, 0.0, 0.0]),
+            spread: 0.0,
         };
         assert!(bbox.check_intersection(&reverseray));
 
         let miss_ray = Ray {
             orig: Vec3([1.5, 1.5, 0.5]),
             dir: Vec3([1.0, 0.0, 0.0]),
+            spread: 0.0,
         };
         assert!(!bbox.check_intersection(&miss_ray));
     }
@@ -444,6 +918,7 @@ mod tests {
 
         let treebase = make_coveringtree(&mut list);
         assert!(treebase.right.is_some());
+        assert_eq!(treebase.depth(), 3);
 
         assert!(treebase.right.unwrap().cover == b1b2cover);
     }
@@ -453,15 +928,15 @@ mod tests {
         let mut covers = Vec::<BoundingBox>::new();
         let sphere1 = Sphere::new(Vec3([0.0, 0.0, 0.0]), 5.0);
         let material1 = Material::Diffuse {
-            albedo: Color::new(1.0, 1.0, 1.0),
+            albedo: Color::new(1.0, 1.0, 1.0).into(),
         };
         let sphere2 = Sphere::new(Vec3([0.0, 0.0, 2.0]), 1.0);
         let material2 = Material::Diffuse {
-            albedo: Color::new(1.0, 1.0, 1.0),
+            albedo: Color::new(1.0, 1.0, 1.0).into(),
         };
         let sphere3 = Sphere::new(Vec3([0.0, 2.0, 0.0]), 1.0);
         let material3 = Material::Diffuse {
-            albedo: Color::new(1.0, 1.0, 1.0),
+            albedo: Color::new(1.0, 1.0, 1.0).into(),
         };
         let hittable1 = Hittable {
             shape: Shape::Sphere(sphere1),
@@ -488,8 +963,9 @@ mod tests {
         let ray = Ray {
             orig: Vec3([-1.5, -0.5, -0.5]),
             dir: Vec3([1.0, 0.0, 0.0]),
+            spread: 0.0,
         };
-        tree_filter(&tree, &mut subscene, &ray);
+        tree_filter(&tree, &mut subscene, &ray, geometry::DEFAULT_EPSILON);
 
         if let Some((hittable, Some(param))) =
             subscene.iter().min_by(|x, y| cmp_intersection(x.1, y.1))
@@ -501,14 +977,423 @@ mod tests {
         assert!(!subscene.is_empty(), "subscene should contain hittable1");
     }
 
+    #[test]
+    fn packet_filter_test() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut boxes: Vec<BoundingBox> = scenegen::gen_hittables_from(&mut rng)
+            .into_iter()
+            .map(|hittable| hittable.make_covering())
+            .collect();
+        let tree = make_coveringtree(&mut boxes);
+
+        // a 2x2 block of rays through neighbouring pixels of the random scene
+        let cam = scenegen::default_camera();
+        for (i, j) in [(0, 0), (100, 80), (320, 200), (500, 300)] {
+            let rays = [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)]
+                .map(|(i, j)| Ray::new(cam.lookfrom, cam.get_pixel_centre(i, j) - cam.lookfrom));
+            let mut packet: [Vec<(&Hittable, Option<f64>)>; 4] = Default::default();
+            tree_filter_packet(&tree, &mut packet, &rays, geometry::DEFAULT_EPSILON);
+
+            for (ray, from_packet) in rays.iter().zip(&packet) {
+                let mut scalar = Vec::new();
+                tree_filter(&tree, &mut scalar, ray, geometry::DEFAULT_EPSILON);
+                let as_keys =
+                    |subscene: &[(&Hittable, Option<f64>)]| -> Vec<(*const Hittable, Option<f64>)> {
+                        subscene
+                            .iter()
+                            .map(|(hittable, param)| (*hittable as *const Hittable, *param))
+                            .collect()
+                    };
+                assert_eq!(as_keys(from_packet), as_keys(&scalar));
+            }
+        }
+    }
+
+    #[test]
+    fn max_depth_test() {
+        // boxes that all coincide can't be separated by any split
+        let mut boxes: Vec<BoundingBox> = (0..100)
+            .map(|_| {
+                Hittable {
+                    shape: Shape::Sphere(Sphere::new(Vec3([1.0, 2.0, 3.0]), 0.5)),
+                    material: Material::Diffuse {
+                        albedo: Color::new(0.5, 0.5, 0.5).into(),
+                    },
+                }
+                .make_covering()
+            })
+            .collect();
+        let tree = make_coveringtree_to_depth(&mut boxes, 3);
+        assert_eq!(tree.depth(), 3);
+        assert_eq!(tree.node_count(), 7);
+
+        let ray = Ray::new(Vec3([1.0, 2.0, -5.0]), Vec3([0.0, 0.0, 1.0]));
+        let mut subscene = Vec::new();
+        tree_filter(&tree, &mut subscene, &ray, geometry::DEFAULT_EPSILON);
+        assert_eq!(subscene.len(), 100);
+        assert!(subscene.iter().all(|(_, param)| *param == Some(7.5)));
+        assert_eq!(
+            tree_nearest(&tree, &ray, geometry::DEFAULT_EPSILON)
+                .unwrap()
+                .1,
+            7.5
+        );
+
+        // a depth of one leaves a single leaf of everything
+        let mut boxes: Vec<BoundingBox> = (0..3)
+            .map(|i| {
+                Hittable {
+                    shape: Shape::Sphere(Sphere::new(Vec3([f64::from(i), 0.0, 0.0]), 0.25)),
+                    material: Material::Diffuse {
+                        albedo: Color::new(0.5, 0.5, 0.5).into(),
+                    },
+                }
+                .make_covering()
+            })
+            .collect();
+        let leaf = make_coveringtree_to_depth(&mut boxes, 1);
+        assert_eq!((leaf.depth(), leaf.bundle.len()), (1, 3));
+    }
+
+    #[test]
+    fn tree_nearest_test() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(494);
+        let mut boxes: Vec<BoundingBox> = scenegen::gen_hittables_from(&mut rng)
+            .into_iter()
+            .map(|hittable| hittable.make_covering())
+            .collect();
+        let tree = make_coveringtree(&mut boxes);
+
+        // camera rays and bounces from all over the scene in every direction
+        let cam = &scenegen::default_camera();
+        let camera_rays = (0..cam.horiz_res).step_by(16).flat_map(|i| {
+            (0..cam.vert_res)
+                .step_by(16)
+                .map(move |j| Ray::new(cam.lookfrom, cam.get_pixel_centre(i, j) - cam.lookfrom))
+        });
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let bounces: Vec<Ray> = (0..2000)
+            .map(|_| {
+                let origin = Vec3([
+                    rng.gen_range(-11.0..11.0),
+                    rng.gen_range(0.0..2.0),
+                    rng.gen_range(-11.0..11.0),
+                ]);
+                let dir = Vec3([
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                ]);
+                Ray::new(origin, dir)
+            })
+            .collect();
+
+        let mut hits = 0;
+        for ray in camera_rays.chain(bounces) {
+            let mut subscene = Vec::new();
+            tree_filter(&tree, &mut subscene, &ray, geometry::DEFAULT_EPSILON);
+            let expected = crate::nearest_of(subscene.into_iter());
+            let nearest = tree_nearest(&tree, &ray, geometry::DEFAULT_EPSILON);
+            assert_eq!(
+                nearest.map(|(hittable, param)| (hittable as *const Hittable, param)),
+                expected.map(|(hittable, param)| (hittable as *const Hittable, param))
+            );
+            hits += usize::from(nearest.is_some());
+        }
+        // enough of them hit something for the pruning to have been tried
+        assert!(hits > 1000);
+    }
+
+    #[test]
+    fn bundled_leaf_test() {
+        // overlapping spheres gain nothing from being split apart
+        let centres = [
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.1, 0.0, 0.0]),
+            Vec3([0.0, 0.1, 0.0]),
+            Vec3([0.0, 0.0, 0.1]),
+        ];
+        // nor do they far from the origin the cover stretches to
+        let far_off = Vec3([20.0, 30.0, 40.0]);
+        let mut far_boxes: Vec<BoundingBox> = centres
+            .into_iter()
+            .map(|centre| {
+                Hittable {
+                    shape: Shape::Sphere(Sphere::new(centre + far_off, 1.0)),
+                    material: Material::Diffuse {
+                        albedo: Color::new(1.0, 1.0, 1.0).into(),
+                    },
+                }
+                .make_covering()
+            })
+            .collect();
+        let far_tree = make_coveringtree(&mut far_boxes);
+        assert_eq!(far_tree.depth(), 1);
+        assert_eq!(far_tree.bundle.len(), 4);
+
+        let mut boxes: Vec<BoundingBox> = centres
+            .into_iter()
+            .map(|centre| {
+                Hittable {
+                    shape: Shape::Sphere(Sphere::new(centre, 1.0)),
+                    material: Material::Diffuse {
+                        albedo: Color::new(1.0, 1.0, 1.0).into(),
+                    },
+                }
+                .make_covering()
+            })
+            .collect();
+        let tree = make_coveringtree(&mut boxes);
+        assert_eq!(tree.depth(), 1);
+        assert_eq!(tree.bundle.len(), 4);
+        assert!(tree.cover.boxed.is_none());
+
+        let mut subscene = Vec::<(&Hittable, Option<f64>)>::new();
+        let ray = Ray::new(Vec3([0.0, 0.0, -5.0]), Vec3([0.0, 0.0, 1.0]));
+        tree_filter(&tree, &mut subscene, &ray, geometry::DEFAULT_EPSILON);
+        assert_eq!(subscene.len(), 4);
+        assert!(subscene.iter().all(|(_, param)| param.is_some()));
+    }
+
+    #[test]
+    fn occlusion_test() {
+        let sphere_at = |centre: Vec3| Hittable {
+            shape: Shape::Sphere(Sphere::new(centre, 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(1.0, 1.0, 1.0).into(),
+            },
+        };
+        let scene = CoveredScene::new(vec![
+            sphere_at(Vec3([0.0, 0.0, 0.0])),
+            sphere_at(Vec3([5.0, 0.0, 0.0])),
+        ]);
+        let eps = geometry::DEFAULT_EPSILON;
+
+        let blocked = (Vec3([-3.0, 0.0, 0.0]), Vec3([3.0, 0.0, 0.0]));
+        assert!(scene.is_occluded(blocked.0, blocked.1, eps, None));
+        let clear = (Vec3([-3.0, 2.0, 0.0]), Vec3([8.0, 2.0, 0.0]));
+        assert!(!scene.is_occluded(clear.0, clear.1, eps, None));
+        // the segment stops short of the sphere it points at
+        let short = (Vec3([2.0, 0.0, 0.0]), Vec3([3.5, 0.0, 0.0]));
+        assert!(!scene.is_occluded(short.0, short.1, eps, None));
+        // ending on a surface isn't blocked by that surface
+        let to_surface = (Vec3([2.0, 0.0, 0.0]), Vec3([4.0, 0.0, 0.0]));
+        assert!(!scene.is_occluded(to_surface.0, to_surface.1, eps, None));
+
+        let ray = Ray::new(blocked.0, blocked.1 - blocked.0);
+        assert!(sphere_at(Vec3([0.0, 0.0, 0.0]))
+            .shape
+            .intersect_any(&ray, eps, 6.0));
+        assert!(!sphere_at(Vec3([0.0, 0.0, 0.0]))
+            .shape
+            .intersect_any(&ray, eps, 1.5));
+    }
+
+    #[test]
+    fn test_negative_radius_covering() {
+        let hittable = Hittable {
+            shape: Shape::Sphere(Sphere::new(Vec3([1.0, 2.0, 3.0]), -0.5)),
+            material: Material::Diffuse {
+                albedo: Color::new(1.0, 1.0, 1.0).into(),
+            },
+        };
+        let bbox = hittable.make_covering();
+
+        assert_eq!(bbox.dims[0], interval!(0.5, 1.5));
+        assert_eq!(bbox.dims[1], interval!(1.5, 2.5));
+        assert_eq!(bbox.dims[2], interval!(2.5, 3.5));
+    }
+
+    #[test]
+    fn test_disc_covering() {
+        use crate::geometry::Disc;
+        let disc = || Hittable {
+            shape: Shape::Disc(Disc::new(Vec3([0.0, 1.0, 0.0]), Vec3([0.0, 1.0, 0.0]), 2.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(1.0, 1.0, 1.0).into(),
+            },
+        };
+        let bbox = disc().make_covering();
+
+        assert_eq!(bbox.dims[0], interval!(-2.0, 2.0));
+        assert!(bbox.dims[1].start < 1.0 && bbox.dims[1].end > 1.0);
+        let ray = Ray::new(Vec3([0.5, 3.0, 0.5]), Vec3([0.0, -1.0, 0.0]));
+        assert!(bbox.check_intersection(&ray));
+
+        // the flat axis thickens in proportion to the scale of the scene
+        let large = disc().try_make_covering_to_scale(1000.0).unwrap();
+        assert!((large.dims[1].size() / bbox.dims[1].size() - 1000.0).abs() < 1.0e-6);
+        let covered = CoveredScene::with_scale(vec![disc()], 1000.0);
+        assert_eq!(covered.tree.unwrap().cover.dims[1], large.dims[1]);
+    }
+
+    #[test]
+    fn test_uncovered_shapes() {
+        let sphere = Hittable {
+            shape: Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(1.0, 1.0, 1.0).into(),
+            },
+        };
+        let unboxable = Hittable {
+            shape: Shape::BoundVolume(geometry::BoundBox::surround(&Shape::Sphere(Sphere::new(
+                Vec3([5.0, 0.0, 0.0]),
+                1.0,
+            )))),
+            material: Material::Diffuse {
+                albedo: Color::new(1.0, 1.0, 1.0).into(),
+            },
+        };
+        let scene = CoveredScene::new(vec![sphere, unboxable]);
+        assert!(scene.tree.is_some());
+        assert_eq!(scene.uncovered.len(), 1);
+
+        for (target, expected_param) in [(0.0, 4.0), (5.0, 4.0)] {
+            let ray = Ray::new(Vec3([target, 0.0, -5.0]), Vec3([0.0, 0.0, 1.0]));
+            let mut subscene = Vec::<(&Hittable, Option<f64>)>::new();
+            scene.filter(&mut subscene, &ray, geometry::DEFAULT_EPSILON);
+            let nearest = subscene.iter().min_by(|x, y| cmp_intersection(x.1, y.1));
+            assert_eq!(nearest.unwrap().1, Some(expected_param));
+        }
+    }
+
+    #[test]
+    fn hittables_with_sources_test() {
+        let sphere = |x: f64| Hittable {
+            shape: Shape::Sphere(Sphere::new(Vec3([x, 0.0, 0.0]), 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(1.0, 1.0, 1.0).into(),
+            },
+        };
+        let unboxable = Hittable {
+            shape: Shape::BoundVolume(geometry::BoundBox::surround(&Shape::Sphere(Sphere::new(
+                Vec3([2.0, 0.0, 0.0]),
+                1.0,
+            )))),
+            material: Material::Diffuse {
+                albedo: Color::new(1.0, 1.0, 1.0).into(),
+            },
+        };
+        // spread out in reverse so the tree has to reorder them
+        let mut hittables: Vec<Hittable> = (0..9).map(|i| sphere(-3.0 * i as f64)).collect();
+        hittables.insert(2, unboxable);
+        let scene = CoveredScene::new(hittables);
+
+        let mut sources: Vec<usize> = Vec::new();
+        for (source, hittable) in scene.hittables_with_sources() {
+            let expected = match source {
+                2 => 2.0,
+                source if source < 2 => -3.0 * source as f64,
+                source => -3.0 * (source - 1) as f64,
+            };
+            match &hittable.shape {
+                Shape::Sphere(sphere) => assert_eq!(sphere.centre[0], expected),
+                Shape::BoundVolume(_) => assert_eq!(source, 2),
+                _ => panic!("only spheres were put in the scene"),
+            }
+            sources.push(source);
+        }
+        assert_ne!(sources, (0..10).collect::<Vec<usize>>());
+        sources.sort_unstable();
+        assert_eq!(sources, (0..10).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_uncovered_plane_instance() {
+        use crate::geometry::{Instance, Plane, Transform};
+        use std::sync::Arc;
+        let floor = Arc::new(Shape::Plane(Plane::new(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 1.0, 0.0]),
+        )));
+        let transform = Transform {
+            translate: Vec3([0.0, -1.0, 0.0]),
+            ..Default::default()
+        };
+        let instance = Hittable {
+            shape: Shape::Instance(Instance::new(floor, 0, transform)),
+            material: Material::Diffuse {
+                albedo: Color::new(1.0, 1.0, 1.0).into(),
+            },
+        };
+        assert!(!instance.shape.is_bounded());
+        // an instance of a plane has no box either, so it goes uncovered
+        let scene = CoveredScene::new(vec![instance]);
+        assert!(scene.tree.is_none());
+        assert_eq!(scene.uncovered.len(), 1);
+
+        let ray = Ray::new(Vec3([3.0, 2.0, 0.0]), Vec3([0.0, -1.0, 0.0]));
+        let (_, param) = scene.nearest_hit(&ray, geometry::DEFAULT_EPSILON).unwrap();
+        assert_eq!(param, 3.0);
+    }
+
+    fn leaves(tree: &CoveringTree) -> Vec<&CoveringTree> {
+        match (&tree.left, &tree.right) {
+            (None, None) => vec![tree],
+            (left, right) => [left, right]
+                .into_iter()
+                .flatten()
+                .flat_map(|child| leaves(child))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_refit_translated() {
+        let centres = [
+            Vec3([1.0, 1.0, 1.0]),
+            Vec3([3.0, 1.0, 1.0]),
+            Vec3([1.0, 4.0, 1.0]),
+            Vec3([2.0, 2.0, 3.0]),
+        ];
+        let sphere_at = |centre: Vec3| Hittable {
+            shape: Shape::Sphere(Sphere::new(centre, 0.5)),
+            material: Material::Diffuse {
+                albedo: Color::new(1.0, 1.0, 1.0).into(),
+            },
+        };
+        let mut boxes: Vec<BoundingBox> = centres
+            .into_iter()
+            .map(|centre| sphere_at(centre).make_covering())
+            .collect();
+        let mut tree = make_coveringtree(&mut boxes);
+        let depth = tree.depth();
+
+        let offset = Vec3([10.0, 0.0, -5.0]);
+        let mut boxed = Vec::new();
+        tree.collect_boxed(&mut boxed);
+        let moved: Vec<BoundingBox> = boxed
+            .into_iter()
+            .map(|hittable| match &hittable.shape {
+                Shape::Sphere(sphere) => sphere_at(sphere.centre + offset).make_covering(),
+                _ => unreachable!(),
+            })
+            .collect();
+        tree.refit(&moved);
+
+        assert_eq!(tree.depth(), depth);
+        assert_eq!(tree.cover.dims[0], interval!(10.5, 13.5));
+        assert_eq!(tree.cover.dims[1], interval!(0.5, 4.5));
+        assert_eq!(tree.cover.dims[2], interval!(-4.5, -1.5));
+        let mut remaining = &moved[..];
+        for leaf in leaves(&tree) {
+            let (leaf_moved, rest) = remaining.split_at(leaf.bundle.len().max(1));
+            assert!(leaf.cover.dims == tight_dims(leaf_moved));
+            remaining = rest;
+        }
+    }
+
     #[test]
     fn test_debug_scene() {
-        let tree = scenegen::debug_scene();
+        let scene = scenegen::debug_scene();
         let mut subscene = Vec::<(&Hittable, Option<f64>)>::new();
         let outray = Ray {
             orig: Vec3([10.0, 0.0, 0.0]),
             dir: Vec3([1.0, 0.0, 0.0]),
+            spread: 0.0,
         };
-        tree_filter(&tree, &mut subscene, &outray);
+        scene.filter(&mut subscene, &outray, geometry::DEFAULT_EPSILON);
     }
 }
@@ -1,5 +1,8 @@
+use crate::sampler;
 use crate::vector::Vec3;
-use rand::{thread_rng, Rng};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
 
 pub struct Camera {
     pub lookat: Vec3,   // focal plane location
@@ -13,6 +16,51 @@ pub struct Camera {
     pub horiz_res: u32, // number of horizontal pixels
     pub vert_res: u32,  // number of vertical pixels
     pub aspect_ratio: f64,
+    pub aperture_shape: ApertureShape,
+}
+
+// the outline of the lens opening, which out of focus highlights take on
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ApertureShape {
+    #[default]
+    Circle,
+    // a regular polygon with its corners on the unit circle, one of them
+    // straight up the vertical arm, as the blades of a lens make
+    Polygon {
+        sides: u32,
+    },
+}
+
+impl ApertureShape {
+    // a uniformly distributed point of the shape scaled to the unit disc
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> [f64; 2] {
+        match self {
+            ApertureShape::Circle => random_in_disc(rng),
+            ApertureShape::Polygon { .. } => self.sample_square(rng.gen()),
+        }
+    }
+
+    // maps a point of the unit square onto the shape, keeping evenly spread
+    // points evenly spread, the polygon taking one triangle from its centre
+    // to an edge for each equal slice of the first coordinate
+    pub fn sample_square(&self, square: [f64; 2]) -> [f64; 2] {
+        match *self {
+            ApertureShape::Circle => sampler::square_to_disc(square),
+            ApertureShape::Polygon { sides } => {
+                let sides = sides.max(3);
+                let scaled = square[0] * f64::from(sides);
+                let edge = scaled.floor().min(f64::from(sides - 1));
+                // uniform in the triangle, sqrt spreading it out towards the edge
+                let (along, out) = (scaled - edge, square[1].sqrt());
+                let corner = |k: f64| {
+                    let angle = 0.5 * PI + 2.0 * PI * k / f64::from(sides);
+                    [angle.cos(), angle.sin()]
+                };
+                let (start, end) = (corner(edge), corner(edge + 1.0));
+                [0, 1].map(|i| out * ((1.0 - along) * start[i] + along * end[i]))
+            }
+        }
+    }
 }
 
 impl Camera {
@@ -25,6 +73,27 @@ impl Camera {
         vert_res: u32,
     ) -> Camera {
         let lookup = Vec3([0.0, 1.0, 0.0]); // fiducial value, normalize first
+        Camera::build_with_up(
+            lookat,
+            lookfrom,
+            lookup,
+            inv_focal_length,
+            aperture,
+            horiz_res,
+            vert_res,
+        )
+    }
+
+    // as build, with lookup giving the roll of the camera about its view
+    pub fn build_with_up(
+        lookat: Vec3,
+        lookfrom: Vec3,
+        lookup: Vec3,
+        inv_focal_length: f64,
+        aperture: f64,
+        horiz_res: u32,
+        vert_res: u32,
+    ) -> Camera {
         let pointing_direction: Vec3 = lookat - lookfrom;
         let focal_distance = pointing_direction.norm();
         // upside down because flipped through aperture
@@ -43,47 +112,65 @@ impl Camera {
             horiz_res,
             vert_res,
             aspect_ratio,
+            aperture_shape: ApertureShape::default(),
         }
     }
 
-    pub fn get_focus_loc(&self) -> Vec3 {
-        let rng_scalars = random_in_disc();
+    pub fn with_aperture_shape(self, aperture_shape: ApertureShape) -> Camera {
+        Camera {
+            aperture_shape,
+            ..self
+        }
+    }
 
-        let nudged_lookfrom: Vec3 = self.lookfrom
-            + self.aperture * rng_scalars[0] * self.horiz_arm.normalize()
-            + self.aperture * rng_scalars[1] * self.vert_arm.normalize();
+    pub fn get_focus_loc(&self) -> Vec3 {
+        self.get_lens_loc(self.aperture_shape.sample(&mut sampler::rng()))
+    }
 
-        return nudged_lookfrom;
+    // the location on the lens at disc_point in the unit disc
+    pub fn get_lens_loc(&self, disc_point: [f64; 2]) -> Vec3 {
+        self.lookfrom
+            + self.aperture * disc_point[0] * self.horiz_arm.normalize()
+            + self.aperture * disc_point[1] * self.vert_arm.normalize()
     }
 
     pub fn get_sample_loc(&self, i: u32, j: u32) -> Vec3 {
-        let rng_scalars: [f64; 2] = thread_rng().gen();
+        self.get_pixel_loc(i, j, sampler::rng().gen())
+    }
+
+    pub fn get_pixel_centre(&self, i: u32, j: u32) -> Vec3 {
+        self.get_pixel_loc(i, j, [0.5, 0.5])
+    }
+
+    // the angle subtended at the lens by one pixel, so the footprint of a
+    // primary ray grows linearly with the distance it has travelled
+    pub fn pixel_spread(&self) -> f64 {
+        self.inv_focal_length / f64::from(self.horiz_res)
+    }
 
+    // the location in the focal plane offset by rng_scalars within pixel (i, j)
+    pub fn get_pixel_loc(&self, i: u32, j: u32, rng_scalars: [f64; 2]) -> Vec3 {
         let horiz_increm = 1.0 / f64::from(self.horiz_res);
         let vert_increm = 1.0 / f64::from(self.vert_res);
-        let horiz_nudge: Vec3 = (rng_scalars[0] * horiz_increm) * self.horiz_arm;
-        let vert_nudge: Vec3 = (rng_scalars[1] * vert_increm) * self.vert_arm;
 
         let horiz_span = self.inv_focal_length * self.focal_distance * self.horiz_arm;
         let vert_span = self.inv_focal_length * self.focal_distance * self.vert_arm;
 
-        let grid_h_offset = -0.5 + f64::from(i) * horiz_increm;
-        let grid_v_offset = 0.5 - f64::from(j) * vert_increm;
+        // rows count down from the top edge so the offset into the pixel is downward
+        let grid_h_offset = -0.5 + (f64::from(i) + rng_scalars[0]) * horiz_increm;
+        let grid_v_offset = 0.5 - (f64::from(j) + rng_scalars[1]) * vert_increm;
 
-        self.lookat
-            + (grid_h_offset * horiz_span)
-            + (grid_v_offset * vert_span)
-            + horiz_nudge
-            + vert_nudge
+        self.lookat + (grid_h_offset * horiz_span) + (grid_v_offset * vert_span)
     }
 }
 
-fn random_in_disc() -> [f64; 2] {
-    let rng_scalars: [f64; 2] = thread_rng().gen();
+// uniformly samples the unit disc by rejection from the enclosing square
+pub fn random_in_disc<R: Rng + ?Sized>(rng: &mut R) -> [f64; 2] {
+    let rng_scalars: [f64; 2] = [rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)];
 
     let radius2: f64 = rng_scalars[0] * rng_scalars[0] + rng_scalars[1] * rng_scalars[1]; // rejection condition
     if radius2 > 1.0 {
-        return random_in_disc();
+        return random_in_disc(rng);
     };
     return rng_scalars;
 }
@@ -92,9 +179,57 @@ fn random_in_disc() -> [f64; 2] {
 mod tests {
     use super::*;
 
+    #[test]
+    fn camera_arms_test() {
+        let lookat = Vec3([1.0, 0.5, -2.0]);
+        let lookfrom = Vec3([-1.0, 2.0, 3.0]);
+        let cam = Camera::build(lookat, lookfrom, 0.5, 0.1, 16, 9);
+        let pointing_direction = lookat - lookfrom;
+
+        for arm in [cam.horiz_arm, cam.vert_arm] {
+            assert!(arm.0.iter().all(|coord| coord.is_finite()));
+            assert!((arm.norm() - 1.0).abs() < 1.0e-12);
+            assert!(arm.dotprod(&pointing_direction).abs() < 1.0e-12);
+        }
+        assert!(cam.horiz_arm.dotprod(&cam.vert_arm).abs() < 1.0e-12);
+        assert_eq!(cam.focal_distance, pointing_direction.norm());
+        assert_eq!(cam.aspect_ratio, 16.0 / 9.0);
+    }
+
+    #[test]
+    fn aperture_shape_test() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+        // the circle draws exactly the points random_in_disc always has
+        let (mut rng, mut circle_rng) = (StdRng::seed_from_u64(3), StdRng::seed_from_u64(3));
+        for _ in 0..16 {
+            assert_eq!(
+                ApertureShape::Circle.sample(&mut circle_rng),
+                random_in_disc(&mut rng)
+            );
+        }
+
+        let hexagon = ApertureShape::Polygon { sides: 6 };
+        let apothem = (PI / 6.0).cos();
+        let mut beyond_apothem = 0;
+        for _ in 0..1000 {
+            let [x, y] = hexagon.sample(&mut rng);
+            let radius = (x * x + y * y).sqrt();
+            assert!(radius <= 1.0 + 1.0e-12);
+            // inside the edge facing the point as well as the circle
+            let angle = (y.atan2(x) - 0.5 * PI).rem_euclid(PI / 3.0) - PI / 6.0;
+            assert!(radius * angle.cos() <= apothem + 1.0e-12);
+            if radius > apothem {
+                beyond_apothem += 1;
+            }
+        }
+        // the corners are reached, unlike a disc of the inscribed radius
+        assert!(beyond_apothem > 0);
+    }
+
     #[test]
     fn random_in_disc_test() {
-        let point = random_in_disc();
+        let point = random_in_disc(&mut rand::thread_rng());
         assert!(
             point[0] * point[0] + point[1] * point[1] <= 1.0,
             "picked point out of disc"
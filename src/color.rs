@@ -2,17 +2,136 @@ use std::ops;
 use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "ColorRepr")]
 pub struct Color {
     pub r: f64,
     pub g: f64,
     pub b: f64,
 }
 
+// a Color can be written in a scene file as {"r", "g", "b"}, [r, g, b] or "#rrggbb"
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorRepr {
+    Hex(String),
+    Rgb { r: f64, g: f64, b: f64 },
+    Array([f64; 3]),
+}
+
+impl TryFrom<ColorRepr> for Color {
+    type Error = String;
+
+    fn try_from(repr: ColorRepr) -> Result<Color, String> {
+        match repr {
+            ColorRepr::Hex(hex) => Color::from_hex(&hex),
+            ColorRepr::Rgb { r, g, b } => Ok(Color::new(r, g, b)),
+            ColorRepr::Array([r, g, b]) => Ok(Color::new(r, g, b)),
+        }
+    }
+}
+
 impl Color {
     pub fn new(r: f64, g: f64, b: f64) -> Color {
         Color {r, g, b}
         
     }
+
+    // the Rec. 709 weighting of the linear channels
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.r.is_finite() && self.g.is_finite() && self.b.is_finite()
+    }
+
+    // each channel into the displayable [0, 1], with the small negatives a
+    // wide filter can leave and any nan going to 0 rather than through the
+    // gamma curve
+    pub fn clamp_gamut(&self) -> Color {
+        let clamp = |value: f64| if value.is_nan() {0.0} else {value.clamp(0.0, 1.0)};
+        Color::new(clamp(self.r), clamp(self.g), clamp(self.b))
+    }
+
+    // parses an sRGB "#rrggbb" into linear channels
+    pub fn from_hex(hex: &str) -> Result<Color, String> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if digits.len() != 6 || !digits.is_ascii() {
+            return Err(format!("expected a color of the form #rrggbb, got {}", hex));
+        }
+        let channel = |idx: usize| {
+            u8::from_str_radix(&digits[idx..idx + 2], 16)
+                .map(|value| srgb_to_linear(value as f64 / 255.0))
+                .map_err(|err| format!("invalid hex color {}: {}", hex, err))
+        };
+        Ok(Color::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    pub fn to_hex(&self) -> String {
+        let channel = |value: f64| (255.0 * linear_to_srgb(value.clamp(0.0, 1.0))).round() as u8;
+        format!("#{:02x}{:02x}{:02x}", channel(self.r), channel(self.g), channel(self.b))
+    }
+}
+
+// the sRGB transfer function, from encoded [0, 1] values to linear
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// the encoding of linear channels into the [0, 1] values written out
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum Gamma {
+    #[default]
+    Srgb,
+    // the earlier gamma 2 approximation
+    Sqrt,
+    // no encoding at all so output values can be checked against linear radiance
+    Linear,
+}
+
+impl Gamma {
+    pub fn encode(&self, value: f64) -> f64 {
+        match self {
+            Gamma::Srgb => linear_to_srgb(value),
+            Gamma::Sqrt => value.sqrt(),
+            Gamma::Linear => value.clamp(0.0, 1.0),
+        }
+    }
+}
+
+// how linear colors brighter than white are brought into gamut before they
+// are encoded
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum Tonemap {
+    // leaves the color alone for the encoding to clip
+    #[default]
+    Clamp,
+    // c / (1 + c) on each channel, compressing highlights instead of clipping
+    Reinhard,
+}
+
+impl Tonemap {
+    pub fn apply(&self, col: Color) -> Color {
+        match self {
+            Tonemap::Clamp => col,
+            Tonemap::Reinhard => {
+                let compress = |value: f64| value / (1.0 + value.max(0.0));
+                Color::new(compress(col.r), compress(col.g), compress(col.b))
+            }
+        }
+    }
 }
 
 impl ops::Add<Color> for Color {
@@ -68,4 +187,42 @@ impl ops::AddAssign for Color {
         }
         
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip_test() {
+        let white = Color::from_hex("#ffffff").unwrap();
+        assert_eq!((white.r, white.g, white.b), (1.0, 1.0, 1.0));
+        assert_eq!(white.to_hex(), "#ffffff");
+
+        let black = Color::from_hex("#000000").unwrap();
+        assert_eq!((black.r, black.g, black.b), (0.0, 0.0, 0.0));
+        assert_eq!(black.to_hex(), "#000000");
+
+        assert_eq!(Color::from_hex("#3377cc").unwrap().to_hex(), "#3377cc");
+        assert!(Color::from_hex("#12345").is_err());
+        assert!(Color::from_hex("#gg0000").is_err());
+    }
+
+    #[test]
+    fn srgb_to_linear_test() {
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1.0e-3);
+        assert!((linear_to_srgb(srgb_to_linear(0.5)) - 0.5).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn hex_deserialize_test() {
+        let hex: Color = serde_json::from_str(r##""#ff0000""##).unwrap();
+        assert_eq!((hex.r, hex.g, hex.b), (1.0, 0.0, 0.0));
+
+        let rgb: Color = serde_json::from_str(r#"{"r": 0.5, "g": 0.25, "b": 0.0}"#).unwrap();
+        assert_eq!((rgb.r, rgb.g, rgb.b), (0.5, 0.25, 0.0));
+
+        let array: Color = serde_json::from_str("[0.5, 0.25, 0.0]").unwrap();
+        assert_eq!((array.r, array.g, array.b), (0.5, 0.25, 0.0));
+    }
+}
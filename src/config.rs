@@ -1,38 +1,524 @@
 use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use crate::vector::Vec3;
-use crate::camera::Camera;
+use crate::camera::{ApertureShape, Camera};
+use crate::film;
+use crate::geometry::Shape;
+use crate::settings::{self, RenderSettings};
 use crate::Hittable;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub camera: SetupCamera,
+    #[serde(default)]
     pub hittables: Vec<Hittable>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    // shapes stored once for the Instance shapes to refer to by index
+    #[serde(default)]
+    pub prototypes: Vec<Shape>,
+    #[serde(default)]
+    pub settings: RenderSettings,
+    // roughly how many units across the objects of the scene are, given it
+    // sets the epsilon of the settings to suit unless they give one, and
+    // the thickness of the flat boxes of the covering tree
+    #[serde(default)]
+    pub scene_scale: Option<f64>,
+    // the positions among the hittables, groups flattened after them, of the
+    // only emitters sampled as lights, rather than every one that can be
+    #[serde(default)]
+    pub lights: Option<Vec<usize>>,
 }
 
+// a named set of hittables, flattened into the scene when loaded
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SetupCamera {
-    lookat: Vec3,
-    lookfrom: Vec3,
-    inv_focal_length: f64,
-    aperture: f64,
-    horiz_res: u32,
-    vert_res: u32,
+pub struct Group {
+    pub name: String,
+    pub hittables: Vec<Hittable>,
+}
+
+impl Config {
+    // parses a scene file, moving the hittables of every group into hittables,
+    // pointing every instance at its prototype and scaling the epsilon
+    pub fn from_slice(contents: &[u8]) -> serde_json::Result<Config> {
+        Config::resolve(serde_json::from_slice(contents)?)
+    }
+
+    // as from_slice for the scene file at path, after appending to its
+    // hittables and prototypes those of every file in its include list,
+    // relative to it, the groups of those flattened into their hittables
+    pub fn from_path<P: AsRef<Path>>(path: P) -> serde_json::Result<Config> {
+        Config::resolve(resolve_includes(path.as_ref(), &mut Vec::new())?)
+    }
+
+    fn resolve(value: Value) -> serde_json::Result<Config> {
+        let explicit_epsilon = value.get("settings").and_then(|settings| settings.get("epsilon"))
+            .is_some();
+        let mut config: Config = serde_json::from_value(value)?;
+        if let Some(scene_scale) = config.scene_scale {
+            if !(scene_scale > 0.0 && scene_scale.is_finite()) {
+                return Err(serde::de::Error::custom(format!(
+                    "scene_scale must be positive, not {}", scene_scale)));
+            }
+            // an epsilon the settings give is kept as tuned for the scene
+            if !explicit_epsilon {
+                config.settings.epsilon = settings::epsilon_for_scale(scene_scale);
+            }
+            config.settings.scene_scale = scene_scale;
+        }
+        let filter_radius = config.settings.filter_radius;
+        if !(filter_radius >= film::MIN_FILTER_RADIUS && filter_radius.is_finite()) {
+            // narrower, a sample could land on no pixel at all
+            return Err(serde::de::Error::custom(format!(
+                "filter_radius must be at least {}, not {}", film::MIN_FILTER_RADIUS, filter_radius)));
+        }
+        if config.settings.diffuse_bounce_cap == Some(0) {
+            // the first diffuse surface would have nothing left to light it
+            return Err(serde::de::Error::custom("diffuse_bounce_cap must be at least 1"));
+        }
+        for group in config.groups.drain(..) {
+            debug!("Group {} has {} hittables", group.name, group.hittables.len());
+            config.hittables.extend(group.hittables);
+        }
+        let shapes = config.hittables.iter().map(|hittable| &hittable.shape).chain(&config.prototypes);
+        for shape in shapes {
+            if let Shape::Disc(disc) = shape {
+                disc.check_ring().map_err(serde::de::Error::custom)?;
+            }
+        }
+        let prototypes: Vec<Arc<Shape>> = config.prototypes.drain(..).map(Arc::new).collect();
+        if prototypes.iter().any(|prototype| matches!(**prototype, Shape::Instance(_))) {
+            return Err(serde::de::Error::custom("a prototype cannot itself be an instance"));
+        }
+        for hittable in config.hittables.iter_mut() {
+            if let Shape::Instance(instance) = &mut hittable.shape {
+                let scale = instance.transform.scale;
+                if !(scale > 0.0 && scale.is_finite()) {
+                    // the inverse would flip or lose the rays taken into its frame
+                    return Err(serde::de::Error::custom(format!(
+                        "the scale of an instance must be positive, not {}", scale)));
+                }
+                let Some(prototype) = prototypes.get(instance.ref_id) else {
+                    return Err(serde::de::Error::custom(format!(
+                        "instance of prototype {} but there are only {} prototypes",
+                        instance.ref_id, prototypes.len())));
+                };
+                instance.prototype = Some(Arc::clone(prototype));
+            }
+        }
+        for &light in config.lights.iter().flatten() {
+            match config.hittables.get(light) {
+                Some(hittable) if hittable.material.is_emitter() && hittable.shape.area().is_some() => {}
+                Some(_) => return Err(serde::de::Error::custom(format!(
+                    "light {} is not an emitter with an area to sample", light))),
+                None => return Err(serde::de::Error::custom(format!(
+                    "light {} but there are only {} hittables", light, config.hittables.len()))),
+            }
+        }
+        config.settings.lights = config.lights.clone();
+        Ok(config)
+    }
+}
+
+// the scene file at path as json with the hittables and prototypes of its
+// included files, resolved in turn, appended to its own, erroring on a file
+// that includes itself through including holding the files that led to this one
+fn resolve_includes(path: &Path, including: &mut Vec<PathBuf>) -> serde_json::Result<Value> {
+    let unreadable = |err: std::io::Error| serde::de::Error::custom(format!(
+        "unable to read {}: {}", path.display(), err));
+    let canonical = path.canonicalize().map_err(unreadable)?;
+    if including.contains(&canonical) {
+        return Err(serde::de::Error::custom(format!(
+            "{} ends up including itself", path.display())));
+    }
+    let mut value: Value = serde_json::from_slice(&fs::read(path).map_err(unreadable)?)?;
+    let Some(includes) = value.as_object_mut().and_then(|object| object.remove("include")) else {
+        return Ok(value);
+    };
+    let includes: Vec<PathBuf> = serde_json::from_value(includes)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    including.push(canonical);
+    let mut included = Vec::new();
+    let mut prototypes = Vec::new();
+    let own_prototypes = value.get("prototypes").and_then(Value::as_array).map_or(0, Vec::len);
+    for include in includes {
+        let include = dir.join(&include);
+        let mut child = resolve_includes(&include, including)?;
+        if child.get("lights").is_some() {
+            // the positions would be among its own hittables alone
+            return Err(serde::de::Error::custom(format!(
+                "{} is included so cannot pick out lights", include.display())));
+        }
+        let mut hittables = take_list(&mut child, "hittables", &include)?;
+        for mut group in take_list(&mut child, "groups", &include)? {
+            hittables.extend(take_list(&mut group, "hittables", &include)?);
+        }
+        // its instances refer to its prototypes, which come after those before it
+        let offset = own_prototypes + prototypes.len();
+        for hittable in hittables.iter_mut() {
+            if let Some(ref_id) = hittable.pointer_mut("/shape/Instance/ref_id") {
+                if let Some(id) = ref_id.as_u64() {
+                    *ref_id = Value::from(id + offset as u64);
+                }
+            }
+        }
+        included.extend(hittables);
+        prototypes.extend(take_list(&mut child, "prototypes", &include)?);
+    }
+    including.pop();
+    let object = value.as_object_mut().expect("only objects have includes");
+    for (key, list) in [("hittables", included), ("prototypes", prototypes)] {
+        match object.entry(key).or_insert_with(|| Value::Array(Vec::new())) {
+            Value::Array(own) => own.extend(list),
+            _ => return Err(serde::de::Error::custom(format!(
+                "the {} of {} are not a list", key, path.display()))),
+        }
+    }
+    Ok(value)
+}
+
+// the list under key in value, taken out of it, empty if there is none
+fn take_list(value: &mut Value, key: &str, path: &Path) -> serde_json::Result<Vec<Value>> {
+    match value.get_mut(key).map(Value::take) {
+        Some(Value::Array(list)) => Ok(list),
+        Some(_) => Err(serde::de::Error::custom(format!(
+            "the {} of {} are not a list", key, path.display()))),
+        None => Ok(Vec::new()),
+    }
+}
+
+// the camera is either aimed at a point or, given no lookat, oriented by
+// euler angles in degrees: yaw turns it about +y, pitch tilts it upwards
+// and roll turns it about its view direction, all zero looks along -z
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SetupCamera {
+    LookAt {
+        lookat: Vec3,
+        lookfrom: Vec3,
+        inv_focal_length: f64,
+        aperture: f64,
+        horiz_res: u32,
+        vert_res: u32,
+        #[serde(default)]
+        aperture_shape: ApertureShape,
+    },
+    Euler {
+        lookfrom: Vec3,
+        yaw: f64,
+        pitch: f64,
+        #[serde(default)]
+        roll: f64,
+        focal_distance: f64,
+        inv_focal_length: f64,
+        aperture: f64,
+        horiz_res: u32,
+        vert_res: u32,
+        #[serde(default)]
+        aperture_shape: ApertureShape,
+    },
 }
 
 impl SetupCamera {
     pub fn setup(&self) -> Camera {
-        Camera::build(self.lookat, self.lookfrom, self.inv_focal_length, self.aperture,
-        self.horiz_res, self.vert_res)
+        match *self {
+            SetupCamera::LookAt { lookat, lookfrom, inv_focal_length, aperture, horiz_res, vert_res,
+                                  aperture_shape } =>
+                Camera::build(lookat, lookfrom, inv_focal_length, aperture, horiz_res, vert_res)
+                    .with_aperture_shape(aperture_shape),
+            SetupCamera::Euler { lookfrom, yaw, pitch, roll, focal_distance, inv_focal_length, aperture,
+                                 horiz_res, vert_res, aperture_shape } => {
+                let (direction, lookup) = euler_orientation(yaw, pitch, roll);
+                Camera::build_with_up(lookfrom + focal_distance * direction, lookfrom, lookup,
+                    inv_focal_length, aperture, horiz_res, vert_res)
+                    .with_aperture_shape(aperture_shape)
+            }
+        }
     }
 }
 
+// the (view direction, up) unit vectors for angles in degrees
+fn euler_orientation(yaw: f64, pitch: f64, roll: f64) -> (Vec3, Vec3) {
+    let (sin_yaw, cos_yaw) = yaw.to_radians().sin_cos();
+    let (sin_pitch, cos_pitch) = pitch.to_radians().sin_cos();
+    let direction = Vec3([-sin_yaw * cos_pitch, sin_pitch, -cos_yaw * cos_pitch]);
+    let up = Vec3([sin_yaw * sin_pitch, cos_pitch, cos_yaw * sin_pitch]);
+    (direction, up.rotate_axis_angle(direction, roll.to_radians()))
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
     use std::fs;
 
     #[test]
     fn load_config_test() {
         let config_contents = fs::read("./scene.json").expect("unable to read message");
     }
+
+    #[test]
+    fn flatten_groups_test() {
+        let contents = br##"{
+            "camera": {"lookat": [0.0, 0.0, 0.0], "lookfrom": [0.0, 0.0, -5.0],
+                       "inv_focal_length": 1.0, "aperture": 0.0, "horiz_res": 4, "vert_res": 4},
+            "hittables": [
+                {"shape": {"Sphere": {"centre": [0.0, 0.0, 0.0], "radius": 1.0}},
+                 "material": {"Dielectric": {"refractive_index": 1.5}}}
+            ],
+            "groups": [
+                {"name": "floor", "hittables": [
+                    {"shape": {"Sphere": {"centre": [0.0, -100.0, 0.0], "radius": 99.0}},
+                     "material": {"Diffuse": {"albedo": "#808080"}}},
+                    {"shape": {"Disc": {"centre": [0.0, -1.0, 0.0], "normal": [0.0, 1.0, 0.0], "radius": 2.0}},
+                     "material": {"Diffuse": {"albedo": "#808080"}}}
+                ]}
+            ]
+        }"##;
+
+        let config = Config::from_slice(contents).expect("config should deserialize");
+        assert_eq!(config.hittables.len(), 3);
+        assert!(config.groups.is_empty());
+    }
+
+    #[test]
+    fn instance_test() {
+        let contents = br##"{
+            "camera": {"lookat": [0.0, 0.0, 0.0], "lookfrom": [0.0, 0.0, -5.0],
+                       "inv_focal_length": 1.0, "aperture": 0.0, "horiz_res": 4, "vert_res": 4},
+            "prototypes": [{"Sphere": {"centre": [0.0, 0.0, 0.0], "radius": 1.0}}],
+            "hittables": [
+                {"shape": {"Instance": {"ref_id": 0, "transform": {"translate": [-3.0, 0.0, 0.0]}}},
+                 "material": {"Diffuse": {"albedo": "#808080"}}},
+                {"shape": {"Instance": {"ref_id": 0, "transform": {"translate": [3.0, 0.0, 0.0], "scale": 2.0}}},
+                 "material": {"Diffuse": {"albedo": "#808080"}}}
+            ]
+        }"##;
+        let config = Config::from_slice(contents).expect("config should deserialize");
+        let [left, right] = [&config.hittables[0].shape, &config.hittables[1].shape];
+        if let (Shape::Instance(left), Shape::Instance(right)) = (left, right) {
+            let (left, right) = (left.prototype.as_ref().unwrap(), right.prototype.as_ref().unwrap());
+            assert!(Arc::ptr_eq(left, right));
+        } else {
+            panic!("both hittables should be instances");
+        }
+
+        let towards_left = crate::ray::Ray::new(Vec3([-3.0, 0.0, -5.0]), Vec3([0.0, 0.0, 1.0]));
+        let towards_right = crate::ray::Ray::new(Vec3([3.0, 0.0, -5.0]), Vec3([0.0, 0.0, 1.0]));
+        assert_eq!(left.intersect(&towards_left), Some(4.0));
+        assert_eq!(right.intersect(&towards_left), None);
+        assert_eq!(left.intersect(&towards_right), None);
+        assert_eq!(right.intersect(&towards_right), Some(3.0));
+        assert_eq!(right.normal_at(Vec3([3.0, 0.0, -2.0])), Vec3([0.0, 0.0, -1.0]));
+
+        for scale in ["0.0", "-0.0", "-2.0"] {
+            let invalid = String::from_utf8_lossy(contents).replace(r#""scale": 2.0"#, &format!(r#""scale": {}"#, scale));
+            let err = Config::from_slice(invalid.as_bytes()).expect_err("the scale is rejected");
+            assert!(err.to_string().contains("must be positive"), "{}", err);
+        }
+
+        let missing = String::from_utf8_lossy(contents).replace(r#""ref_id": 0, "transform": {"translate": [-3"#,
+                                                                 r#""ref_id": 1, "transform": {"translate": [-3"#);
+        let err = Config::from_slice(missing.as_bytes()).expect_err("prototype 1 doesn't exist");
+        assert!(err.to_string().contains("prototype 1"));
+    }
+
+    #[test]
+    fn scene_scale_test() {
+        let contents = br##"{
+            "camera": {"lookat": [0.0, 0.0, 0.0], "lookfrom": [0.0, 0.0, -5.0],
+                       "inv_focal_length": 1.0, "aperture": 0.0, "horiz_res": 4, "vert_res": 4},
+            "scene_scale": 1.0
+        }"##;
+        let unit = Config::from_slice(contents).expect("config should deserialize");
+        assert_eq!(unit.settings.epsilon, crate::geometry::DEFAULT_EPSILON);
+
+        let scaled = String::from_utf8_lossy(contents).replace("1.0\n", "1000.0\n");
+        let large = Config::from_slice(scaled.as_bytes()).expect("config should deserialize");
+        assert_eq!(large.scene_scale, Some(1000.0));
+        assert!((large.settings.epsilon / unit.settings.epsilon - 1000.0).abs() < 1.0e-9);
+        assert_eq!(large.settings.scene_scale, 1000.0);
+
+        // an epsilon the settings give wins over the scale
+        let tuned = String::from_utf8_lossy(contents).replace(
+            "\"scene_scale\": 1.0", "\"scene_scale\": 1000.0, \"settings\": {\"epsilon\": 1.0e-5}");
+        let tuned = Config::from_slice(tuned.as_bytes()).expect("config should deserialize");
+        assert_eq!(tuned.settings.epsilon, 1.0e-5);
+        assert_eq!(tuned.settings.scene_scale, 1000.0);
+
+        let flat = String::from_utf8_lossy(contents).replace("1.0\n", "0.0\n");
+        assert!(Config::from_slice(flat.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn diffuse_bounce_cap_test() {
+        let contents = br##"{
+            "camera": {"lookat": [0.0, 0.0, 0.0], "lookfrom": [0.0, 0.0, -5.0],
+                       "inv_focal_length": 1.0, "aperture": 0.0, "horiz_res": 4, "vert_res": 4},
+            "settings": {"diffuse_bounce_cap": 1}
+        }"##;
+        let capped = Config::from_slice(contents).expect("config should deserialize");
+        assert_eq!(capped.settings.diffuse_bounce_cap, Some(1));
+
+        let uncapped = String::from_utf8_lossy(contents).replace(": 1}", ": 0}");
+        let err = Config::from_slice(uncapped.as_bytes()).expect_err("a cap of 0 is rejected");
+        assert!(err.to_string().contains("diffuse_bounce_cap"));
+    }
+
+    #[test]
+    fn filter_radius_test() {
+        let contents = br##"{
+            "camera": {"lookat": [0.0, 0.0, 0.0], "lookfrom": [0.0, 0.0, -5.0],
+                       "inv_focal_length": 1.0, "aperture": 0.0, "horiz_res": 4, "vert_res": 4},
+            "settings": {"filter_radius": 0.5}
+        }"##;
+        assert_eq!(Config::from_slice(contents).unwrap().settings.filter_radius, 0.5);
+        let narrow = String::from_utf8_lossy(contents).replace("0.5}", "0.25}");
+        let err = Config::from_slice(narrow.as_bytes()).expect_err("a narrow filter is rejected");
+        assert!(err.to_string().contains("filter_radius"), "{}", err);
+    }
+
+    #[test]
+    fn annulus_light_test() {
+        let contents = br##"{
+            "camera": {"lookat": [0.0, 0.0, 0.0], "lookfrom": [0.0, 3.0, -3.0],
+                       "inv_focal_length": 1.0, "aperture": 0.0, "horiz_res": 4, "vert_res": 4},
+            "hittables": [
+                {"shape": {"Disc": {"centre": [0.0, 0.0, 0.0], "normal": [0.0, 1.0, 0.0], "radius": 4.0}},
+                 "material": {"Diffuse": {"albedo": "#808080"}}},
+                {"shape": {"Disc": {"centre": [0.0, 2.0, 0.0], "normal": [0.0, -1.0, 0.0], "radius": 1.0,
+                                    "inner_radius": 0.999}},
+                 "material": {"Emitter": {"albedo": "#ffffff", "intensity": 4.0}}}
+            ],
+            "settings": {"light_sampling": true, "seed": 459}
+        }"##;
+        let config = Config::from_slice(contents).expect("config should deserialize");
+        // the thin ring is sampled as a light without holding the render up
+        let image = crate::render_rgba_image(&config.camera.setup(), &config.hittables, &config.settings, 4);
+        assert!(image.pixels().any(|pixel| pixel.0[..3].iter().any(|&channel| channel > 0)));
+
+        for inner_radius in ["1.0", "1.5", "-0.5"] {
+            let invalid = String::from_utf8_lossy(contents).replace("0.999", inner_radius);
+            let err = Config::from_slice(invalid.as_bytes()).expect_err("the ring is rejected");
+            assert!(err.to_string().contains("cannot have a hole"));
+        }
+        // prototypes are checked too
+        let prototype = String::from_utf8_lossy(contents).replace(r#""hittables""#,
+            r#""prototypes": [{"Disc": {"centre": [0.0, 0.0, 0.0], "normal": [0.0, 1.0, 0.0], "radius": 1.0,
+                                         "inner_radius": 2.0}}],
+               "hittables""#);
+        assert!(Config::from_slice(prototype.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn cuboid_round_trip_test() {
+        let contents = br##"{
+            "camera": {"lookat": [0.0, 0.0, 0.0], "lookfrom": [0.0, 0.0, -5.0],
+                       "inv_focal_length": 1.0, "aperture": 0.0, "horiz_res": 4, "vert_res": 4},
+            "hittables": [
+                {"shape": {"Cuboid": {"dims": [{"start": -1.0, "end": 1.0}, {"start": 0.0, "end": 0.5},
+                                               {"start": 2.0, "end": 3.0}]}},
+                 "material": {"Diffuse": {"albedo": "#808080"}}}
+            ]
+        }"##;
+        let config = Config::from_slice(contents).expect("config should deserialize");
+        let written = serde_json::to_vec(&config).expect("config should serialize");
+        let reread = Config::from_slice(&written).expect("written config should deserialize");
+        let Shape::Cuboid(cuboid) = &reread.hittables[0].shape else {
+            panic!("expected a cuboid, not {}", reread.hittables[0].shape.kind());
+        };
+        assert_eq!(cuboid.dims[2], crate::intervals::Interval::new(2.0, 3.0));
+        assert_eq!(cuboid.min_corner(), Vec3([-1.0, 0.0, 2.0]));
+    }
+
+    #[test]
+    fn include_test() {
+        let dir = std::env::temp_dir().join("raytracer_include_test");
+        fs::create_dir_all(dir.join("parts")).unwrap();
+        let sphere = r##"{"shape": {"Sphere": {"centre": [0.0, 0.0, 0.0], "radius": 1.0}},
+                          "material": {"Diffuse": {"albedo": "#808080"}}}"##;
+        let parent = format!(r#"{{
+            "camera": {{"lookat": [0.0, 0.0, 0.0], "lookfrom": [0.0, 0.0, -5.0],
+                       "inv_focal_length": 1.0, "aperture": 0.0, "horiz_res": 4, "vert_res": 4}},
+            "include": ["parts/child.json"],
+            "hittables": [{}]
+        }}"#, sphere);
+        fs::write(dir.join("parent.json"), parent).unwrap();
+        // includes are relative to the file including them
+        fs::write(dir.join("parts/child.json"),
+            format!(r#"{{"include": ["grandchild.json"], "hittables": [{}, {}]}}"#, sphere, sphere)).unwrap();
+        fs::write(dir.join("parts/grandchild.json"), format!(r#"{{"hittables": [{}]}}"#, sphere)).unwrap();
+        let config = Config::from_path(dir.join("parent.json")).expect("includes should resolve");
+        assert_eq!(config.hittables.len(), 4);
+
+        // the grandchild including the parent closes a cycle
+        fs::write(dir.join("parts/grandchild.json"), r#"{"include": ["../parent.json"]}"#).unwrap();
+        let err = Config::from_path(dir.join("parent.json")).expect_err("the include is cyclic");
+        assert!(err.to_string().contains("including itself"), "{}", err);
+        assert!(Config::from_path(dir.join("missing.json")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn include_instances_test() {
+        let dir = std::env::temp_dir().join("raytracer_include_instances_test");
+        fs::create_dir_all(&dir).unwrap();
+        let prototype = |radius: f64| format!(r#"{{"Sphere": {{"centre": [0.0, 0.0, 0.0], "radius": {:.1}}}}}"#, radius);
+        let instance = r##"{"shape": {"Instance": {"ref_id": 0}}, "material": {"Diffuse": {"albedo": "#808080"}}}"##;
+        fs::write(dir.join("parent.json"), format!(r#"{{
+            "camera": {{"lookat": [0.0, 0.0, 0.0], "lookfrom": [0.0, 0.0, -5.0],
+                       "inv_focal_length": 1.0, "aperture": 0.0, "horiz_res": 4, "vert_res": 4}},
+            "include": ["child.json"],
+            "prototypes": [{}],
+            "hittables": [{}]
+        }}"#, prototype(1.0), instance)).unwrap();
+        fs::write(dir.join("child.json"), format!(
+            r#"{{"include": ["grandchild.json"], "prototypes": [{}], "groups": [{{"name": "parts", "hittables": [{}]}}]}}"#,
+            prototype(2.0), instance)).unwrap();
+        fs::write(dir.join("grandchild.json"), format!(r#"{{"prototypes": [{}], "hittables": [{}]}}"#,
+            prototype(3.0), instance)).unwrap();
+        let config = Config::from_path(dir.join("parent.json")).expect("includes should resolve");
+
+        // each instance keeps to the prototype of the file it is in
+        let radii: Vec<f64> = config.hittables.iter().map(|hittable| match &hittable.shape {
+            Shape::Instance(instance) => match instance.prototype() {
+                Shape::Sphere(sphere) => sphere.radius,
+                _ => panic!("the prototypes are spheres"),
+            },
+            _ => panic!("the hittables are instances"),
+        }).collect();
+        assert_eq!(radii, vec![1.0, 3.0, 2.0]);
+
+        // which of the parent's hittables are lights is for the parent to say
+        fs::write(dir.join("grandchild.json"), format!(r#"{{"hittables": [{}], "lights": [0]}}"#, instance)).unwrap();
+        let err = Config::from_path(dir.join("parent.json")).expect_err("included lights are rejected");
+        assert!(err.to_string().contains("cannot pick out lights"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn euler_camera_test() {
+        let contents = br#"{"lookfrom": [1.0, 2.0, 3.0], "yaw": 0.0, "pitch": 0.0, "focal_distance": 2.0,
+                            "inv_focal_length": 1.0, "aperture": 0.0, "horiz_res": 4, "vert_res": 4}"#;
+        let setup: SetupCamera = serde_json::from_slice(contents).expect("camera should deserialize");
+        assert!(matches!(setup, SetupCamera::Euler { .. }));
+
+        let cam = setup.setup();
+        assert_eq!(cam.lookat, Vec3([1.0, 2.0, 1.0]));
+        assert_eq!(cam.lookup, Vec3([0.0, 1.0, 0.0]));
+        assert_eq!(cam.focal_distance, 2.0);
+        assert_eq!(cam.aperture_shape, ApertureShape::Circle);
+
+        // a quarter turn of roll lays the up vector along the horizontal
+        let (direction, up) = euler_orientation(0.0, 0.0, 90.0);
+        assert!((direction - Vec3([0.0, 0.0, -1.0])).norm() < 1.0e-12);
+        assert!((up - Vec3([1.0, 0.0, 0.0])).norm() < 1.0e-12);
+        // pitching up keeps the up vector square to the view
+        let (direction, up) = euler_orientation(30.0, 45.0, 0.0);
+        assert!(direction.dotprod(&up).abs() < 1.0e-12);
+        assert!(direction.0[1] > 0.0 && up.0[1] > 0.0);
+    }
 }
@@ -0,0 +1,282 @@
+use std::ops::Range;
+
+use crate::color::Color;
+use crate::settings::RenderSettings;
+use crate::{encode_pixel, encode_with_maxval};
+
+// the narrowest box filter, covering exactly its own pixel
+pub const MIN_FILTER_RADIUS: f64 = 0.5;
+
+// the weighted sum of the samples landing on one pixel, normalized by the
+// sum of their weights as a wide filter gives each pixel a different total
+#[derive(Debug, Clone, Copy)]
+pub struct PixelAccumulator {
+    weighted_color_sum: Color,
+    weighted_alpha_sum: f64,
+    weight_sum: f64,
+}
+
+impl PixelAccumulator {
+    pub fn new() -> PixelAccumulator {
+        PixelAccumulator {
+            weighted_color_sum: Color::new(0.0, 0.0, 0.0),
+            weighted_alpha_sum: 0.0,
+            weight_sum: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, color: Color, alpha: f64, weight: f64) {
+        self.weighted_color_sum += weight * color;
+        self.weighted_alpha_sum += weight * alpha;
+        self.weight_sum += weight;
+    }
+
+    // a pixel no sample reached stays black
+    pub fn resolve(&self) -> Color {
+        if self.weight_sum > 0.0 {
+            (1.0 / self.weight_sum) * self.weighted_color_sum
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    // the fraction of the samples that hit the scene, 0 for no samples
+    pub fn resolve_alpha(&self) -> f64 {
+        if self.weight_sum > 0.0 {
+            self.weighted_alpha_sum / self.weight_sum
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Default for PixelAccumulator {
+    fn default() -> Self {
+        PixelAccumulator::new()
+    }
+}
+
+// the accumulated samples over the (columns, rows) render region
+pub struct Film {
+    columns: Range<u32>,
+    rows: Range<u32>,
+    pixels: Vec<PixelAccumulator>,
+    non_finite_samples: u32,
+}
+
+impl Film {
+    pub fn new(columns: Range<u32>, rows: Range<u32>) -> Film {
+        let pixels = vec![PixelAccumulator::new(); columns.len() * rows.len()];
+        Film {
+            columns,
+            rows,
+            pixels,
+            non_finite_samples: 0,
+        }
+    }
+
+    // adds a sample taken at (x, y) in pixel units to every pixel of the
+    // region whose box filter of half-width filter_radius contains it,
+    // the filter of pixel i covers [i + 0.5 - radius, i + 0.5 + radius),
+    // a nan or infinite sample counts as black so it can't spoil the pixel
+    pub fn splat(&mut self, x: f64, y: f64, color: Color, alpha: f64, filter_radius: f64) {
+        let color = if color.is_finite() {
+            color
+        } else {
+            self.non_finite_samples += 1;
+            Color::new(0.0, 0.0, 0.0)
+        };
+        let (columns, rows) = (
+            covering_pixels(x, filter_radius, &self.columns),
+            covering_pixels(y, filter_radius, &self.rows),
+        );
+        for j in rows {
+            for i in columns.clone() {
+                let idx = (j - self.rows.start) as usize * self.columns.len()
+                    + (i - self.columns.start) as usize;
+                self.pixels[idx].add(color, alpha, 1.0);
+            }
+        }
+    }
+
+    // the resolved colors row by row from the top left
+    pub fn pixels(&self) -> impl Iterator<Item = Color> + '_ {
+        self.pixels.iter().map(PixelAccumulator::resolve)
+    }
+
+    // the resolved alphas in the same order as pixels
+    pub fn alphas(&self) -> impl Iterator<Item = f64> + '_ {
+        self.pixels.iter().map(PixelAccumulator::resolve_alpha)
+    }
+
+    // the (column, row) of each pixel in the same order as pixels
+    pub fn positions(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.rows
+            .clone()
+            .flat_map(|j| self.columns.clone().map(move |i| (i, j)))
+    }
+
+    // every resolved pixel encoded to the output levels of settings, with
+    // its exposure, tone mapping, gamma and dither
+    pub fn encoded<'a>(
+        &'a self,
+        settings: &'a RenderSettings,
+    ) -> impl Iterator<Item = (u16, u16, u16)> + 'a {
+        self.pixels()
+            .zip(self.positions())
+            .map(|(color, pixel)| encode_pixel(color, settings, pixel))
+    }
+
+    // as encoded, to the 8-bit channels of an image of the region
+    pub fn to_rgb_image(&self, settings: &RenderSettings) -> image::RgbImage {
+        let channels = self
+            .pixels()
+            .zip(self.positions())
+            .flat_map(|(color, pixel)| {
+                let (r, g, b) = encode_with_maxval(color, settings, pixel, u8::MAX as u16);
+                [r as u8, g as u8, b as u8]
+            })
+            .collect();
+        image::RgbImage::from_raw(self.width(), self.height(), channels)
+            .expect("a channel per pixel")
+    }
+
+    // as to_rgb_image with the alphas in a fourth channel
+    pub fn to_rgba_image(&self, settings: &RenderSettings) -> image::RgbaImage {
+        let channels = self
+            .pixels()
+            .zip(self.alphas())
+            .zip(self.positions())
+            .flat_map(|((color, alpha), pixel)| {
+                let (r, g, b) = encode_with_maxval(color, settings, pixel, u8::MAX as u16);
+                [r as u8, g as u8, b as u8, (255.0 * alpha).round() as u8]
+            })
+            .collect();
+        image::RgbaImage::from_raw(self.width(), self.height(), channels)
+            .expect("a channel per pixel")
+    }
+
+    pub fn width(&self) -> u32 {
+        self.columns.len() as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.rows.len() as u32
+    }
+
+    // how many samples were blacked out by splat
+    pub fn non_finite_samples(&self) -> u32 {
+        self.non_finite_samples
+    }
+}
+
+// how many pixels to either side of its own a sample taken within a pixel
+// can land on, so 0 for the narrowest filter
+pub fn filter_reach(filter_radius: f64) -> u32 {
+    ((filter_radius.max(MIN_FILTER_RADIUS) + 0.5).ceil() - 1.0) as u32
+}
+
+// the pixels along one axis whose filter contains coord, clamped to region,
+// a filter narrower than the pixel being widened to it so each sample still
+// lands on the pixel it was taken in
+fn covering_pixels(coord: f64, filter_radius: f64, region: &Range<u32>) -> Range<u32> {
+    let filter_radius = filter_radius.max(MIN_FILTER_RADIUS);
+    let first = (coord - 0.5 - filter_radius).floor() + 1.0;
+    let last = (coord - 0.5 + filter_radius).floor();
+    let clamp = |pixel: f64| pixel.clamp(region.start as f64, region.end as f64) as u32;
+    clamp(first)..clamp(last + 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulator_normalizes_by_weight_test() {
+        let mut acc = PixelAccumulator::new();
+        acc.add(Color::new(1.0, 0.0, 0.0), 1.0, 3.0);
+        acc.add(Color::new(0.0, 1.0, 0.0), 0.0, 1.0);
+        let color = acc.resolve();
+        assert_eq!((color.r, color.g, color.b), (0.75, 0.25, 0.0));
+        assert_eq!(acc.resolve_alpha(), 0.75);
+    }
+
+    #[test]
+    fn splat_radius_test() {
+        // the unit box filter only ever reaches the pixel the sample is in
+        assert_eq!(covering_pixels(2.0, 0.5, &(0..4)), 2..3);
+        assert_eq!(covering_pixels(2.999, 0.5, &(0..4)), 2..3);
+        // a wider one reaches the neighbours as well, but not off the image
+        assert_eq!(covering_pixels(2.25, 1.0, &(0..4)), 1..3);
+        assert_eq!(covering_pixels(0.25, 1.5, &(0..4)), 0..2);
+        assert_eq!(covering_pixels(2.75, 1.0, &(1..3)), 2..3);
+        // a narrower one would miss every pixel centre, so is widened
+        assert_eq!(covering_pixels(2.0, 0.25, &(0..4)), 2..3);
+        assert_eq!(covering_pixels(2.999, 0.0, &(0..4)), 2..3);
+    }
+
+    #[test]
+    fn filter_reach_test() {
+        assert_eq!(filter_reach(0.25), 0);
+        assert_eq!(filter_reach(0.5), 0);
+        assert_eq!(filter_reach(0.75), 1);
+        assert_eq!(filter_reach(1.5), 1);
+        assert_eq!(filter_reach(1.75), 2);
+        // the furthest the samples of pixel 2 land
+        assert_eq!(covering_pixels(2.0, 1.5, &(0..6)), 1..4);
+        assert_eq!(covering_pixels(2.999, 1.5, &(0..6)), 1..4);
+        assert_eq!(covering_pixels(2.0, 1.75, &(0..6)), 0..4);
+        assert_eq!(covering_pixels(2.999, 1.75, &(0..6)), 1..5);
+    }
+
+    #[test]
+    fn splat_accumulates_test() {
+        let mut film = Film::new(2..4, 0..1);
+        film.splat(2.5, 0.5, Color::new(1.0, 0.0, 0.0), 1.0, 0.5);
+        film.splat(2.25, 0.75, Color::new(0.0, 0.0, 1.0), 0.0, 0.5);
+        // off the region, so only a wider filter would reach it
+        film.splat(1.5, 0.5, Color::new(1.0, 1.0, 1.0), 1.0, 0.5);
+
+        let pixels: Vec<Color> = film.pixels().collect();
+        assert_eq!((pixels[0].r, pixels[0].g, pixels[0].b), (0.5, 0.0, 0.5));
+        assert_eq!((pixels[1].r, pixels[1].g, pixels[1].b), (0.0, 0.0, 0.0));
+        assert_eq!(film.alphas().collect::<Vec<f64>>(), [0.5, 0.0]);
+        assert_eq!(film.positions().collect::<Vec<_>>(), [(2, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn to_rgb_image_test() {
+        let mut film = Film::new(0..2, 0..1);
+        film.splat(0.5, 0.5, Color::new(0.5, 1.0, 3.0), 1.0, 0.5);
+        let settings = RenderSettings {
+            gamma: crate::color::Gamma::Linear,
+            ..Default::default()
+        };
+        let image = film.to_rgb_image(&settings);
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(0, 0).0, [128, 255, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 0]);
+
+        // tone mapping keeps the brighter channels apart instead of clipping
+        let reinhard = RenderSettings {
+            tonemap: crate::color::Tonemap::Reinhard,
+            ..settings
+        };
+        let image = film.to_rgb_image(&reinhard);
+        assert_eq!(image.get_pixel(0, 0).0, [85, 128, 191]);
+        assert_eq!(film.to_rgba_image(&reinhard).get_pixel(0, 0).0[3], 255);
+    }
+
+    #[test]
+    fn non_finite_sample_test() {
+        let mut film = Film::new(0..1, 0..1);
+        film.splat(0.5, 0.5, Color::new(1.0, 1.0, 1.0), 1.0, 0.5);
+        film.splat(0.5, 0.5, Color::new(f64::NAN, 0.0, 0.0), 1.0, 0.5);
+        film.splat(0.5, 0.5, Color::new(0.0, f64::INFINITY, 0.0), 1.0, 0.5);
+        film.splat(0.5, 0.5, Color::new(1.0, 1.0, 1.0), 1.0, 0.5);
+
+        let color = film.pixels().next().unwrap();
+        assert_eq!((color.r, color.g, color.b), (0.5, 0.5, 0.5));
+        assert_eq!(film.non_finite_samples(), 2);
+    }
+}
@@ -1,36 +1,237 @@
+use rand::Rng;
 use serde::{Serialize, Deserialize};
 use std::ops::Deref;
+use std::sync::Arc;
 
-use crate::vector::Vec3;
+
+use crate::vector::{lerp_vec3, Vec3};
 use crate::ray::Ray;
 use crate::intervals;
 use crate::intervals::Interval;
 
+// converts a density uniform over the area of a surface into one over the
+// solid angle seen from distance away, with the surface at cosine to the view
+fn solid_angle_pdf(distance_squared: f64, cosine: f64, area: f64) -> f64 {
+    if cosine == 0.0 {return 0.0};
+    distance_squared / (cosine * area)
+}
+
+// whether the parallelogram on edges u and v has next to no area for the
+// length of its edges, the edges lying along one line or one having none,
+// so that no plane is left to hit
+fn is_degenerate(u: Vec3, v: Vec3) -> bool {
+    u.cross(&v).norm_squared() <= 1.0e-24 * u.norm_squared() * v.norm_squared()
+}
+
+// the default lower bound on hit distances, keeping a scattered ray from
+// hitting the surface it leaves, RenderSettings::epsilon scales it per scene
+pub const DEFAULT_EPSILON: f64 = 1.0e-6;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Shape {
     Sphere(Sphere),
     Disc(Disc),
+    Plane(Plane),
+    Quad(Quad),
+    Cuboid(Cuboid),
+    Cylinder(Cylinder),
+    Triangle(Triangle),
+    SphereSection(SphereSection),
+    Instance(Instance),
     #[serde(skip_serializing, skip_deserializing)]
     BoundVolume(BoundBox),
 }
 
 impl Shape {
     pub fn intersect(&self, ray: &Ray) -> Option<f64> {
+        self.intersect_beyond(ray, DEFAULT_EPSILON)
+    }
+
+    // the nearest hit further along the ray than t_min
+    pub fn intersect_beyond(&self, ray: &Ray, t_min: f64) -> Option<f64> {
         match self {
-            Shape::Sphere(sphere) => sphere.intersect(ray),
-            Shape::Disc(disc) => disc.intersect(ray),
-            Shape::BoundVolume(bbox) => bbox.intersect(ray),
-            _ => unreachable!(),
+            Shape::Sphere(sphere) => sphere.intersect_beyond(ray, t_min),
+            Shape::Disc(disc) => disc.intersect_beyond(ray, t_min),
+            Shape::Plane(plane) => plane.intersect_beyond(ray, t_min),
+            Shape::Quad(quad) => quad.intersect_beyond(ray, t_min),
+            Shape::Cuboid(cuboid) => cuboid.intersect_beyond(ray, t_min),
+            Shape::Cylinder(cylinder) => cylinder.intersect_beyond(ray, t_min),
+            Shape::Triangle(triangle) => triangle.intersect_beyond(ray, t_min),
+            Shape::SphereSection(section) => section.intersect_beyond(ray, t_min),
+            Shape::Instance(instance) => instance.intersect_beyond(ray, t_min),
+            Shape::BoundVolume(bbox) => bbox.intersect_beyond(ray, t_min),
         }
     }
 
+    // whether the ray hits anywhere in [t_min, t_max], for shadow rays which
+    // only need to know if something is in the way
+    pub fn intersect_any(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect_beyond(ray, t_min).is_some_and(|param| param <= t_max)
+    }
+
+    // every hit further along the ray than t_min, nearest first, found by
+    // asking for the next one beyond each hit in turn, stopping should a
+    // shape not get any further than the hit it was asked from
+    pub fn intersect_all(&self, ray: &Ray, t_min: f64) -> Vec<f64> {
+        if let Shape::Instance(instance) = self {return instance.intersect_all(ray, t_min)};
+        let next_hit = |&param: &f64| self.intersect_beyond(ray, param).filter(|&t| t > param);
+        std::iter::successors(self.intersect_beyond(ray, t_min), next_hit).collect()
+    }
+
     pub fn normal_at(&self, surface_pos: Vec3) -> Vec3 {
         match self {
             Shape::Sphere(sphere) => sphere.normal_at(surface_pos),
             Shape::Disc(disc) => disc.normal_at(surface_pos),
-            _ => todo!(),
+            Shape::Plane(plane) => plane.normal_at(surface_pos),
+            Shape::Quad(quad) => quad.normal_at(surface_pos),
+            Shape::Cuboid(cuboid) => cuboid.normal_at(surface_pos),
+            Shape::Cylinder(cylinder) => cylinder.normal_at(surface_pos),
+            Shape::Triangle(triangle) => triangle.normal_at(surface_pos),
+            Shape::SphereSection(section) => section.sphere.normal_at(surface_pos),
+            Shape::Instance(instance) => instance.prototype().normal_at(instance.transform.to_local(surface_pos)),
+            Shape::BoundVolume(bbox) => bbox.normal_at(surface_pos),
         }
     }
+
+    // the normal turned to face against incoming_dir, along with whether
+    // the ray hit the front, the side normal_at points out of
+    pub fn oriented_normal_at(&self, surface_pos: Vec3, incoming_dir: Vec3) -> (Vec3, bool) {
+        let normal = self.normal_at(surface_pos);
+        let front_face = normal.dotprod(&incoming_dir) <= 0.0;
+        if front_face {(normal, true)} else {(-1.0 * normal, false)}
+    }
+
+    // whether the shape only has a front, so that as an emitter it gives off
+    // light only on the side its normal points out of like a panel light
+    pub fn is_one_sided(&self) -> bool {
+        match self {
+            Shape::Disc(_) => true,
+            Shape::Instance(instance) => instance.prototype().is_one_sided(),
+            _ => false,
+        }
+    }
+
+    // whether BoundBox::surround can box the shape in, planes going on forever
+    pub fn is_bounded(&self) -> bool {
+        match self {
+            Shape::Plane(_) => false,
+            Shape::Instance(instance) => instance.prototype().is_bounded(),
+            _ => true,
+        }
+    }
+
+    // the point relative to the shape, which is where materials look up
+    // their albedo: the unit direction from the centre of round shapes and
+    // the offset from the centre or corner of flat ones
+    pub fn local_coords(&self, point: Vec3) -> Vec3 {
+        match self {
+            Shape::Sphere(sphere) => (point - sphere.centre).normalize(),
+            Shape::Disc(disc) => point - disc.centre,
+            Shape::Plane(plane) => point - plane.point,
+            Shape::Quad(quad) => point - quad.corner,
+            Shape::Cuboid(cuboid) => point - cuboid.min_corner(),
+            Shape::Cylinder(cylinder) => point - cylinder.base,
+            Shape::Triangle(triangle) => point - triangle.vertices[0],
+            Shape::SphereSection(section) => (point - section.sphere.centre).normalize(),
+            // every instance is textured the same as its prototype
+            Shape::Instance(instance) => instance.prototype().local_coords(instance.transform.to_local(point)),
+            Shape::BoundVolume(bbox) => point - Vec3([bbox[0].start, bbox[1].start, bbox[2].start]),
+        }
+    }
+
+    // a point uniformly distributed over the area of the shapes that can be
+    // sampled as lights
+    pub fn sample_surface<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Vec3> {
+        match self {
+            Shape::Disc(disc) => Some(disc.sample_point(rng)),
+            Shape::Quad(quad) => Some(quad.sample_point(rng)),
+            Shape::Triangle(triangle) => Some(triangle.sample_point(rng)),
+            Shape::Instance(instance) =>
+                instance.prototype().sample_surface(rng).map(|point| instance.transform.to_world(point)),
+            _ => None,
+        }
+    }
+
+    pub fn area(&self) -> Option<f64> {
+        match self {
+            Shape::Disc(disc) => Some(disc.area()),
+            Shape::Quad(quad) => Some(quad.edge_u.cross(&quad.edge_v).norm()),
+            Shape::Triangle(triangle) => Some(0.5 * triangle.area_normal().norm()),
+            Shape::Instance(instance) => {
+                let scale = instance.transform.scale;
+                instance.prototype().area().map(|area| scale * scale * area)
+            }
+            _ => None,
+        }
+    }
+
+    // the solid angle density seen from `from` of sample_surface picking
+    // `point`, zero for shapes never sampled and for a surface seen edge on
+    pub fn pdf(&self, from: Vec3, point: Vec3) -> f64 {
+        let Some(area) = self.area() else {return 0.0};
+        let to_point = point - from;
+        let cosine = self.normal_at(point).normalize().dotprod(&to_point.normalize()).abs();
+        solid_angle_pdf(to_point.norm_squared(), cosine, area)
+    }
+
+    // how far a surface point is from the nearest edge of the shape, in units
+    // of its edge lengths, None for shapes without edges to draw
+    pub fn edge_distance(&self, point: Vec3) -> Option<f64> {
+        match self {
+            Shape::Quad(quad) => {
+                let (alpha, beta) = quad.edge_coords(point);
+                Some(alpha.min(1.0 - alpha).min(beta).min(1.0 - beta))
+            }
+            Shape::Triangle(triangle) => {
+                let (alpha, beta) = triangle.barycentrics(point);
+                Some(alpha.min(beta).min(1.0 - alpha - beta))
+            }
+            Shape::Instance(instance) => instance.prototype().edge_distance(instance.transform.to_local(point)),
+            _ => None,
+        }
+    }
+
+    // the variant name, as written in a scene file
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Shape::Sphere(_) => "Sphere",
+            Shape::Disc(_) => "Disc",
+            Shape::Plane(_) => "Plane",
+            Shape::Quad(_) => "Quad",
+            Shape::Cuboid(_) => "Cuboid",
+            Shape::Cylinder(_) => "Cylinder",
+            Shape::Triangle(_) => "Triangle",
+            Shape::SphereSection(_) => "SphereSection",
+            Shape::Instance(_) => "Instance",
+            Shape::BoundVolume(_) => "BoundVolume",
+        }
+    }
+
+    // orthonormal (tangent, bitangent) at a surface point with the given normal
+    pub fn tangent_frame(&self, surface_pos: Vec3, normal: Vec3) -> (Vec3, Vec3) {
+        match self {
+            Shape::Sphere(sphere) => sphere.tangent_frame(surface_pos, normal),
+            Shape::Disc(disc) => disc.basis(),
+            Shape::Quad(quad) => quad.basis(),
+            Shape::Triangle(triangle) => triangle.basis(),
+            Shape::SphereSection(section) => section.sphere.tangent_frame(surface_pos, normal),
+            Shape::Instance(instance) =>
+                instance.prototype().tangent_frame(instance.transform.to_local(surface_pos), normal),
+            _ => perpendicular_basis(normal),
+        }
+    }
+}
+
+fn perpendicular_basis(normal: Vec3) -> (Vec3, Vec3) {
+    let normal = normal.normalize();
+    let helper = if normal[0].abs() > 0.9 {
+        Vec3([0.0, 1.0, 0.0])
+    } else {
+        Vec3([1.0, 0.0, 0.0])
+    };
+    let tangent = helper.cross(&normal).normalize();
+    let bitangent = normal.cross(&tangent);
+    (tangent, bitangent)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -44,6 +245,140 @@ pub struct Disc {
     pub centre: Vec3,
     pub normal: Vec3,
     pub radius: f64,
+    // a ring with a hole of this radius, 0 for a whole disc
+    #[serde(default)]
+    pub inner_radius: f64,
+    // the direction u increases in across the disc, made square to the
+    // normal, one is picked from the normal alone when not given
+    #[serde(default)]
+    pub tangent: Option<Vec3>,
+}
+
+// the whole plane through point square to normal, which has no bounding
+// box so is tested against every ray, for floors reaching the horizon
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+// the part of a sphere with polar angle theta from the y axis and azimuth
+// phi about it, from the x axis towards the z axis, inside the given ranges
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SphereSection {
+    pub sphere: Sphere,
+    pub theta: Interval,
+    pub phi: Interval,
+}
+
+// a copy of the scene's prototype number ref_id placed by transform, all the
+// instances of a prototype share the one shape, resolved when the scene loads
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Instance {
+    pub ref_id: usize,
+    #[serde(default)]
+    pub transform: Transform,
+    #[serde(skip)]
+    pub prototype: Option<Arc<Shape>>,
+}
+
+// scales by a positive factor about the origin and then moves by translate,
+// a uniform scale leaves directions and so normals unchanged
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Transform {
+    #[serde(default = "no_translation")]
+    pub translate: Vec3,
+    #[serde(default = "unit_scale")]
+    pub scale: f64,
+}
+
+fn no_translation() -> Vec3 {
+    Vec3([0.0, 0.0, 0.0])
+}
+
+fn unit_scale() -> f64 {
+    1.0
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {translate: no_translation(), scale: unit_scale()}
+    }
+}
+
+impl Transform {
+    pub fn to_local(&self, point: Vec3) -> Vec3 {
+        (point - self.translate) / self.scale
+    }
+
+    pub fn to_world(&self, point: Vec3) -> Vec3 {
+        self.scale * point + self.translate
+    }
+}
+
+impl Instance {
+    pub fn new(prototype: Arc<Shape>, ref_id: usize, transform: Transform) -> Self {
+        Self {ref_id, transform, prototype: Some(prototype)}
+    }
+
+    pub fn prototype(&self) -> &Shape {
+        self.prototype.as_deref().expect("instance traced before its prototype was resolved")
+    }
+
+    // the ray is taken into the frame of the prototype, where distances
+    // along it are shorter by the scale
+    pub fn intersect_beyond(&self, ray: &Ray, t_min: f64) -> Option<f64> {
+        let scale = self.transform.scale;
+        let local_ray = Ray {orig: self.transform.to_local(ray.orig), ..*ray};
+        self.prototype().intersect_beyond(&local_ray, t_min / scale).map(|t| t * scale)
+    }
+
+    // stepping from hit to hit in the frame of the prototype, as a hit taken
+    // out to the world and back may land just short of itself and be found
+    // again, ending the walk early
+    pub fn intersect_all(&self, ray: &Ray, t_min: f64) -> Vec<f64> {
+        let scale = self.transform.scale;
+        let local_ray = Ray {orig: self.transform.to_local(ray.orig), ..*ray};
+        self.prototype().intersect_all(&local_ray, t_min / scale).into_iter().map(|t| t * scale).collect()
+    }
+}
+
+// a sphere moving linearly from centre0 at time 0 to centre1 at time 1,
+// rays carry no time yet so it is not a Shape to trace against
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MovingSphere {
+    pub centre0: Vec3,
+    pub centre1: Vec3,
+    pub radius: f64,
+}
+
+// the parallelogram spanned by edge_u and edge_v from corner, facing along edge_u x edge_v
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Quad {
+    pub corner: Vec3,
+    pub edge_u: Vec3,
+    pub edge_v: Vec3,
+}
+
+// facing out of the side the vertices go anticlockwise around
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Triangle {
+    pub vertices: [Vec3; 3],
+}
+
+// the closed cylinder of radius around axis, from base up to height along it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cylinder {
+    pub base: Vec3,
+    pub axis: Vec3,
+    pub radius: f64,
+    pub height: f64,
+}
+
+// the solid box between the start and end of dims along each axis
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cuboid {
+    pub dims: [Interval; 3],
 }
 
 #[derive(Debug)]
@@ -80,16 +415,74 @@ impl BoundBox {
         }
     }
 
+    pub fn intersect_beyond(&self, ray: &Ray, t_min: f64) -> Option<f64> {
+        box_intersect_beyond(self, ray, t_min)
+    }
+
+    pub fn normal_at(&self, surface_pos: Vec3) -> Vec3 {
+        box_normal_at(self, surface_pos)
+    }
+
+    // the box around a shape, which has to be one that is_bounded
     pub fn surround(shape: &Shape) -> BoundBox {
         match shape {
             Shape::Sphere(sphere) => {
-                let mut starts = sphere.centre.iter().map(|coord| coord - sphere.radius);
-                let mut ends = sphere.centre.iter().map(|coord| coord + sphere.radius);
+                let radius = sphere.radius.abs(); // negative radius spheres face inward
+                let mut starts = sphere.centre.iter().map(|coord| coord - radius);
+                let mut ends = sphere.centre.iter().map(|coord| coord + radius);
                 return BoundBox([Interval::new(starts.next().unwrap(), ends.next().unwrap()),
                                  Interval::new(starts.next().unwrap(), ends.next().unwrap()),
                                  Interval::new(starts.next().unwrap(), ends.next().unwrap())]);
 
             },
+            Shape::Disc(disc) => {
+                let normal = disc.normal.normalize();
+                // a tilted disc reaches radius * sin(angle between axis and normal)
+                let mut extents = (0..3).map(|i| disc.radius * (1.0 - normal[i] * normal[i]).max(0.0).sqrt());
+                let mut axis_interval = |i: usize| {
+                    let extent = extents.next().unwrap();
+                    Interval::new(disc.centre[i] - extent, disc.centre[i] + extent)
+                };
+                BoundBox([axis_interval(0), axis_interval(1), axis_interval(2)])
+            },
+            Shape::Quad(quad) => {
+                let corners = [quad.corner, quad.corner + quad.edge_u,
+                               quad.corner + quad.edge_v, quad.corner + quad.edge_u + quad.edge_v];
+                let axis_interval = |i: usize| {
+                    let start = corners.iter().map(|corner| corner[i]).fold(f64::INFINITY, f64::min);
+                    let end = corners.iter().map(|corner| corner[i]).fold(f64::NEG_INFINITY, f64::max);
+                    Interval::new(start, end)
+                };
+                BoundBox([axis_interval(0), axis_interval(1), axis_interval(2)])
+            },
+            Shape::Triangle(triangle) => {
+                let axis_interval = |i: usize| {
+                    let start = triangle.vertices.iter().map(|vertex| vertex[i]).fold(f64::INFINITY, f64::min);
+                    let end = triangle.vertices.iter().map(|vertex| vertex[i]).fold(f64::NEG_INFINITY, f64::max);
+                    Interval::new(start, end)
+                };
+                BoundBox([axis_interval(0), axis_interval(1), axis_interval(2)])
+            },
+            Shape::Cuboid(cuboid) => BoundBox(cuboid.dims),
+            // everything between the caps lies within the box of the two
+            Shape::Cylinder(cylinder) => {
+                let [bottom, top] = cylinder.caps();
+                cover(&BoundBox::surround(&Shape::Disc(bottom)), &BoundBox::surround(&Shape::Disc(top)))
+            },
+            // the whole sphere's box, loose for small sections but always covering
+            Shape::SphereSection(section) => {
+                let whole = Sphere::new(section.sphere.centre, section.sphere.radius);
+                BoundBox::surround(&Shape::Sphere(whole))
+            },
+            // a positive scale keeps each side of the box on the same side
+            Shape::Instance(instance) => {
+                let prototype_box = BoundBox::surround(instance.prototype());
+                let (start, end) = (
+                    instance.transform.to_world(Vec3([prototype_box[0].start, prototype_box[1].start, prototype_box[2].start])),
+                    instance.transform.to_world(Vec3([prototype_box[0].end, prototype_box[1].end, prototype_box[2].end])));
+                BoundBox([Interval::new(start[0], end[0]), Interval::new(start[1], end[1]),
+                          Interval::new(start[2], end[2])])
+            },
             _ => unimplemented!(),
         }
     }
@@ -102,6 +495,19 @@ impl Sphere {
     }
 
     pub fn intersect(&self, ray: &Ray) -> Option<f64> {
+        self.intersect_beyond(ray, DEFAULT_EPSILON)
+    }
+
+    pub fn intersect_beyond(&self, ray: &Ray, t_min: f64) -> Option<f64> {
+        let (t_smaller, t_larger) = self.roots(ray)?;
+        if t_smaller > t_min {
+            return Some(t_smaller);
+        };
+        if t_larger > t_min { Some(t_larger) } else {Option::None} // t_min to avoid self-intersection
+    }
+
+    // both ray parameters where the ray crosses the sphere, smaller first
+    fn roots(&self, ray: &Ray) -> Option<(f64, f64)> {
         let ray_to_centre = ray.orig - self.centre;
         let b = 2.0 * ray_to_centre.dotprod(&ray.dir);
         let c = ray_to_centre.dotprod(&ray_to_centre) - self.radius * self.radius;
@@ -112,35 +518,394 @@ impl Sphere {
         };
         let sq = discrm.sqrt(); // there are two roots from here
 
-        let t_smaller = -0.5 * (b + sq);
-        if t_smaller > 0.0 {
-            return Some(t_smaller);
-        };
-        let t_larger = t_smaller + sq;
-        if t_larger > 1.0e-6 { Some(t_larger) } else {Option::None} // 1.0e-6 to avoid self-intersection
+        // adding sq with the sign of b never cancels, the other root then
+        // follows from their product being c
+        let q = -0.5 * (b + b.signum() * sq);
+        if q == 0.0 {return None}; // the ray starts on the sphere and only grazes it
+        if q < c / q { Some((q, c / q)) } else { Some((c / q, q)) }
     }
 
     pub fn normal_at(&self, surface_pos: Vec3) -> Vec3 {
         (surface_pos - self.centre)/self.radius
     }
+
+    // the tangent follows increasing longitude about the y axis
+    pub fn tangent_frame(&self, _surface_pos: Vec3, normal: Vec3) -> (Vec3, Vec3) {
+        let around = Vec3([0.0, 1.0, 0.0]).cross(&normal);
+        if around.norm_squared() < 1.0e-18 {
+            return perpendicular_basis(normal); // longitude is undefined at the poles
+        }
+        let tangent = around.normalize();
+        let bitangent = normal.cross(&tangent);
+        (tangent, bitangent)
+    }
+}
+
+// the nearest crossing of the surface of the box with dims further along
+// than t_min, the way in from outside and the way out when the ray starts
+// inside
+fn box_intersect_beyond(dims: &[Interval; 3], ray: &Ray, t_min: f64) -> Option<f64> {
+    let mut inside = Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+    for ((interval, orig), dir) in dims.iter().zip(ray.orig.iter()).zip(ray.dir.iter()) {
+        let (t0, t1) = ((interval.start - orig)/dir, (interval.end - orig)/dir);
+        inside = intervals::intersection(&inside, &Interval::new(t0.min(t1), t0.max(t1)))?;
+    }
+    [inside.start, inside.end].into_iter().find(|param| *param > t_min)
+}
+
+// the outward normal of the face of the box with dims nearest the point
+fn box_normal_at(dims: &[Interval; 3], surface_pos: Vec3) -> Vec3 {
+    let mut normal = Vec3([0.0, 0.0, 0.0]);
+    let mut nearest = f64::INFINITY;
+    for (i, interval) in dims.iter().enumerate() {
+        for (face, sign) in [(interval.start, -1.0), (interval.end, 1.0)] {
+            let distance = (surface_pos[i] - face).abs();
+            if distance < nearest {
+                nearest = distance;
+                normal = Vec3([0.0, 0.0, 0.0]);
+                normal.0[i] = sign;
+            }
+        }
+    }
+    normal
+}
+
+impl Cuboid {
+    pub fn new(dims: [Interval; 3]) -> Self {
+        Self {dims}
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Option<f64> {
+        self.intersect_beyond(ray, DEFAULT_EPSILON)
+    }
+
+    pub fn intersect_beyond(&self, ray: &Ray, t_min: f64) -> Option<f64> {
+        box_intersect_beyond(&self.dims, ray, t_min)
+    }
+
+    pub fn normal_at(&self, surface_pos: Vec3) -> Vec3 {
+        box_normal_at(&self.dims, surface_pos)
+    }
+
+    pub fn min_corner(&self) -> Vec3 {
+        Vec3(self.dims.map(|interval| interval.start))
+    }
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, axis: Vec3, radius: f64, height: f64) -> Self {
+        Self {base, axis: axis.normalize(), radius, height}
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Option<f64> {
+        self.intersect_beyond(ray, DEFAULT_EPSILON)
+    }
+
+    // the nearer of the hits on the side, those of the infinite cylinder
+    // within the height, and on the caps
+    pub fn intersect_beyond(&self, ray: &Ray, t_min: f64) -> Option<f64> {
+        let axis = self.axis.normalize(); // deserialized cylinders skip Cylinder::new
+        let across = |v: Vec3| v - v.dotprod(&axis) * axis;
+        let (dir, offset) = (across(ray.dir), across(ray.orig - self.base));
+        let mut side = None;
+        let a = dir.norm_squared();
+        if a > 1.0e-12 { // rays along the axis only meet the caps
+            let half_b = offset.dotprod(&dir);
+            let discrim = half_b * half_b - a * (offset.norm_squared() - self.radius * self.radius);
+            if discrim >= 0.0 {
+                let root = discrim.sqrt();
+                side = [(-half_b - root) / a, (-half_b + root) / a].into_iter().find(|&t| {
+                    let along = (ray.position_at(t) - self.base).dotprod(&axis);
+                    t > t_min && (0.0..=self.height).contains(&along)
+                });
+            }
+        }
+        self.caps().iter().filter_map(|cap| cap.intersect_beyond(ray, t_min))
+            .chain(side).min_by(f64::total_cmp)
+    }
+
+    // the radial normal on the side and the axis on the caps, whichever
+    // surface the point is nearest
+    pub fn normal_at(&self, surface_pos: Vec3) -> Vec3 {
+        let axis = self.axis.normalize();
+        let offset = surface_pos - self.base;
+        let along = offset.dotprod(&axis);
+        let radial = offset - along * axis;
+        let side_distance = (radial.norm() - self.radius).abs();
+        if along.abs() < side_distance {
+            -1.0 * axis
+        } else if (self.height - along).abs() < side_distance {
+            axis
+        } else {
+            radial.normalize()
+        }
+    }
+
+    // the discs closing the bottom and the top, facing out of the cylinder
+    pub fn caps(&self) -> [Disc; 2] {
+        let axis = self.axis.normalize();
+        [Disc::new(self.base, -1.0 * axis, self.radius),
+         Disc::new(self.base + self.height * axis, axis, self.radius)]
+    }
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3) -> Self {
+        Self {point, normal: normal.normalize()}
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Option<f64> {
+        self.intersect_beyond(ray, DEFAULT_EPSILON)
+    }
+
+    pub fn intersect_beyond(&self, ray: &Ray, t_min: f64) -> Option<f64> {
+        let facing = self.normal.dotprod(&ray.dir);
+        if facing.abs() < 1.0e-9 {return None}; // rays along the plane never meet it
+        let h: f64 = (self.point - ray.orig).dotprod(&self.normal)/facing;
+        if h <= t_min {return None}; // behind the origin, t_min as for spheres
+        Some(h)
+    }
+
+    pub fn normal_at(&self, _surface_pos: Vec3) -> Vec3 {
+        self.normal
+    }
 }
 
 impl Disc {
     pub fn new(centre: Vec3, normal: Vec3, radius: f64) -> Self {
-        Self {centre, normal: normal.normalize(), radius}
+        Self {centre, normal: normal.normalize(), radius, inner_radius: 0.0, tangent: None}
+    }
+
+    pub fn with_tangent(self, tangent: Vec3) -> Self {
+        Self {tangent: Some(tangent), ..self}
+    }
+
+    pub fn annulus(centre: Vec3, normal: Vec3, radius: f64, inner_radius: f64) -> Self {
+        let ring = Self {inner_radius, ..Disc::new(centre, normal, radius)};
+        if let Err(err) = ring.check_ring() {panic!("{}", err)};
+        ring
+    }
+
+    // a hole at least as wide as the disc would leave nothing of it
+    pub fn check_ring(&self) -> Result<(), String> {
+        if (0.0..self.radius).contains(&self.inner_radius) {return Ok(())};
+        Err(format!("a disc of radius {} cannot have a hole of radius {}",
+                    self.radius, self.inner_radius))
+    }
+
+    pub fn area(&self) -> f64 {
+        std::f64::consts::PI * (self.radius * self.radius - self.inner_radius * self.inner_radius)
     }
 
     pub fn intersect(&self, ray: &Ray) -> Option<f64> {
-        if self.normal.dotprod(&ray.dir) == 0.0 {return None};
-        let h: f64 = (self.centre - ray.orig).dotprod(&self.normal)/self.normal.dotprod(&ray.dir);
+        self.intersect_beyond(ray, DEFAULT_EPSILON)
+    }
+
+    pub fn intersect_beyond(&self, ray: &Ray, t_min: f64) -> Option<f64> {
+        self.intersect_side(ray, t_min).map(|(h, _)| h)
+    }
+
+    // as intersect_beyond, hitting either side, along with whether the ray
+    // met the front, the side the normal points out of, as a one-sided
+    // emitter or an oriented normal for the shading needs to know
+    pub fn intersect_side(&self, ray: &Ray, t_min: f64) -> Option<(f64, bool)> {
+        let facing = self.normal.dotprod(&ray.dir);
+        if facing.abs() < 1.0e-9 {return None}; // (near) parallel rays never land on the disc
+        let h: f64 = (self.centre - ray.orig).dotprod(&self.normal)/facing;
+        if h <= t_min {return None}; // t_min to avoid self-intersection as for spheres
         let point_in_disc: Vec3 = ray.position_at(h) - self.centre;
-        if point_in_disc.dotprod(&point_in_disc) > self.radius * self.radius {return None};
-        return Some(h)
+        let distance2 = point_in_disc.norm_squared();
+        if distance2 > self.radius * self.radius {return None};
+        if distance2 < self.inner_radius * self.inner_radius {return None}; // through the hole
+        Some((h, facing < 0.0))
     }
 
     pub fn normal_at(&self, _surface_pos: Vec3) -> Vec3 {
         self.normal
     }
+
+    // two unit vectors spanning the plane of the disc, the first along the
+    // tangent when there is one
+    pub fn basis(&self) -> (Vec3, Vec3) {
+        let normal = self.normal.normalize(); // deserialized discs skip Disc::new
+        match self.tangent {
+            Some(tangent) => {
+                let tangent = (tangent - tangent.dotprod(&normal) * normal).normalize();
+                (tangent, normal.cross(&tangent))
+            },
+            None => perpendicular_basis(normal),
+        }
+    }
+
+    // the (u, v) texture coordinates of a point on the disc, each in [0, 1]
+    // across its width with the centre at (0.5, 0.5)
+    pub fn uv(&self, point: Vec3) -> (f64, f64) {
+        let (tangent, bitangent) = self.basis();
+        let offset = (point - self.centre) / self.radius;
+        (0.5 + 0.5 * offset.dotprod(&tangent), 0.5 + 0.5 * offset.dotprod(&bitangent))
+    }
+
+    // the solid angle density seen from `from` of sample_point landing where
+    // the ray along dir meets the disc, zero when it misses
+    pub fn pdf(&self, from: Vec3, dir: Vec3) -> f64 {
+        let ray = Ray::new(from, dir);
+        let Some(distance) = self.intersect(&ray) else {return 0.0};
+        let cosine = self.normal.normalize().dotprod(&ray.dir).abs();
+        solid_angle_pdf(distance * distance, cosine, self.area())
+    }
+
+    // uniformly distributed over the area of the disc, or of the ring, the
+    // squared distance from the centre being uniform between the radii
+    pub fn sample_point<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec3 {
+        let hole2 = (self.inner_radius / self.radius).powi(2);
+        let distance = self.radius * (hole2 + (1.0 - hole2) * rng.gen::<f64>()).sqrt();
+        let angle = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        let (tangent, bitangent) = self.basis();
+        self.centre + (distance * angle.cos()) * tangent + (distance * angle.sin()) * bitangent
+    }
+}
+
+impl SphereSection {
+    pub fn new(sphere: Sphere, theta: Interval, phi: Interval) -> Self {
+        Self {sphere, theta, phi}
+    }
+
+    // the nearer crossing may be cut away while the farther is kept, as
+    // when looking into a bowl
+    pub fn intersect_beyond(&self, ray: &Ray, t_min: f64) -> Option<f64> {
+        let (t_smaller, t_larger) = self.sphere.roots(ray)?;
+        [t_smaller, t_larger].into_iter()
+            .find(|&t| t > t_min && self.contains(ray.position_at(t)))
+    }
+
+    fn contains(&self, surface_pos: Vec3) -> bool {
+        let local = (surface_pos - self.sphere.centre).normalize();
+        let theta = local[1].clamp(-1.0, 1.0).acos();
+        let phi = local[2].atan2(local[0]).rem_euclid(2.0 * std::f64::consts::PI);
+        self.theta.contains(theta) && self.phi.contains(phi)
+    }
+}
+
+impl MovingSphere {
+    pub fn new(centre0: Vec3, centre1: Vec3, radius: f64) -> Self {
+        Self {centre0, centre1, radius}
+    }
+
+    pub fn centre_at(&self, time: f64) -> Vec3 {
+        lerp_vec3(self.centre0, self.centre1, time)
+    }
+
+    // covers the sphere over the whole of the [0, 1] shutter interval
+    pub fn bounding_box(&self) -> BoundBox {
+        let start = BoundBox::surround(&Shape::Sphere(Sphere::new(self.centre0, self.radius)));
+        let end = BoundBox::surround(&Shape::Sphere(Sphere::new(self.centre1, self.radius)));
+        cover(&start, &end)
+    }
+}
+
+impl Quad {
+    pub fn new(corner: Vec3, edge_u: Vec3, edge_v: Vec3) -> Self {
+        Self {corner, edge_u, edge_v}
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Option<f64> {
+        self.intersect_beyond(ray, DEFAULT_EPSILON)
+    }
+
+    pub fn intersect_beyond(&self, ray: &Ray, t_min: f64) -> Option<f64> {
+        if is_degenerate(self.edge_u, self.edge_v) {return None};
+        let area_normal = self.edge_u.cross(&self.edge_v); // not normalized, its length is the area
+        let facing = area_normal.dotprod(&ray.dir);
+        if facing.abs() < 1.0e-9 * area_normal.norm() {return None}; // as for discs
+        let h: f64 = (self.corner - ray.orig).dotprod(&area_normal)/facing;
+        if h <= t_min {return None};
+        let (alpha, beta) = self.edge_coords(ray.position_at(h));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {return None};
+        Some(h)
+    }
+
+    pub fn normal_at(&self, _surface_pos: Vec3) -> Vec3 {
+        self.edge_u.cross(&self.edge_v).normalize()
+    }
+
+    // the coordinates of a point in the plane along each edge, both in [0, 1] inside the quad
+    pub fn edge_coords(&self, point: Vec3) -> (f64, f64) {
+        let area_normal = self.edge_u.cross(&self.edge_v);
+        let offset: Vec3 = point - self.corner;
+        let w = area_normal / area_normal.dotprod(&area_normal);
+        (w.dotprod(&offset.cross(&self.edge_v)), w.dotprod(&self.edge_u.cross(&offset)))
+    }
+
+    // the tangent follows edge_u, the edges themselves need not be perpendicular
+    pub fn basis(&self) -> (Vec3, Vec3) {
+        let tangent = self.edge_u.normalize();
+        let bitangent = self.normal_at(self.corner).cross(&tangent);
+        (tangent, bitangent)
+    }
+
+    // uniformly distributed over the area of the quad
+    pub fn sample_point<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec3 {
+        let (u, v): (f64, f64) = rng.gen();
+        self.corner + u * self.edge_u + v * self.edge_v
+    }
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3) -> Self {
+        Self {vertices: [a, b, c]}
+    }
+
+    // not normalized, its length is twice the area
+    fn area_normal(&self) -> Vec3 {
+        let [a, b, c] = self.vertices;
+        (b - a).cross(&(c - a))
+    }
+
+    // the weights (beta, gamma) of the second and third vertices making up
+    // a point in the plane, the first vertex has weight 1 - beta - gamma
+    pub fn barycentrics(&self, point: Vec3) -> (f64, f64) {
+        let [a, b, c] = self.vertices;
+        let area_normal = self.area_normal();
+        let w = area_normal / area_normal.dotprod(&area_normal);
+        let offset = point - a;
+        (w.dotprod(&offset.cross(&(c - a))), w.dotprod(&(b - a).cross(&offset)))
+    }
+
+    // the plane is hit as for quads and then the hit tested against the edges
+    pub fn intersect_beyond(&self, ray: &Ray, t_min: f64) -> Option<f64> {
+        let [a, b, c] = self.vertices;
+        if is_degenerate(b - a, c - a) {return None}; // as tessellated meshes leave
+        let area_normal = self.area_normal();
+        let facing = area_normal.dotprod(&ray.dir);
+        if facing.abs() < 1.0e-9 * area_normal.norm() {return None};
+        let h: f64 = (self.vertices[0] - ray.orig).dotprod(&area_normal)/facing;
+        if h <= t_min {return None};
+        let (beta, gamma) = self.barycentrics(ray.position_at(h));
+        if beta < 0.0 || gamma < 0.0 || beta + gamma > 1.0 {return None};
+        Some(h)
+    }
+
+    pub fn normal_at(&self, _surface_pos: Vec3) -> Vec3 {
+        self.area_normal().normalize()
+    }
+
+    // the tangent follows the first edge
+    pub fn basis(&self) -> (Vec3, Vec3) {
+        let tangent = (self.vertices[1] - self.vertices[0]).normalize();
+        let bitangent = self.normal_at(self.vertices[0]).cross(&tangent);
+        (tangent, bitangent)
+    }
+
+    // uniformly distributed over the area, folding the far half of the
+    // parallelogram on the first two edges back onto the triangle
+    pub fn sample_point<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec3 {
+        let [a, b, c] = self.vertices;
+        let (mut u, mut v): (f64, f64) = rng.gen();
+        if u + v > 1.0 {
+            (u, v) = (1.0 - u, 1.0 - v);
+        }
+        a + u * (b - a) + v * (c - a)
+    }
 }
 
 #[cfg(test)]
@@ -173,11 +938,452 @@ mod tests {
         assert_eq!(sph.intersect(&ray), Option::None);
     }
 
+    #[test]
+    fn distant_ground_sphere_test() {
+        let ground = Sphere::new(Vec3([0.0, -1000.0, 0.0]), 1000.0);
+        let target = Vec3([30.0, (1000.0_f64 * 1000.0 - 30.0 * 30.0 - 40.0 * 40.0).sqrt() - 1000.0, -40.0]);
+        let dir = Vec3([0.0, -0.1, 1.0]).normalize();
+        let ray = Ray::new(target - 2.0e4 * dir, dir);
+
+        let hit = ray.position_at(ground.intersect(&ray).unwrap());
+        assert!((hit - target).norm() < 1.0e-9);
+        assert!(((hit - ground.centre).norm() - 1000.0).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn inside_sphere_test() {
+        let sph = Sphere::new(Vec3([1.0,2.0,3.0]), 2.0);
+        let from_centre = Ray::new(sph.centre, Vec3([1.0,1.0,0.0]));
+        assert!((sph.intersect(&from_centre).unwrap() - 2.0).abs() < 1.0e-12);
+
+        // reflecting around inside, each bounce must find the far wall rather than its own start
+        let mut ray = Ray::new(sph.centre + Vec3([0.5,0.0,0.0]), Vec3([0.3,1.0,0.2]));
+        for _ in 0..1000 {
+            let t = sph.intersect(&ray).expect("an inside ray always exits");
+            assert!(t > 1.0e-3);
+            let hit = ray.position_at(t);
+            assert!(((hit - sph.centre).norm() - 2.0).abs() < 1.0e-9);
+            let normal = sph.normal_at(hit);
+            ray = Ray::new(hit, ray.dir - 2.0 * normal.dotprod(&ray.dir) * normal);
+        }
+    }
+
+    #[test]
+    fn negative_radius_intersect_test() {
+        let sph = Sphere::new(Vec3([0.0,0.0,0.0]), -2.0);
+        let outside = Ray::new(Vec3([0.0,0.0,-3.0]), Vec3([0.0,0.0,1.0]));
+        assert_eq!(sph.intersect(&outside), Some(1.0));
+
+        let inside = Ray::new(Vec3([0.0,0.0,0.0]), Vec3([0.0,0.0,1.0]));
+        assert_eq!(sph.intersect(&inside), Some(2.0));
+        assert_eq!(sph.normal_at(Vec3([0.0,0.0,2.0])), Vec3([0.0,0.0,-1.0]));
+    }
+
     #[test]
     fn disc_intersection_test() {
         let disc = Disc::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 0.0, 1.0]), 2.0);
         let ray = Ray::new(Vec3([1.0,0.0,3.0]), Vec3([0.0, 0.0, -1.0]));
         assert_eq!(ray.position_at(disc.intersect(&ray).unwrap()), Vec3([1.0, 0.0, 0.0]));
+        assert_eq!(disc.intersect_beyond(&ray, 3.0), None);
+        assert_eq!(Shape::Disc(disc).intersect_all(&ray, DEFAULT_EPSILON), vec![3.0]);
+    }
+
+    #[test]
+    fn disc_both_sides_test() {
+        let disc = Disc::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 0.0, 1.0]), 2.0);
+        let from_front = Ray::new(Vec3([1.0, 0.0, 3.0]), Vec3([0.0, 0.0, -1.0]));
+        let from_back = Ray::new(Vec3([1.0, 0.0, -3.0]), Vec3([0.0, 0.0, 1.0]));
+        assert_eq!(disc.intersect_side(&from_front, DEFAULT_EPSILON), Some((3.0, true)));
+        assert_eq!(disc.intersect_side(&from_back, DEFAULT_EPSILON), Some((3.0, false)));
+
+        // the normals the shading gets face back along each ray
+        let shape = Shape::Disc(disc);
+        let hit = Vec3([1.0, 0.0, 0.0]);
+        let (front_normal, front) = shape.oriented_normal_at(hit, from_front.dir);
+        let (back_normal, back) = shape.oriented_normal_at(hit, from_back.dir);
+        assert_eq!((front, back), (true, false));
+        assert_eq!(front_normal, -1.0 * back_normal);
+        assert!(front_normal.dotprod(&from_front.dir) < 0.0 && back_normal.dotprod(&from_back.dir) < 0.0);
+    }
+
+    #[test]
+    fn cylinder_test() {
+        let cylinder = Cylinder::new(Vec3([0.0, 1.0, 0.0]), Vec3([0.0, 2.0, 0.0]), 0.5, 2.0);
+        // into the side, square to the axis
+        let side = Ray::new(Vec3([-3.0, 2.0, 0.0]), Vec3([1.0, 0.0, 0.0]));
+        assert_eq!(cylinder.intersect(&side), Some(2.5));
+        assert_eq!(cylinder.normal_at(side.position_at(2.5)), Vec3([-1.0, 0.0, 0.0]));
+        // above and below the height band the side is missed
+        let over = Ray::new(Vec3([-3.0, 3.5, 0.0]), Vec3([1.0, 0.0, 0.0]));
+        assert_eq!(cylinder.intersect(&over), None);
+
+        // down onto the top cap and up into the bottom one
+        let down = Ray::new(Vec3([0.2, 5.0, 0.1]), Vec3([0.0, -1.0, 0.0]));
+        assert_eq!(cylinder.intersect(&down), Some(2.0));
+        assert_eq!(cylinder.normal_at(down.position_at(2.0)), Vec3([0.0, 1.0, 0.0]));
+        let up = Ray::new(Vec3([0.2, -1.0, 0.1]), Vec3([0.0, 1.0, 0.0]));
+        assert_eq!(cylinder.intersect(&up), Some(2.0));
+        assert_eq!(cylinder.normal_at(up.position_at(2.0)), Vec3([0.0, -1.0, 0.0]));
+
+        // from inside, out through the far wall and along the axis to a cap
+        let across = Ray::new(Vec3([0.0, 2.0, 0.0]), Vec3([0.0, 0.0, 1.0]));
+        assert_eq!(cylinder.intersect(&across), Some(0.5));
+        assert_eq!(cylinder.normal_at(across.position_at(0.5)), Vec3([0.0, 0.0, 1.0]));
+        let along = Ray::new(Vec3([0.0, 2.0, 0.0]), Vec3([0.0, 1.0, 0.0]));
+        assert_eq!(cylinder.intersect(&along), Some(1.0));
+        // and in one side and out the other, and in the top and out the side
+        let shape = Shape::Cylinder(cylinder);
+        assert_eq!(shape.intersect_all(&side, DEFAULT_EPSILON), vec![2.5, 3.5]);
+        let slant = Ray::new(Vec3([0.0, 4.0, 0.0]), Vec3([0.2, -1.0, 0.0]));
+        let hits = shape.intersect_all(&slant, DEFAULT_EPSILON);
+        assert_eq!(hits.len(), 2);
+        let (entry, exit) = (slant.position_at(hits[0]), slant.position_at(hits[1]));
+        assert!((entry[1] - 3.0).abs() < 1.0e-12 && (exit[0] - 0.5).abs() < 1.0e-12);
+        assert_eq!(shape.normal_at(slant.position_at(hits[1])), Vec3([1.0, 0.0, 0.0]));
+        assert_eq!(shape.local_coords(Vec3([0.5, 2.0, 0.0])), Vec3([0.5, 1.0, 0.0]));
+
+        let bbox = BoundBox::surround(&shape);
+        assert_eq!((bbox[0], bbox[1]), (Interval::new(-0.5, 0.5), Interval::new(1.0, 3.0)));
+    }
+
+    #[test]
+    fn cuboid_faces_test() {
+        let cuboid = Cuboid::new([Interval::new(0.0, 1.0), Interval::new(0.0, 2.0), Interval::new(-1.0, 1.0)]);
+        let centre = Vec3([0.5, 1.0, 0.0]);
+        // a ray in towards the centre of each face from outside it
+        for (face_centre, normal) in [
+            ([1.0, 1.0, 0.0], [1.0, 0.0, 0.0]), ([0.0, 1.0, 0.0], [-1.0, 0.0, 0.0]),
+            ([0.5, 2.0, 0.0], [0.0, 1.0, 0.0]), ([0.5, 0.0, 0.0], [0.0, -1.0, 0.0]),
+            ([0.5, 1.0, 1.0], [0.0, 0.0, 1.0]), ([0.5, 1.0, -1.0], [0.0, 0.0, -1.0]),
+        ] {
+            let (face_centre, normal) = (Vec3(face_centre), Vec3(normal));
+            let ray = Ray::new(face_centre + 3.0 * normal, -1.0 * normal);
+            let param = cuboid.intersect(&ray).expect("the ray should hit the face");
+            assert!((param - 3.0).abs() < 1.0e-12);
+            assert_eq!(cuboid.normal_at(ray.position_at(param)), normal);
+            // and from inside, the way out through the same face
+            let outward = Ray::new(centre, face_centre - centre);
+            assert_eq!(cuboid.normal_at(outward.position_at(cuboid.intersect(&outward).unwrap())), normal);
+        }
+        assert_eq!(cuboid.intersect(&Ray::new(Vec3([2.0, 3.0, 0.0]), Vec3([0.0, 0.0, 1.0]))), None);
+    }
+
+    #[test]
+    fn plane_test() {
+        let plane = Plane::new(Vec3([0.0, -1.0, 0.0]), Vec3([0.0, 2.0, 0.0]));
+        // straight down and from far off at a slant, from either side
+        let down = Ray::new(Vec3([3.0, 1.0, 0.0]), Vec3([0.0, -1.0, 0.0]));
+        assert_eq!(plane.intersect(&down), Some(2.0));
+        let far = Ray::new(Vec3([1.0e4, 1.0, 0.0]), Vec3([1.0, -1.0, 0.0]));
+        assert!((far.position_at(plane.intersect(&far).unwrap())[1] + 1.0).abs() < 1.0e-9);
+        let up = Ray::new(Vec3([0.0, -3.0, 0.0]), Vec3([0.0, 1.0, 0.0]));
+        assert_eq!(plane.intersect(&up), Some(2.0));
+        // behind, parallel to it, and leaving from on it
+        assert_eq!(plane.intersect(&Ray::new(Vec3([0.0, 1.0, 0.0]), Vec3([0.0, 1.0, 0.0]))), None);
+        assert_eq!(plane.intersect(&Ray::new(Vec3([0.0, 1.0, 0.0]), Vec3([1.0, 0.0, 0.0]))), None);
+        assert_eq!(plane.intersect(&Ray::new(Vec3([0.0, -1.0, 0.0]), Vec3([0.0, 1.0, 0.0]))), None);
+        assert_eq!(plane.normal_at(Vec3([5.0, -1.0, 5.0])), Vec3([0.0, 1.0, 0.0]));
+
+        let shape: Shape = serde_json::from_str(r#"{"Plane": {"point": [0.0, 0.0, 0.0], "normal": [0.0, 1.0, 0.0]}}"#)
+            .expect("plane should deserialize");
+        assert_eq!(shape.intersect(&down), Some(1.0));
+        assert_eq!(shape.intersect_all(&down, DEFAULT_EPSILON), vec![1.0]);
+    }
+
+    #[test]
+    fn disc_sample_point_test() {
+        let disc = Disc::new(Vec3([1.0, 2.0, 3.0]), Vec3([1.0, 1.0, 0.0]), 0.5);
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            let offset = disc.sample_point(&mut rng) - disc.centre;
+            assert!(offset.norm() <= disc.radius + 1.0e-12);
+            assert!(offset.dotprod(&disc.normal).abs() < 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn oriented_normal_test() {
+        let sphere = Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), 2.0));
+        let top = Vec3([0.0, 2.0, 0.0]);
+        let down = Vec3([0.0, -1.0, 0.0]);
+        // from above the ray meets the outside, from the centre the inside
+        assert_eq!(sphere.oriented_normal_at(top, down), (Vec3([0.0, 1.0, 0.0]), true));
+        assert_eq!(sphere.oriented_normal_at(top, -1.0 * down), (Vec3([0.0, -1.0, 0.0]), false));
+
+        // a negative radius turns the outside in
+        let inward = Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), -2.0));
+        assert_eq!(inward.oriented_normal_at(top, -1.0 * down), (Vec3([0.0, -1.0, 0.0]), true));
+    }
+
+    #[test]
+    fn annulus_intersection_test() {
+        let ring = Disc::annulus(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 1.0, 0.0]), 2.0, 1.0);
+        let down = Vec3([0.0, -1.0, 0.0]);
+        assert_eq!(ring.intersect(&Ray::new(Vec3([0.0, 1.0, 0.0]), down)), None);
+        assert_eq!(ring.intersect(&Ray::new(Vec3([0.5, 1.0, 0.0]), down)), None);
+        assert_eq!(ring.intersect(&Ray::new(Vec3([1.5, 1.0, 0.0]), down)), Some(1.0));
+        assert_eq!(ring.intersect(&Ray::new(Vec3([2.5, 1.0, 0.0]), down)), None);
+
+        assert!((ring.area() - 3.0 * std::f64::consts::PI).abs() < 1.0e-12);
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            let distance = (ring.sample_point(&mut rng) - ring.centre).norm();
+            assert!((1.0 - 1.0e-12..=2.0 + 1.0e-12).contains(&distance));
+        }
+        // a thin ring is sampled as quickly, spread evenly over its area so
+        // the squared distances average halfway between those of the radii
+        let thin = Disc::annulus(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 1.0, 0.0]), 2.0, 1.999);
+        let samples = 10000;
+        let mean2 = (0..samples).map(|_| thin.sample_point(&mut rng).norm_squared()).sum::<f64>()
+            / samples as f64;
+        assert!((mean2 - 0.5 * (4.0 + 1.999 * 1.999)).abs() < 1.0e-3);
+        let shape = Shape::Disc(thin);
+        assert!(shape.sample_surface(&mut rng).is_some());
+        assert!(shape.area().unwrap() > 0.0);
+
+        // no hole, or one as wide as the disc or wider, or of negative radius
+        assert!(Disc::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 1.0, 0.0]), 2.0).check_ring().is_ok());
+        for inner_radius in [2.0, 3.0, -1.0, f64::NAN] {
+            let ring = Disc {inner_radius, ..Disc::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 1.0, 0.0]), 2.0)};
+            assert!(ring.check_ring().is_err());
+        }
+
+        // an inner radius left out of a scene file is a whole disc
+        let disc: Disc = serde_json::from_str(
+            r#"{"centre": [0.0, 0.0, 0.0], "normal": [0.0, 1.0, 0.0], "radius": 2.0}"#).unwrap();
+        assert_eq!(disc.inner_radius, 0.0);
+    }
+
+    #[test]
+    fn disc_pdf_test() {
+        let disc = Disc::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 1.0, 0.0]), 1.0);
+        let down = Vec3([0.0, -1.0, 0.0]);
+        let far = disc.pdf(Vec3([0.0, 2.0, 0.0]), down);
+        assert!((far - 4.0 / std::f64::consts::PI).abs() < 1.0e-12);
+        // closer the disc covers more of the view, spreading the same samples
+        // over a wider solid angle so the density per steradian is lower
+        assert!(disc.pdf(Vec3([0.0, 1.0, 0.0]), down) < far);
+        // while seen obliquely from as far it covers less and the density rises
+        let oblique = Vec3([0.0, 2.0, 0.0]).rotate_axis_angle(Vec3([0.0, 0.0, 1.0]), 1.0);
+        assert!(disc.pdf(oblique, -1.0 * oblique) > far);
+        // but exactly edge on, or pointing away, the ray never meets it
+        assert_eq!(disc.pdf(Vec3([-2.0, 0.0, 0.0]), Vec3([1.0, 0.0, 0.0])), 0.0);
+        assert_eq!(disc.pdf(Vec3([0.0, 2.0, 0.0]), -1.0 * down), 0.0);
+        assert_eq!(disc.pdf(Vec3([3.0, 2.0, 0.0]), down), 0.0);
+    }
+
+    #[test]
+    fn quad_light_pdf_test() {
+        let quad = Quad::new(Vec3([0.0, 0.0, 0.0]), Vec3([2.0, 0.0, 0.0]), Vec3([0.0, 0.0, 1.0]));
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            let offset = quad.sample_point(&mut rng);
+            assert!((0.0..=2.0).contains(&offset[0]) && (0.0..=1.0).contains(&offset[2]));
+            assert_eq!(offset[1], 0.0);
+        }
+
+        let shape = Shape::Quad(quad);
+        assert_eq!(shape.area(), Some(2.0));
+        // straight above at distance 2 the density is 2^2 / (1 * 2)
+        assert_eq!(shape.pdf(Vec3([1.0, 2.0, 0.5]), Vec3([1.0, 0.0, 0.5])), 2.0);
+        let sphere = Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0));
+        assert_eq!(sphere.pdf(Vec3([0.0, 2.0, 0.0]), Vec3([0.0, 1.0, 0.0])), 0.0);
+    }
+
+    fn assert_orthonormal(normal: Vec3, (tangent, bitangent): (Vec3, Vec3)) {
+        assert!((tangent.norm() - 1.0).abs() < 1.0e-12);
+        assert!((bitangent.norm() - 1.0).abs() < 1.0e-12);
+        assert!(tangent.dotprod(&bitangent).abs() < 1.0e-12);
+        assert!(tangent.dotprod(&normal).abs() < 1.0e-12);
+        assert!(bitangent.dotprod(&normal).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn tangent_frame_test() {
+        let sphere = Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), 2.0));
+        for pos in [Vec3([2.0, 0.0, 0.0]), Vec3([0.0, 2.0, 0.0]), Vec3([1.0, 1.0, 2.0_f64.sqrt()])] {
+            let normal = sphere.normal_at(pos);
+            assert_orthonormal(normal, sphere.tangent_frame(pos, normal));
+        }
+
+        let disc = Shape::Disc(Disc::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 1.0, 1.0]), 1.0));
+        let normal = disc.normal_at(Vec3([0.0, 0.0, 0.0]));
+        assert_orthonormal(normal, disc.tangent_frame(Vec3([0.0, 0.0, 0.0]), normal));
+    }
+
+    #[test]
+    fn disc_grazing_ray_test() {
+        let disc = Disc::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 0.0, 1.0]), 2.0);
+        let grazing = Ray::new(Vec3([-3.0, 0.0, 1.0e-12]), Vec3([1.0, 0.0, -1.0e-13]));
+        assert_eq!(disc.intersect(&grazing), None);
+    }
+
+    #[test]
+    fn disc_self_origin_test() {
+        let disc = Disc::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 0.0, 1.0]), 2.0);
+        let scattered = Ray::new(Vec3([0.5, 0.0, 0.0]), Vec3([0.0, 1.0, 1.0]));
+        assert_eq!(disc.intersect(&scattered), None);
+
+        let behind = Ray::new(Vec3([0.5, 0.0, 1.0]), Vec3([0.0, 0.0, 1.0]));
+        assert_eq!(disc.intersect(&behind), None);
+    }
+
+    #[test]
+    fn test_surround_disc() {
+        let flat = BoundBox::surround(&Shape::Disc(Disc::new(Vec3([1.0, 2.0, 3.0]), Vec3([0.0, 0.0, 1.0]), 2.0)));
+        assert_eq!(flat[0], Interval::new(-1.0, 3.0));
+        assert_eq!(flat[1], Interval::new(0.0, 4.0));
+        assert_eq!(flat[2], Interval::new(3.0, 3.0));
+
+        let tilted = BoundBox::surround(&Shape::Disc(Disc::new(Vec3([0.0, 0.0, 0.0]), Vec3([1.0, 1.0, 0.0]), 1.0)));
+        let half_diagonal = 0.5_f64.sqrt();
+        assert!((tilted[0].end - half_diagonal).abs() < 1.0e-12);
+        assert!((tilted[1].end - half_diagonal).abs() < 1.0e-12);
+        assert!((tilted[2].end - 1.0).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn quad_intersection_test() {
+        let quad = Quad::new(Vec3([0.0, 0.0, 0.0]), Vec3([2.0, 0.0, 0.0]), Vec3([0.0, 1.0, 0.0]));
+        assert_eq!(quad.normal_at(quad.corner), Vec3([0.0, 0.0, 1.0]));
+
+        let inside = Ray::new(Vec3([1.5, 0.5, 3.0]), Vec3([0.0, 0.0, -1.0]));
+        assert_eq!(ray_hit(&quad, &inside), Some(Vec3([1.5, 0.5, 0.0])));
+
+        let outside = Ray::new(Vec3([1.5, 1.5, 3.0]), Vec3([0.0, 0.0, -1.0]));
+        assert_eq!(quad.intersect(&outside), None);
+
+        let grazing = Ray::new(Vec3([-1.0, 0.5, 1.0e-12]), Vec3([1.0, 0.0, 0.0]));
+        assert_eq!(quad.intersect(&grazing), None);
+
+        // a flat shape is hit once, not again from the hit itself
+        assert_eq!(Shape::Quad(quad).intersect_all(&inside, DEFAULT_EPSILON), vec![3.0]);
+    }
+
+    #[test]
+    fn disc_tangent_test() {
+        // the tangent is made square to the normal before it is used
+        let disc = Disc::new(Vec3([1.0, 2.0, 3.0]), Vec3([0.0, 1.0, 0.0]), 2.0)
+            .with_tangent(Vec3([1.0, 1.0, 0.0]));
+        let (tangent, bitangent) = disc.basis();
+        assert_eq!(tangent, Vec3([1.0, 0.0, 0.0]));
+        assert_eq!(bitangent, Vec3([0.0, 0.0, -1.0]));
+
+        assert_eq!(disc.uv(Vec3([1.0, 2.0, 3.0])), (0.5, 0.5));
+        assert_eq!(disc.uv(Vec3([2.0, 2.0, 3.0])), (0.75, 0.5));
+        assert_eq!(disc.uv(Vec3([-1.0, 2.0, 3.0])), (0.0, 0.5));
+
+        let parsed: Disc = serde_json::from_str(
+            r#"{"centre": [0.0, 0.0, 0.0], "normal": [0.0, 0.0, 1.0], "radius": 1.0, "tangent": [0.0, 1.0, 0.0]}"#,
+        ).expect("disc should deserialize");
+        assert_eq!(parsed.uv(Vec3([0.0, 0.5, 0.0])).0, 0.75);
+    }
+
+    #[test]
+    fn triangle_intersection_test() {
+        let triangle = Triangle::new(Vec3([0.0, 0.0, 0.0]), Vec3([2.0, 0.0, 0.0]), Vec3([0.0, 2.0, 0.0]));
+        assert_eq!(triangle.normal_at(Vec3([0.5, 0.5, 0.0])), Vec3([0.0, 0.0, 1.0]));
+
+        let inside = Ray::new(Vec3([0.5, 0.5, 3.0]), Vec3([0.0, 0.0, -1.0]));
+        assert_eq!(triangle.intersect_beyond(&inside, DEFAULT_EPSILON), Some(3.0));
+        // inside the quad on the same edges but past the hypotenuse
+        let beyond = Ray::new(Vec3([1.5, 1.5, 3.0]), Vec3([0.0, 0.0, -1.0]));
+        assert_eq!(triangle.intersect_beyond(&beyond, DEFAULT_EPSILON), None);
+
+        let shape = Shape::Triangle(triangle);
+        assert_eq!(shape.area(), Some(2.0));
+        assert_eq!(shape.edge_distance(Vec3([0.5, 0.5, 0.0])), Some(0.25));
+        let bbox = BoundBox::surround(&shape);
+        assert_eq!(bbox[0], Interval::new(0.0, 2.0));
+        assert_eq!(bbox[2], Interval::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn degenerate_test() {
+        let down = Vec3([0.0, 0.0, -1.0]);
+        // straight down onto the line, and along it
+        let rays = [Ray::new(Vec3([0.5, 0.5, 3.0]), down),
+                    Ray::new(Vec3([-1.0, -1.0, 0.0]), Vec3([1.0, 1.0, 0.0]))];
+        let collinear = Triangle::new(Vec3([0.0, 0.0, 0.0]), Vec3([1.0, 1.0, 0.0]), Vec3([2.0, 2.0, 0.0]));
+        let collapsed = Triangle::new(Vec3([0.5, 0.5, 0.0]), Vec3([0.5, 0.5, 0.0]), Vec3([0.5, 0.5, 0.0]));
+        let flat_quad = Quad::new(Vec3([0.0, 0.0, 0.0]), Vec3([1.0, 1.0, 0.0]), Vec3([2.0, 2.0, 0.0]));
+        for ray in &rays {
+            assert_eq!(collinear.intersect_beyond(ray, DEFAULT_EPSILON), None);
+            assert_eq!(collapsed.intersect_beyond(ray, DEFAULT_EPSILON), None);
+            assert_eq!(flat_quad.intersect_beyond(ray, DEFAULT_EPSILON), None);
+        }
+        assert_eq!(Shape::Triangle(collinear).area(), Some(0.0));
+
+        // a thin sliver is still hit
+        let sliver = Triangle::new(Vec3([0.0, 0.0, 0.0]), Vec3([2.0, 0.0, 0.0]), Vec3([0.0, 1.0e-6, 0.0]));
+        let onto = Ray::new(Vec3([0.5, 1.0e-7, 3.0]), down);
+        assert_eq!(sliver.intersect_beyond(&onto, DEFAULT_EPSILON), Some(3.0));
+    }
+
+    #[test]
+    fn scaled_instance_intersect_all_test() {
+        let scale = 0.1;
+        let prototype = Arc::new(Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)));
+        let transform = Transform {translate: Vec3([0.0, 0.0, 0.0]), scale};
+        let instance = Shape::Instance(Instance::new(prototype, 0, transform));
+        let world = Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), scale));
+        // a ray on which the near hit taken to the world and back comes up short
+        let ray = Ray::new(Vec3([0.1 * scale, 0.2 * scale, -6.68 * scale]), Vec3([0.0, 0.0, 1.0]));
+        let hits = instance.intersect_all(&ray, DEFAULT_EPSILON);
+        let expected = world.intersect_all(&ray, DEFAULT_EPSILON);
+        assert_eq!(hits.len(), 2);
+        for (hit, expected) in hits.iter().zip(&expected) {
+            assert!((hit - expected).abs() < 1.0e-12, "{} against {}", hit, expected);
+        }
+    }
+
+    fn ray_hit(quad: &Quad, ray: &Ray) -> Option<Vec3> {
+        quad.intersect(ray).map(|h| ray.position_at(h))
+    }
+
+    #[test]
+    fn test_surround_quad() {
+        let quad = Quad::new(Vec3([1.0, 0.0, 0.0]), Vec3([0.0, 2.0, 0.0]), Vec3([-1.0, 0.0, 1.0]));
+        let bbox = BoundBox::surround(&Shape::Quad(quad));
+        assert_eq!(bbox[0], Interval::new(0.0, 1.0));
+        assert_eq!(bbox[1], Interval::new(0.0, 2.0));
+        assert_eq!(bbox[2], Interval::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn test_moving_sphere_bounding_box() {
+        let moving = MovingSphere::new(Vec3([0.0, 0.0, 0.0]), Vec3([4.0, 0.0, 0.0]), 0.5);
+        let bbox = moving.bounding_box();
+        assert_eq!(bbox[0], Interval::new(-0.5, 4.5));
+        assert_eq!(bbox[1], Interval::new(-0.5, 0.5));
+        assert_eq!(bbox[2], Interval::new(-0.5, 0.5));
+
+        let midway = BoundBox::surround(&Shape::Sphere(Sphere::new(moving.centre_at(0.5), 0.5)));
+        assert!(bbox[0].start <= midway[0].start && midway[0].end <= bbox[0].end);
+    }
+
+    #[test]
+    fn sphere_section_test() {
+        use std::f64::consts::PI;
+        let unit = || Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0);
+        let rays = [
+            Ray::new(Vec3([0.0, 0.0, -3.0]), Vec3([0.0, 0.0, 1.0])),
+            Ray::new(Vec3([0.3, -3.0, 0.2]), Vec3([0.0, 1.0, 0.0])),
+            Ray::new(Vec3([0.0, 0.0, 0.0]), Vec3([-1.0, -1.0, 0.5])),
+            Ray::new(Vec3([3.0, 3.0, 0.0]), Vec3([1.0, 0.0, 0.0])),
+        ];
+        let full = SphereSection::new(unit(), Interval::new(0.0, PI), Interval::new(0.0, 2.0 * PI));
+        for ray in &rays {
+            assert_eq!(full.intersect_beyond(ray, DEFAULT_EPSILON), unit().intersect(ray));
+        }
+
+        // the upper hemisphere, a ray from below passes the removed half to the inside of the dome
+        let dome = SphereSection::new(unit(), Interval::new(0.0, 0.5 * PI), Interval::new(0.0, 2.0 * PI));
+        let from_below = Ray::new(Vec3([0.0, -3.0, 0.0]), Vec3([0.0, 1.0, 0.0]));
+        assert_eq!(dome.intersect_beyond(&from_below, DEFAULT_EPSILON), Some(4.0));
+        let under = Ray::new(Vec3([-3.0, -0.5, 0.0]), Vec3([1.0, 0.0, 0.0]));
+        assert_eq!(dome.intersect_beyond(&under, DEFAULT_EPSILON), None);
+        assert!(unit().intersect(&under).is_some());
     }
 
     #[test]
@@ -209,4 +1415,35 @@ mod tests {
         assert_eq!(bbox[2].start, 1.0);
         assert_eq!(bbox[2].end, 5.0);
     }
+
+    #[test]
+    fn test_surround_negative_sphere() {
+        let sphere = Sphere::new(Vec3([1.0,2.0,3.0]),-2.0);
+
+        let bbox = BoundBox::surround(&Shape::Sphere(sphere));
+
+        assert_eq!(bbox[0].start, -1.0);
+        assert_eq!(bbox[0].end, 3.0);
+        assert_eq!(bbox[2].start, 1.0);
+        assert_eq!(bbox[2].end, 5.0);
+    }
+
+    #[test]
+    fn bound_volume_surface_test() {
+        let bbox = BoundBox::surround(&Shape::Sphere(Sphere::new(Vec3([0.0,0.0,0.0]),1.0)));
+
+        // from outside along -x the ray enters at the +x face
+        let ray = Ray::new(Vec3([3.0,0.5,0.0]), Vec3([-1.0,0.0,0.0]));
+        let param = bbox.intersect_beyond(&ray, DEFAULT_EPSILON).unwrap();
+        assert_eq!(param, 2.0);
+        assert_eq!(bbox.normal_at(ray.position_at(param)), Vec3([1.0,0.0,0.0]));
+        // and from inside it leaves through the -x face
+        let inner = Ray::new(Vec3([0.0,0.5,0.0]), Vec3([-1.0,0.0,0.0]));
+        assert_eq!(bbox.intersect_beyond(&inner, DEFAULT_EPSILON), Some(1.0));
+        let inner = Ray::new(Vec3([0.0,0.5,0.0]), Vec3([0.0,0.0,1.0]));
+        assert_eq!(bbox.intersect_beyond(&inner, DEFAULT_EPSILON), Some(1.0));
+
+        let shape = Shape::BoundVolume(bbox);
+        assert_eq!(shape.local_coords(Vec3([1.0,0.5,0.0])), Vec3([2.0,1.5,1.0]));
+    }
 }
@@ -0,0 +1,388 @@
+use serde::{Deserialize, Serialize};
+
+use crate::boundingvolume::CoveredScene;
+use crate::color::Color;
+use crate::lights::LightSampler;
+use crate::materials;
+use crate::ray::Ray;
+use crate::settings::RenderSettings;
+use crate::vector::Vec3;
+use crate::{is_occluded, nearest_hit, sampled_lights, trace_path, Hittable};
+
+// what the integrators need to ask of a scene, so that the linear and the
+// tree-accelerated ones are traced by the same code
+pub trait Scene {
+    fn nearest_hit(&self, ray: &Ray, t_min: f64) -> Option<(&Hittable, f64)>;
+
+    // the emitters light sampling picks from, empty unless it is enabled
+    fn sampled_lights(&self, settings: &RenderSettings) -> Vec<&Hittable>;
+
+    // as crate::is_occluded, stopping at the first hittable found in the way
+    fn is_occluded(&self, from: Vec3, to: Vec3, epsilon: f64, target: Option<&Hittable>) -> bool;
+}
+
+// a slice being unsized, it is the reference to one that is the scene
+impl Scene for &[Hittable] {
+    fn nearest_hit(&self, ray: &Ray, t_min: f64) -> Option<(&Hittable, f64)> {
+        nearest_hit(self, ray, t_min)
+    }
+
+    fn sampled_lights(&self, settings: &RenderSettings) -> Vec<&Hittable> {
+        sampled_lights(self.iter().enumerate(), settings)
+    }
+
+    fn is_occluded(&self, from: Vec3, to: Vec3, epsilon: f64, target: Option<&Hittable>) -> bool {
+        is_occluded(self, from, to, epsilon, target)
+    }
+}
+
+impl Scene for CoveredScene {
+    fn nearest_hit(&self, ray: &Ray, t_min: f64) -> Option<(&Hittable, f64)> {
+        CoveredScene::nearest_hit(self, ray, t_min)
+    }
+
+    fn sampled_lights(&self, settings: &RenderSettings) -> Vec<&Hittable> {
+        sampled_lights(self.hittables_with_sources(), settings)
+    }
+
+    fn is_occluded(&self, from: Vec3, to: Vec3, epsilon: f64, target: Option<&Hittable>) -> bool {
+        CoveredScene::is_occluded(self, from, to, epsilon, target)
+    }
+}
+
+// turns a camera ray into the color of its sample in the scene it traces
+pub trait Integrator {
+    // the color along with the alpha of the sample, 1 when the ray hit
+    // anything and 0 when it went straight out to the background
+    fn radiance_with_alpha(&self, ray: &Ray) -> (Color, f64);
+
+    fn radiance(&self, ray: &Ray) -> Color {
+        self.radiance_with_alpha(ray).0
+    }
+}
+
+// which integrator the render uses, as written in a scene file
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum IntegratorKind {
+    #[default]
+    PathTracer,
+    Normals,
+    AmbientOcclusion {
+        #[serde(default = "default_occlusion_samples")]
+        samples: u32,
+        // how far away something still shades the surface
+        #[serde(default = "default_occlusion_distance")]
+        distance: f64,
+    },
+    Depth {
+        // the distance that comes out black, nearer being brighter
+        max_depth: f64,
+    },
+}
+
+fn default_occlusion_samples() -> u32 {
+    16
+}
+
+fn default_occlusion_distance() -> f64 {
+    1.0
+}
+
+// the integrator settings.integrator names, tracing scene with the rest of
+// settings, and made once for all the rays of a render as whatever it
+// gathers from the scene up front is shared between them
+pub fn select<'a>(
+    settings: &'a RenderSettings,
+    scene: &'a (dyn Scene + Sync),
+) -> Box<dyn Integrator + Sync + 'a> {
+    match settings.integrator {
+        IntegratorKind::PathTracer => Box::new(PathTracer::new(settings, scene)),
+        IntegratorKind::Normals => Box::new(NormalVisualizer { settings, scene }),
+        IntegratorKind::AmbientOcclusion { samples, distance } => Box::new(AmbientOcclusion {
+            settings,
+            scene,
+            samples,
+            distance,
+        }),
+        IntegratorKind::Depth { max_depth } => Box::new(DepthMap {
+            settings,
+            scene,
+            max_depth,
+        }),
+    }
+}
+
+// the full light transport of trace_path
+pub struct PathTracer<'a> {
+    pub settings: &'a RenderSettings,
+    pub scene: &'a (dyn Scene + Sync),
+    // the emitters of the scene picked between by every ray
+    pub lights: LightSampler<'a>,
+}
+
+impl<'a> PathTracer<'a> {
+    pub fn new(settings: &'a RenderSettings, scene: &'a (dyn Scene + Sync)) -> PathTracer<'a> {
+        PathTracer {
+            settings,
+            scene,
+            lights: LightSampler::new(scene.sampled_lights(settings)),
+        }
+    }
+
+    // the light getting from `from` to `to` on light, all or none of it as
+    // glass blocks the shadow rays too, the light getting through it being
+    // left to the paths refracting through it, or to the photons
+    fn transmittance(&self, from: Vec3, to: Vec3, light: &Hittable) -> Color {
+        if self
+            .scene
+            .is_occluded(from, to, self.settings.epsilon, Some(light))
+        {
+            Color::new(0.0, 0.0, 0.0)
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+}
+
+impl Integrator for PathTracer<'_> {
+    fn radiance_with_alpha(&self, ray: &Ray) -> (Color, f64) {
+        trace_path(
+            ray,
+            self.settings,
+            &self.lights,
+            |ray| self.scene.nearest_hit(ray, self.settings.epsilon),
+            |from, to, light| self.transmittance(from, to, light),
+        )
+    }
+}
+
+// the outward normal at the first hit mapped from [-1, 1] onto [0, 1] in
+// each channel, black where the ray escaped
+pub struct NormalVisualizer<'a> {
+    pub settings: &'a RenderSettings,
+    pub scene: &'a (dyn Scene + Sync),
+}
+
+impl Integrator for NormalVisualizer<'_> {
+    fn radiance_with_alpha(&self, ray: &Ray) -> (Color, f64) {
+        let Some((hit_obj, param)) = self.scene.nearest_hit(ray, self.settings.epsilon) else {
+            return (Color::new(0.0, 0.0, 0.0), 0.0);
+        };
+        let normal = hit_obj.shape.normal_at(ray.position_at(param)).normalize();
+        let [r, g, b] = normal.0.map(|coord| 0.5 * (coord + 1.0));
+        (Color::new(r, g, b), 1.0)
+    }
+}
+
+// the fraction of rays scattered diffusely off the first hit that get
+// further than distance, white for an open surface darkening into the
+// creases, and white where the camera ray itself escaped
+pub struct AmbientOcclusion<'a> {
+    pub settings: &'a RenderSettings,
+    pub scene: &'a (dyn Scene + Sync),
+    pub samples: u32,
+    pub distance: f64,
+}
+
+impl Integrator for AmbientOcclusion<'_> {
+    fn radiance_with_alpha(&self, ray: &Ray) -> (Color, f64) {
+        let Some((hit_obj, param)) = self.scene.nearest_hit(ray, self.settings.epsilon) else {
+            return (Color::new(1.0, 1.0, 1.0), 0.0);
+        };
+        let position = ray.position_at(param);
+        let (facing_normal, _) = hit_obj.shape.oriented_normal_at(position, ray.dir);
+        let open = (0..self.samples)
+            .filter(|_| {
+                let probe = Ray::new(position, facing_normal + materials::random_vec3());
+                self.scene
+                    .nearest_hit(&probe, self.settings.epsilon)
+                    .is_none_or(|(_, param)| param > self.distance)
+            })
+            .count();
+        let shade = open as f64 / f64::from(self.samples.max(1));
+        (Color::new(shade, shade, shade), 1.0)
+    }
+}
+
+// the distance to the first hit, white up close fading to black at
+// max_depth, black where the ray escaped
+pub struct DepthMap<'a> {
+    pub settings: &'a RenderSettings,
+    pub scene: &'a (dyn Scene + Sync),
+    pub max_depth: f64,
+}
+
+impl Integrator for DepthMap<'_> {
+    fn radiance_with_alpha(&self, ray: &Ray) -> (Color, f64) {
+        let Some((_, param)) = self.scene.nearest_hit(ray, self.settings.epsilon) else {
+            return (Color::new(0.0, 0.0, 0.0), 0.0);
+        };
+        let shade = 1.0 - (param / self.max_depth).clamp(0.0, 1.0);
+        (Color::new(shade, shade, shade), 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Shape, Sphere};
+    use crate::materials::Material;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn integrators_test() {
+        let scene = [Hittable {
+            shape: Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Emitter {
+                albedo: Color::new(1.0, 0.5, 0.25),
+                intensity: 1.0,
+            },
+        }];
+        // straight at the side of the sphere facing -z, and past it
+        let hit = Ray::new(Vec3([0.0, 0.0, -4.0]), Vec3([0.0, 0.0, 1.0]));
+        let miss = Ray::new(Vec3([0.0, 2.0, -4.0]), Vec3([0.0, 0.0, 1.0]));
+        let color_of = |kind: IntegratorKind, ray: &Ray| {
+            let settings = RenderSettings {
+                integrator: kind,
+                ..Default::default()
+            };
+            let (color, alpha) = select(&settings, &&scene[..]).radiance_with_alpha(ray);
+            (color.r, color.g, color.b, alpha)
+        };
+
+        // the emitter seen head on
+        assert_eq!(
+            color_of(IntegratorKind::PathTracer, &hit),
+            (1.0, 0.5, 0.25, 1.0)
+        );
+        assert_eq!(
+            color_of(IntegratorKind::Normals, &hit),
+            (0.5, 0.5, 0.0, 1.0)
+        );
+        assert_eq!(
+            color_of(IntegratorKind::Normals, &miss),
+            (0.0, 0.0, 0.0, 0.0)
+        );
+        // nothing else in the scene to shade it
+        let occlusion = IntegratorKind::AmbientOcclusion {
+            samples: 8,
+            distance: 10.0,
+        };
+        assert_eq!(color_of(occlusion, &hit), (1.0, 1.0, 1.0, 1.0));
+        assert_eq!(color_of(occlusion, &miss).3, 0.0);
+        // three units from the camera out of six
+        let depth = IntegratorKind::Depth { max_depth: 6.0 };
+        assert_eq!(color_of(depth, &hit), (0.5, 0.5, 0.5, 1.0));
+        assert_eq!(color_of(depth, &miss), (0.0, 0.0, 0.0, 0.0));
+
+        // and the same through the covering tree
+        let covered = CoveredScene::new(vec![Hittable {
+            shape: Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(0.5, 0.5, 0.5).into(),
+            },
+        }]);
+        let settings = RenderSettings {
+            integrator: depth,
+            ..Default::default()
+        };
+        assert_eq!(select(&settings, &covered).radiance(&hit).r, 0.5);
+    }
+
+    // a scene counting how often its lights are gathered
+    struct CountingScene<'a> {
+        scene: &'a [Hittable],
+        gathered: std::sync::atomic::AtomicUsize,
+    }
+
+    impl Scene for CountingScene<'_> {
+        fn nearest_hit(&self, ray: &Ray, t_min: f64) -> Option<(&Hittable, f64)> {
+            self.scene.nearest_hit(ray, t_min)
+        }
+
+        fn sampled_lights(&self, settings: &RenderSettings) -> Vec<&Hittable> {
+            self.gathered
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            self.scene.sampled_lights(settings)
+        }
+
+        fn is_occluded(
+            &self,
+            from: Vec3,
+            to: Vec3,
+            epsilon: f64,
+            target: Option<&Hittable>,
+        ) -> bool {
+            self.scene.is_occluded(from, to, epsilon, target)
+        }
+    }
+
+    #[test]
+    fn lights_gathered_once_test() {
+        let hittables = [
+            Hittable {
+                shape: Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+                material: Material::Diffuse {
+                    albedo: Color::new(0.5, 0.5, 0.5).into(),
+                },
+            },
+            Hittable {
+                shape: Shape::Sphere(Sphere::new(Vec3([0.0, 3.0, 0.0]), 0.5)),
+                material: Material::Emitter {
+                    albedo: Color::new(1.0, 1.0, 1.0),
+                    intensity: 4.0,
+                },
+            },
+        ];
+        let scene = CountingScene {
+            scene: &hittables,
+            gathered: Default::default(),
+        };
+        let settings = RenderSettings {
+            light_sampling: true,
+            ..Default::default()
+        };
+        let integrator = select(&settings, &scene);
+        let ray = Ray::new(Vec3([0.0, 0.0, -4.0]), Vec3([0.0, 0.0, 1.0]));
+        for _ in 0..16 {
+            integrator.radiance(&ray);
+        }
+        assert_eq!(scene.gathered.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn shadow_rays_test() {
+        let sphere = |centre: Vec3, material: Material| Hittable {
+            shape: Shape::Sphere(Sphere::new(centre, 1.0)),
+            material,
+        };
+        let diffuse = || Material::Diffuse {
+            albedo: Color::new(0.5, 0.5, 0.5).into(),
+        };
+        let light = || Material::Emitter {
+            albedo: Color::new(1.0, 1.0, 1.0),
+            intensity: 4.0,
+        };
+        let hittables = [
+            sphere(Vec3([0.0, 0.0, 0.0]), diffuse()),
+            sphere(Vec3([0.0, 5.0, 0.0]), light()),
+        ];
+        let covered = CoveredScene::new(vec![
+            sphere(Vec3([0.0, 0.0, 0.0]), diffuse()),
+            sphere(Vec3([0.0, 5.0, 0.0]), light()),
+        ]);
+        let eps = RenderSettings::default().epsilon;
+        // from the top of the diffuse sphere to the far side of the light
+        let (from, to) = (Vec3([0.0, 1.0, 0.0]), Vec3([0.0, 6.0, 0.0]));
+        let scenes: [&dyn Scene; 2] = [&&hittables[..], &covered];
+        for scene in scenes {
+            let target = scene
+                .nearest_hit(&Ray::new(from, to - from), eps)
+                .unwrap()
+                .0;
+            assert!(scene.is_occluded(from, to, eps, None));
+            // the light itself doesn't shadow the point sampled on it
+            assert!(!scene.is_occluded(from, to, eps, Some(target)));
+        }
+    }
+}
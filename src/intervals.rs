@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, Copy)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Interval {
     pub start: f64,
     pub end: f64,
@@ -23,6 +25,11 @@ impl Interval {
         Interval { start, end }
     }
 
+    // whether value lies in the closed interval
+    pub fn contains(&self, value: f64) -> bool {
+        self.start <= value && value <= self.end
+    }
+
     pub fn size_partial_cmp(&self, other: &Interval) -> Option<std::cmp::Ordering> {
         self.size().partial_cmp(&other.size())
     }
@@ -1,25 +1,45 @@
 // pub mod boundingvolume;
+// logging comes first so its macros are visible in the other modules
+#[macro_use]
+pub mod logging;
+pub mod animation;
+pub mod background;
 pub mod boundingvolume;
 pub mod camera;
 pub mod color;
 pub mod config;
+pub mod film;
 pub mod geometry;
+pub mod integrator;
 #[macro_use]
 pub mod intervals;
+pub mod lights;
 pub mod materials;
+pub mod photons;
+pub mod progress;
 pub mod ray;
+pub mod sampler;
+pub mod scenecache;
 pub mod scenegen;
+pub mod settings;
+pub mod summary;
+pub mod tiles;
 pub mod vector;
 
-use std::fs::File;
+use std::f64::consts::PI;
 use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::time::Instant;
 
-use boundingvolume::{tree_filter, BoundingBox, CoveringTree};
-use color::Color;
+use boundingvolume::CoveredScene;
+use color::{Color, Gamma};
+use film::Film;
 use geometry::Shape;
+use lights::LightSampler;
 use materials::Material;
 use ray::Ray;
 use serde::{Deserialize, Serialize};
+use settings::RenderSettings;
 use vector::Vec3;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,164 +57,1870 @@ pub fn cmp_intersection(a: Option<f64>, b: Option<f64>) -> std::cmp::Ordering {
     }
 }
 
-pub fn raytrace(ray: &Ray, scene: &[Hittable], scatter_depth: u8) -> Color {
-    let mut color = Color::new(1.0, 1.0, 1.0);
+// where a ray first meets a surface, before any shading
+#[derive(Debug, Clone, Copy)]
+pub struct HitRecord {
+    pub param: f64,
+    pub position: Vec3,
+    // facing back along the ray, front_face is whether that is the side
+    // the shape's outward normal points out of
+    pub normal: Vec3,
+    pub front_face: bool,
+}
 
-    let mut ray = ray;
-    let mut scatter_ray: Ray;
-    for _ in 1..=scatter_depth {
-        if let Some((hit_obj, Some(param))) = scene
-            .iter()
-            .map(|hittable| (hittable, hittable.shape.intersect(ray)))
-            .min_by(|x, y| cmp_intersection(x.1, y.1))
-        {
-            let scatter_loc: Vec3 = ray.position_at(param);
-            if let Material::Emitter { albedo } = hit_obj.material {
-                let cosine: f64 = ray.dir.dotprod(&hit_obj.shape.normal_at(scatter_loc));
-                return albedo * cosine.abs();
-            };
-            scatter_ray = hit_obj.material.scatter(ray, &hit_obj.shape, scatter_loc);
-            let obj_relative_loc: Vec3;
-            match &hit_obj.shape {
-                Shape::Sphere(sphere) => {
-                    obj_relative_loc = (scatter_loc - sphere.centre).normalize()
-                }
-                Shape::Disc(disc) => obj_relative_loc = scatter_loc - disc.centre,
-                _ => todo!(),
-            }
-            color = color * hit_obj.material.albedo(&obj_relative_loc);
-            ray = &scatter_ray;
-        } else {
-            let t = 0.5 * (ray.dir[1] + 1.0);
-            let sky_color = (1.0 - t)
-                * Color {
-                    r: 1.0,
-                    g: 1.0,
-                    b: 1.0,
-                }
-                + t * Color {
-                    r: 0.5,
-                    g: 0.7,
-                    b: 1.0,
-                };
+impl HitRecord {
+    fn new(ray: &Ray, hittable: &Hittable, param: f64) -> HitRecord {
+        let position = ray.position_at(param);
+        let (normal, front_face) = hittable.shape.oriented_normal_at(position, ray.dir);
+        HitRecord {
+            param,
+            position,
+            normal,
+            front_face,
+        }
+    }
+}
 
-            return color * sky_color;
+// the first hit along the ray through the centre of pixel (i, j), with no
+// scattering, for tools that need to know what is under a pixel
+pub fn trace_primary<'a>(
+    cam: &camera::Camera,
+    scene: &'a [Hittable],
+    i: u32,
+    j: u32,
+) -> Option<(&'a Hittable, HitRecord)> {
+    let ray = primary_ray(cam, i, j);
+    nearest_hit(scene, &ray, geometry::DEFAULT_EPSILON)
+        .map(|(hittable, param)| (hittable, HitRecord::new(&ray, hittable, param)))
+}
+
+pub fn accel_trace_primary<'a>(
+    cam: &camera::Camera,
+    scene: &'a CoveredScene,
+    i: u32,
+    j: u32,
+) -> Option<(&'a Hittable, HitRecord)> {
+    let ray = primary_ray(cam, i, j);
+    scene
+        .nearest_hit(&ray, geometry::DEFAULT_EPSILON)
+        .map(|(hittable, param)| (hittable, HitRecord::new(&ray, hittable, param)))
+}
+
+// the ray through the centre of pixel (i, j) from the centre of the lens
+fn primary_ray(cam: &camera::Camera, i: u32, j: u32) -> Ray {
+    Ray::new(cam.lookfrom, cam.get_pixel_centre(i, j) - cam.lookfrom)
+        .with_spread(cam.pixel_spread())
+}
+
+// the scatter depth is spent in quarters so that specular and transmissive
+// bounces are cheaper than diffuse ones, keeping a diffuse-only path at
+// exactly scatter_depth bounces while glass chains can go up to 4x deeper
+const DEPTH_QUARTERS: u32 = 4;
+
+pub struct DepthBudget {
+    remaining: u32,
+}
+
+impl DepthBudget {
+    pub fn new(scatter_depth: u8) -> DepthBudget {
+        DepthBudget {
+            remaining: scatter_depth as u32 * DEPTH_QUARTERS,
         }
     }
 
-    color
+    pub fn spend(&mut self, material: &Material) {
+        self.remaining = self.remaining.saturating_sub(material.depth_cost());
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0
+    }
 }
 
-pub fn accel_raytrace(ray: &Ray, tree: &Box<CoveringTree>, scatter_depth: u8) -> Color {
+pub fn raytrace(ray: &Ray, scene: &[Hittable], settings: &RenderSettings) -> Color {
+    raytrace_with_alpha(ray, scene, settings).0
+}
+
+pub fn accel_raytrace(ray: &Ray, scene: &CoveredScene, settings: &RenderSettings) -> Color {
+    accel_raytrace_with_alpha(ray, scene, settings).0
+}
+
+// as raytrace along with the alpha of the sample, 1 when the ray hit
+// anything and 0 when it went straight out to the background, making the
+// integrator of settings for just this ray where the renders make it once
+// for all of theirs
+pub fn raytrace_with_alpha(
+    ray: &Ray,
+    scene: &[Hittable],
+    settings: &RenderSettings,
+) -> (Color, f64) {
+    integrator::select(settings, &scene).radiance_with_alpha(ray)
+}
+
+pub fn accel_raytrace_with_alpha(
+    ray: &Ray,
+    scene: &CoveredScene,
+    settings: &RenderSettings,
+) -> (Color, f64) {
+    integrator::select(settings, scene).radiance_with_alpha(ray)
+}
+
+// whether anything but target lies on the segment between from and to,
+// leaving out epsilon at either end so neither the surface the segment
+// starts on nor the one it ends on counts
+pub fn is_occluded(
+    scene: &[Hittable],
+    from: Vec3,
+    to: Vec3,
+    epsilon: f64,
+    target: Option<&Hittable>,
+) -> bool {
+    let ray = Ray::new(from, to - from);
+    is_occluded_along(scene, &ray, epsilon, (to - from).norm() - epsilon, target)
+}
+
+fn is_occluded_along(
+    scene: &[Hittable],
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+    target: Option<&Hittable>,
+) -> bool {
+    scene
+        .iter()
+        .filter(|hittable| target.is_none_or(|target| !std::ptr::eq(*hittable, target)))
+        .any(|hittable| hittable.shape.intersect_any(ray, t_min, t_max))
+}
+
+fn nearest_hit<'a>(scene: &'a [Hittable], ray: &Ray, t_min: f64) -> Option<(&'a Hittable, f64)> {
+    nearest_of(
+        scene
+            .iter()
+            .map(|hittable| (hittable, hittable.shape.intersect_beyond(ray, t_min))),
+    )
+}
+
+// every hit along the ray beyond t_min nearest first, each hittable giving
+// all of its own such as where the ray enters and leaves a sphere, for
+// compositing transparent surfaces or finding where objects overlap
+pub fn scene_all_hits<'a>(
+    scene: &'a [Hittable],
+    ray: &Ray,
+    t_min: f64,
+) -> Vec<(&'a Hittable, f64)> {
+    sorted_hits(scene.iter(), ray, t_min)
+}
+
+pub(crate) fn sorted_hits<'a, I>(hittables: I, ray: &Ray, t_min: f64) -> Vec<(&'a Hittable, f64)>
+where
+    I: Iterator<Item = &'a Hittable>,
+{
+    let mut hits: Vec<(&Hittable, f64)> = hittables
+        .flat_map(|hittable| {
+            hittable
+                .shape
+                .intersect_all(ray, t_min)
+                .into_iter()
+                .map(move |param| (hittable, param))
+        })
+        .collect();
+    hits.sort_by(|x, y| x.1.total_cmp(&y.1));
+    hits
+}
+
+pub(crate) fn nearest_of<'a, I>(candidates: I) -> Option<(&'a Hittable, f64)>
+where
+    I: Iterator<Item = (&'a Hittable, Option<f64>)>,
+{
+    match candidates.min_by(|x, y| cmp_intersection(x.1, y.1)) {
+        Some((hittable, Some(param))) => Some((hittable, param)),
+        _ => None,
+    }
+}
+
+// the emitters next event estimation samples, none unless it is enabled
+fn sampled_lights<'a, I>(hittables: I, settings: &RenderSettings) -> Vec<&'a Hittable>
+where
+    I: Iterator<Item = (usize, &'a Hittable)>,
+{
+    if !settings.light_sampling {
+        return Vec::new();
+    }
+    designated_lights(hittables, settings)
+}
+
+// the emitters with a surface to sample, only the ones at the positions
+// settings.lights gives if set, hittables pairing each with its position
+// in the scene file, the lights coming out in that order however the
+// hittables are visited
+fn designated_lights<'a, I>(hittables: I, settings: &RenderSettings) -> Vec<&'a Hittable>
+where
+    I: Iterator<Item = (usize, &'a Hittable)>,
+{
+    let mut lights: Vec<(usize, &Hittable)> = hittables
+        .filter(|(index, hittable)| {
+            let designated = settings
+                .lights
+                .as_ref()
+                .is_none_or(|lights| lights.contains(index));
+            designated && is_sampled_light(hittable)
+        })
+        .collect();
+    lights.sort_unstable_by_key(|(index, _)| *index);
+    lights.into_iter().map(|(_, hittable)| hittable).collect()
+}
+
+fn is_sampled_light(hittable: &Hittable) -> bool {
+    hittable.material.is_emitter() && hittable.shape.area().is_some()
+}
+
+// traces the photons settings.caustics asks for into settings.caustics_map,
+// which the renders then gather from
+pub fn caustics_prepass(scene: &[Hittable], settings: &mut RenderSettings) {
+    let Some(photons) = settings.caustics else {
+        return;
+    };
+    let lights = designated_lights(scene.iter().enumerate(), settings);
+    let map = photons::trace_photons(&lights, &photons, true, |ray| {
+        nearest_hit(scene, ray, settings.epsilon)
+    });
+    debug!("{} caustic photons stored", map.len());
+    settings.caustics_map = Some(map);
+}
+
+pub fn accel_caustics_prepass(scene: &CoveredScene, settings: &mut RenderSettings) {
+    let Some(photons) = settings.caustics else {
+        return;
+    };
+    let lights = designated_lights(scene.hittables_with_sources(), settings);
+    let map = photons::trace_photons(&lights, &photons, true, |ray| {
+        scene.nearest_hit(ray, settings.epsilon)
+    });
+    debug!("{} caustic photons stored", map.len());
+    settings.caustics_map = Some(map);
+}
+
+// follows one path through the scene, with nearest finding the closest hit
+// and transmittance the light getting from a point to one on a light, so
+// that the linear and the tree-accelerated scenes share the shading
+fn trace_path<'a, F, G>(
+    ray: &Ray,
+    settings: &RenderSettings,
+    lights: &LightSampler<'a>,
+    nearest: F,
+    transmittance: G,
+) -> (Color, f64)
+where
+    F: Fn(&Ray) -> Option<(&'a Hittable, f64)>,
+    G: Fn(Vec3, Vec3, &Hittable) -> Color,
+{
     let mut color = Color::new(1.0, 1.0, 1.0);
+    let mut alpha = 0.0;
+    // the light gathered by sampling the lights along the way
+    let mut direct = Color::new(0.0, 0.0, 0.0);
+    // where the previous bounce sampled the lights from, with the density
+    // of the direction it then scattered in
+    let mut light_sampled_from: Option<(Vec3, f64)> = None;
 
+    let mut first_hit = true;
     let mut ray = ray;
     let mut scatter_ray: Ray;
-    for _ in 1..=scatter_depth {
-        let mut subscene = Vec::<(&Hittable, Option<f64>)>::new();
-        tree_filter(tree, &mut subscene, ray);
-
-        if let Some((hit_obj, Some(param))) =
-            subscene.iter().min_by(|x, y| cmp_intersection(x.1, y.1))
-        {
-            let scatter_loc: Vec3 = ray.position_at(*param);
-            if let Material::Emitter { albedo } = hit_obj.material {
+    let mut budget = DepthBudget::new(settings.scatter_depth);
+    let mut diffuse_bounces: u8 = 0;
+    // whether the path went specular after going diffuse, so that reaching
+    // an emitter now is light the caustic photons already brought
+    let mut caustic_path = false;
+    while !budget.is_exhausted() {
+        if let Some((hit_obj, param)) = nearest(ray) {
+            alpha = 1.0;
+            let scatter_loc: Vec3 = ray.position_at(param);
+            if hit_obj.material.is_emitter() {
                 let cosine: f64 = ray.dir.dotprod(&hit_obj.shape.normal_at(scatter_loc));
-                return albedo * cosine.abs();
+                if hit_obj.shape.is_one_sided() && cosine > 0.0 {
+                    // the back of a one-sided light is dark
+                    return (direct, alpha);
+                }
+                if caustic_path && settings.caustics_map.is_some() {
+                    return (direct, alpha);
+                }
+                let weight = match light_sampled_from {
+                    Some((from, scatter_pdf)) if lights.probability(hit_obj) > 0.0 => {
+                        let light_pdf =
+                            hit_obj.shape.pdf(from, scatter_loc) * lights.probability(hit_obj);
+                        power_heuristic(scatter_pdf, light_pdf)
+                    }
+                    _ => 1.0,
+                };
+                let emitted = color
+                    * hit_obj.material.radiance_towards(-1.0 * ray.dir)
+                    * (weight * cosine.abs());
+                return (direct + emitted, alpha);
             };
-            scatter_ray = hit_obj.material.scatter(ray, &hit_obj.shape, scatter_loc);
-            let obj_relative_loc: Vec3;
-            match &hit_obj.shape {
-                Shape::Sphere(sphere) => {
-                    obj_relative_loc = (scatter_loc - sphere.centre).normalize()
+            if settings.direct_only && !first_hit {
+                // only the light reaching the first surface straight from
+                // the emitters and the background is wanted
+                return (direct, alpha);
+            }
+            budget.spend(&hit_obj.material);
+            let (facing_normal, front_face) =
+                hit_obj.shape.oriented_normal_at(scatter_loc, ray.dir);
+            let scattered = hit_obj.material.scatter(ray, &hit_obj.shape, scatter_loc);
+            let obj_relative_loc = hit_obj.shape.local_coords(scatter_loc);
+            let outgoing = -1.0 * ray.dir;
+            let albedo = hit_obj
+                .material
+                .albedo(&obj_relative_loc, outgoing, facing_normal);
+            scatter_ray = scattered.ray;
+            if let Material::Dielectric { tint, .. } = hit_obj.material {
+                if !front_face {
+                    // coming out of the glass, having gone through param of it
+                    color = color * materials::absorption(tint, param);
                 }
-                Shape::Disc(disc) => obj_relative_loc = scatter_loc - disc.centre,
-                _ => todo!(),
             }
-            color = color * hit_obj.material.albedo(&obj_relative_loc);
-            ray = &scatter_ray;
-        } else {
-            let t = 0.5 * (ray.dir[1] + 1.0);
-            let sky_color = (1.0 - t)
-                * Color {
-                    r: 1.0,
-                    g: 1.0,
-                    b: 1.0,
+            // no light sample could have taken the path on from here, as a
+            // shadow ray blocked by glass doesn't bend through it
+            light_sampled_from = None;
+            if hit_obj.material.is_diffuse() {
+                direct += color * albedo * settings.ambient;
+                if let Some(caustics) = &settings.caustics_map {
+                    direct += (1.0 / PI) * color * albedo * caustics.gather(scatter_loc);
                 }
-                + t * Color {
-                    r: 0.5,
-                    g: 0.7,
-                    b: 1.0,
-                };
+                caustic_path = false;
+            } else if diffuse_bounces > 0 {
+                caustic_path = true;
+            }
+            if hit_obj.material.is_diffuse() && !lights.is_empty() {
+                let sampled =
+                    sample_light(lights, hit_obj, scatter_loc, facing_normal, &transmittance);
+                if let Some((incoming, light)) = sampled {
+                    let brdf = hit_obj.material.brdf_value(
+                        &obj_relative_loc,
+                        incoming,
+                        outgoing,
+                        facing_normal,
+                    );
+                    direct += color * brdf * light * incoming.dotprod(&facing_normal);
+                }
+                light_sampled_from = Some((scatter_loc, scattered.pdf));
+            }
+            color = color * albedo;
+            if color.luminance() <= 0.0 {
+                // nothing further along the path can be seen through a black surface
+                return (direct, alpha);
+            }
+            ray = &scatter_ray;
+            first_hit = false;
 
-            return color * sky_color;
+            if hit_obj.material.is_diffuse() {
+                diffuse_bounces = diffuse_bounces.saturating_add(1);
+                if settings.diffuse_bounce_cap == Some(diffuse_bounces) {
+                    // as if the last bounce escaped straight to the sky
+                    return (direct + color * settings.background.color(ray), alpha);
+                }
+            }
+        } else {
+            return (direct + color * settings.background.color(ray), alpha);
         }
     }
 
-    color
+    // the path ran out of depth without reaching any more light
+    (direct, alpha)
 }
 
-pub fn render_into_file(file: &mut File, cam: &camera::Camera, scene: &[Hittable], spp: u32) {
-    let mut vis_stream = BufWriter::new(file);
-    for j in 0..cam.vert_res {
-        for i in 0..cam.horiz_res {
-            let mut pixel_color: Color = (0..spp)
-                .map(|_| cam.get_focus_loc())
-                .map(|focus_loc| Ray::new(focus_loc, cam.get_sample_loc(i, j) - focus_loc))
-                .fold(Color::new(0.0, 0.0, 0.0), |acc, r| {
-                    acc + raytrace(&r, &scene, 10)
-                });
+// the direction towards a point on one of the lights picked at random from
+// a diffuse surface at position, with the light reaching it from there over
+// the density it was picked with, weighted against the surface scattering
+// towards the light, and scaled by the transmittance from position to the
+// point on the light
+fn sample_light<G>(
+    lights: &LightSampler,
+    surface: &Hittable,
+    position: Vec3,
+    normal: Vec3,
+    transmittance: &G,
+) -> Option<(Vec3, Color)>
+where
+    G: Fn(Vec3, Vec3, &Hittable) -> Color,
+{
+    let mut rng = sampler::rng();
+    let (light, probability) = lights.sample(&mut rng)?;
+    let point = light.shape.sample_surface(&mut rng)?;
+    let shadow_ray = Ray::new(position, point - position);
+    let scatter_pdf = surface.material.scatter_pdf(normal, shadow_ray.dir);
+    let light_pdf = light.shape.pdf(position, point) * probability;
+    if scatter_pdf == 0.0 || light_pdf == 0.0 {
+        return None;
+    }
+    let cosine = light.shape.normal_at(point).dotprod(&shadow_ray.dir);
+    if light.shape.is_one_sided() && cosine > 0.0 {
+        return None;
+    }
+    // tested last as the shadow ray is what costs the most
+    let transmittance = transmittance(position, point, light);
+    if transmittance.luminance() <= 0.0 {
+        return None;
+    }
+    let weight = power_heuristic(light_pdf, scatter_pdf) / light_pdf;
+    let radiance = light.material.radiance_towards(-1.0 * shadow_ray.dir);
+    Some((
+        shadow_ray.dir,
+        transmittance * radiance * (weight * cosine.abs()),
+    ))
+}
 
-            pixel_color = (1.0 / (spp as f64)) * pixel_color; // no Div defined for Color
-            let color = color_to_ppm(pixel_color);
+// the multiple importance sampling weight of the strategy with density pdf
+// against the other strategy with density other_pdf
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    pdf * pdf / (pdf * pdf + other_pdf * other_pdf)
+}
 
-            writeln!(vis_stream, "{} {} {}", color.0, color.1, color.2)
-                .expect("Unable to write colors.");
+// the fraction of light getting from `from` to `to`, dielectrics let light
+// through attenuated by their Fresnel transmission and by their tint over
+// the length inside them, anything else blocks it
+pub fn shadow_transmittance(scene: &[Hittable], from: Vec3, to: Vec3) -> Color {
+    let epsilon = geometry::DEFAULT_EPSILON;
+    transmittance_between(from, to, None, epsilon, &|ray: &Ray| {
+        nearest_hit(scene, ray, epsilon)
+    })
+}
+
+// shadow_transmittance with nearest finding the closest hit, reaching the
+// target the segment ends on counting as getting through, the shadow ray
+// going straight through the glass rather than bending as the scattered
+// rays do
+fn transmittance_between<'a, F>(
+    from: Vec3,
+    to: Vec3,
+    target: Option<&Hittable>,
+    epsilon: f64,
+    nearest: &F,
+) -> Color
+where
+    F: Fn(&Ray) -> Option<(&'a Hittable, f64)>,
+{
+    let mut transmittance = Color::new(1.0, 1.0, 1.0);
+    let mut ray = Ray::new(from, to - from);
+    let mut remaining = (to - from).norm();
+
+    for _ in 0..MAX_SHADOW_HITS {
+        let Some((hit_obj, param)) = nearest(&ray) else {
+            return transmittance;
+        };
+        if param >= remaining - epsilon || target.is_some_and(|t| std::ptr::eq(hit_obj, t)) {
+            return transmittance;
+        }
+        let Material::Dielectric {
+            refractive_index,
+            tint,
+        } = hit_obj.material
+        else {
+            return Color::new(0.0, 0.0, 0.0);
+        };
+        let hit_loc = ray.position_at(param);
+        let cosine = hit_obj.shape.normal_at(hit_loc).dotprod(&ray.dir);
+        if cosine > 0.0 {
+            // coming out of the glass, having gone through param of it
+            transmittance = transmittance * materials::absorption(tint, param);
         }
-        eprint!("\rScanline: {} out of {}", j, cam.vert_res);
+        transmittance = (1.0 - materials::reflectance(cosine, refractive_index)) * transmittance;
+        remaining -= param;
+        ray = Ray::new(hit_loc, ray.dir);
     }
-    eprintln!("");
+
+    Color::new(0.0, 0.0, 0.0)
+}
+
+// stops shadow rays bouncing between coincident surfaces forever
+const MAX_SHADOW_HITS: u32 = 64;
+
+pub fn write_ppm_header<W: Write>(out: &mut W, width: u32, height: u32, maxval: u16) {
+    write!(out, "P3\n{} {}\n{}\n", width, height, maxval).expect("Unable to write header to ppm");
+}
+
+pub fn render_into_file<W: Write>(
+    file: &mut W,
+    cam: &camera::Camera,
+    scene: &[Hittable],
+    settings: &RenderSettings,
+    spp: u32,
+) {
+    let integrator = integrator::select(settings, &scene);
+    render_pixels(file, cam, settings, spp, |ray| {
+        integrator.radiance_with_alpha(ray)
+    });
 }
 
-pub fn accel_render_into_file(
-    file: &mut File,
+pub fn accel_render_into_file<W: Write>(
+    file: &mut W,
     cam: &camera::Camera,
-    tree: Box<CoveringTree>,
+    scene: &CoveredScene,
+    settings: &RenderSettings,
     spp: u32,
 ) {
+    let integrator = integrator::select(settings, scene);
+    render_pixels(file, cam, settings, spp, |ray| {
+        integrator.radiance_with_alpha(ray)
+    });
+}
+
+// an unlit view of the scene for checking its modelling, dark within
+// edge_width of the edges of the shapes that have them and light elsewhere
+// on them, with mid gray where the rays through the pixel centres escaped
+pub fn render_wireframe(
+    cam: &camera::Camera,
+    scene: &[Hittable],
+    edge_width: f64,
+) -> image::GrayImage {
+    image::GrayImage::from_fn(cam.horiz_res, cam.vert_res, |i, j| {
+        let ray = primary_ray(cam, i, j);
+        let shade = match nearest_hit(scene, &ray, geometry::DEFAULT_EPSILON) {
+            Some((hit_obj, param)) => match hit_obj.shape.edge_distance(ray.position_at(param)) {
+                Some(distance) if distance < edge_width => 0,
+                _ => 255,
+            },
+            None => 128,
+        };
+        image::Luma([shade])
+    })
+}
+
+// the render region as an 8-bit rgba image, transparent where the camera
+// rays escaped so the objects can be composited over another backdrop
+pub fn render_rgba_image(
+    cam: &camera::Camera,
+    scene: &[Hittable],
+    settings: &RenderSettings,
+    spp: u32,
+) -> image::RgbaImage {
+    let integrator = integrator::select(settings, &scene);
+    render_rgba(cam, settings, spp, |ray| {
+        integrator.radiance_with_alpha(ray)
+    })
+}
+
+pub fn accel_render_rgba_image(
+    cam: &camera::Camera,
+    scene: &CoveredScene,
+    settings: &RenderSettings,
+    spp: u32,
+) -> image::RgbaImage {
+    let integrator = integrator::select(settings, scene);
+    render_rgba(cam, settings, spp, |ray| {
+        integrator.radiance_with_alpha(ray)
+    })
+}
+
+fn render_rgba<F>(
+    cam: &camera::Camera,
+    settings: &RenderSettings,
+    spp: u32,
+    trace: F,
+) -> image::RgbaImage
+where
+    F: Fn(&Ray) -> (Color, f64),
+{
+    let (columns, rows) = settings.region(cam);
+    let film = render_film(cam, settings, spp, columns, rows, &trace);
+    info!("");
+    film.to_rgba_image(settings)
+}
+
+// writes the pixels inside the render region as a ppm, with trace giving
+// the radiance along each camera ray
+fn render_pixels<W, F>(
+    file: &mut W,
+    cam: &camera::Camera,
+    settings: &RenderSettings,
+    spp: u32,
+    trace: F,
+) where
+    W: Write,
+    F: Fn(&Ray) -> (Color, f64),
+{
+    let (columns, rows) = settings.region(cam);
+    let film = render_film(cam, settings, spp, columns.clone(), rows.clone(), &trace);
+    info!("");
+
     let mut vis_stream = BufWriter::new(file);
-    for j in 0..cam.vert_res {
-        for i in 0..cam.horiz_res {
-            let mut pixel_color: Color = (0..spp)
-                .map(|_| cam.get_focus_loc())
-                .map(|focus_loc| Ray::new(focus_loc, cam.get_sample_loc(i, j) - focus_loc))
-                .fold(Color::new(0.0, 0.0, 0.0), |acc, r| {
-                    acc + accel_raytrace(&r, &tree, 10)
-                });
+    write_ppm_header(
+        &mut vis_stream,
+        columns.len() as u32,
+        rows.len() as u32,
+        settings.ppm_maxval,
+    );
+    for color in film.encoded(settings) {
+        writeln!(vis_stream, "{} {} {}", color.0, color.1, color.2)
+            .expect("Unable to write colors.");
+    }
+}
 
-            pixel_color = (1.0 / (spp as f64)) * pixel_color; // no Div defined for Color
-            let color = color_to_ppm(pixel_color);
+// accumulates the samples of every pixel in (columns, rows)
+pub(crate) fn render_film<F>(
+    cam: &camera::Camera,
+    settings: &RenderSettings,
+    spp: u32,
+    columns: Range<u32>,
+    rows: Range<u32>,
+    trace: &F,
+) -> Film
+where
+    F: Fn(&Ray) -> (Color, f64),
+{
+    render_film_reporting(cam, settings, spp, columns, rows, trace, |j| {
+        if logging::enabled(logging::Level::Info) {
+            eprint!("\rScanline: {} out of {}", j, cam.vert_res);
+        }
+    })
+}
 
-            writeln!(vis_stream, "{} {} {}", color.0, color.1, color.2)
-                .expect("Unable to write colors.");
+// as render_film, calling on_scanline with each row once it is finished,
+// the pixels just outside the region are sampled too when the filter is
+// wide enough for their samples to land inside it
+pub(crate) fn render_film_reporting<F, R>(
+    cam: &camera::Camera,
+    settings: &RenderSettings,
+    spp: u32,
+    columns: Range<u32>,
+    rows: Range<u32>,
+    trace: &F,
+    mut on_scanline: R,
+) -> Film
+where
+    F: Fn(&Ray) -> (Color, f64),
+    R: FnMut(u32),
+{
+    let reach = film::filter_reach(settings.filter_radius);
+    let padded = |region: Range<u32>, res: u32| {
+        region.start.saturating_sub(reach)..(region.end + reach).min(res)
+    };
+    let (sampled_columns, sampled_rows) = (
+        padded(columns.clone(), cam.horiz_res),
+        padded(rows.clone(), cam.vert_res),
+    );
+    let mut film = Film::new(columns, rows);
+    let render_timer = Instant::now();
+    for j in sampled_rows {
+        for i in sampled_columns.clone() {
+            if let Some(seed) = settings.seed {
+                sampler::seed_pixel(i, j, seed);
+            }
+            sample_pixel(cam, settings, spp, i, j, trace, |x, y, (color, alpha)| {
+                film.splat(x, y, color, alpha, settings.filter_radius)
+            });
         }
-        eprint!("\rScanline: {} out of {}", j, cam.vert_res);
+        on_scanline(j);
+        if let Some(limit) = settings.time_limit {
+            if render_timer.elapsed() >= limit {
+                info!("");
+                warn!("time limit of {:?} reached after scanline {}", limit, j);
+                break;
+            }
+        }
+    }
+    if film.non_finite_samples() > 0 {
+        warn!(
+            "{} non-finite samples were counted as black",
+            film.non_finite_samples()
+        );
     }
-    eprintln!("");
+    film
 }
 
-pub fn color_to_ppm(col: Color) -> (u8, u8, u8) {
-    (
-        (255.0 * col.r.sqrt()) as u8,
-        (255.0 * col.g.sqrt()) as u8,
-        (255.0 * col.b.sqrt()) as u8,
-    )
+// traces the samples of pixel (i, j), handing each to splat along with
+// where it was taken on the image plane in pixel units
+fn sample_pixel<F, S>(
+    cam: &camera::Camera,
+    settings: &RenderSettings,
+    spp: u32,
+    i: u32,
+    j: u32,
+    trace: &F,
+    mut splat: S,
+) where
+    F: Fn(&Ray) -> (Color, f64),
+    S: FnMut(f64, f64, (Color, f64)),
+{
+    let (x, y) = (f64::from(i), f64::from(j));
+    if !settings.antialiasing {
+        // a single ray through the pixel centre from the centre of the lens
+        let sample = trace(&primary_ray(cam, i, j));
+        return splat(x + 0.5, y + 0.5, sample);
+    }
+
+    let shift = settings.sampler.pixel_shift();
+    for index in 0..spp {
+        let (offset, lens) = settings
+            .sampler
+            .camera_sample(index, shift, cam.aperture_shape);
+        let focus_loc = cam.get_lens_loc(lens);
+        let ray = Ray::new(focus_loc, cam.get_pixel_loc(i, j, offset) - focus_loc)
+            .with_spread(cam.pixel_spread());
+        splat(x + offset[0], y + offset[1], trace(&ray));
+    }
 }
 
-pub fn rgba_to_color(rgba: image::Rgba<u8>) -> Color {
-    Color::new(
-        (rgba[0] as f64) / 255.0,
-        (rgba[1] as f64) / 255.0,
-        (rgba[2] as f64) / 255.0,
-    )
+// the output values of the rendered pixel at (column, row), the exposure
+// scales the linear color before it is gamma encoded
+pub fn encode_pixel(col: Color, settings: &RenderSettings, pixel: (u32, u32)) -> (u16, u16, u16) {
+    encode_with_maxval(col, settings, pixel, settings.ppm_maxval)
+}
+
+pub(crate) fn encode_with_maxval(
+    col: Color,
+    settings: &RenderSettings,
+    pixel: (u32, u32),
+    maxval: u16,
+) -> (u16, u16, u16) {
+    let offset = if settings.dither {
+        dither_offset(pixel)
+    } else {
+        0.0
+    };
+    let mapped = settings.tonemap.apply(expose(col, settings.exposure));
+    quantize(mapped, settings.gamma, maxval, offset)
+}
+
+// scales by 2^exposure, so +1 is one stop brighter
+pub fn expose(col: Color, exposure: f64) -> Color {
+    exposure.exp2() * col
+}
+
+// rounds rather than truncates as the sRGB curve puts 1.0 a hair below 1
+pub fn color_to_ppm(col: Color, gamma: Gamma) -> (u8, u8, u8) {
+    let (r, g, b) = color_to_ppm_maxval(col, gamma, u8::MAX as u16);
+    (r as u8, g as u8, b as u8)
+}
+
+// as color_to_ppm with the channels scaled to [0, maxval], clamped into
+// gamut before they are encoded
+pub fn color_to_ppm_maxval(col: Color, gamma: Gamma, maxval: u16) -> (u16, u16, u16) {
+    quantize(col, gamma, maxval, 0.0)
+}
+
+// as color_to_ppm_maxval, dithered by the ordered dither of pixel
+pub fn color_to_ppm_dithered(
+    col: Color,
+    gamma: Gamma,
+    maxval: u16,
+    pixel: (u32, u32),
+) -> (u16, u16, u16) {
+    quantize(col, gamma, maxval, dither_offset(pixel))
+}
+
+// offset in [-0.5, 0.5) output levels is added to each channel before it
+// is rounded
+fn quantize(col: Color, gamma: Gamma, maxval: u16, offset: f64) -> (u16, u16, u16) {
+    let col = col.clamp_gamut();
+    let scale = |value: f64| {
+        (f64::from(maxval) * gamma.encode(value) + offset)
+            .round()
+            .clamp(0.0, f64::from(maxval)) as u16
+    };
+    (scale(col.r), scale(col.g), scale(col.b))
+}
+
+// the 4x4 Bayer matrix tiled over the image, each block of 16 pixels
+// spreading its offsets evenly over a level so a flat color rounds up in
+// exactly the fraction of them it lies above the level below, and being
+// keyed on the position alone the pattern is the same in every render
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+fn dither_offset((i, j): (u32, u32)) -> f64 {
+    let rank = BAYER_4X4[(j % 4) as usize][(i % 4) as usize];
+    (f64::from(rank) + 0.5) / 16.0 - 0.5
+}
+
+// textures are usually sRGB encoded, srgb = false reads them as already linear
+pub fn rgba_to_color(rgba: image::Rgba<u8>, srgb: bool) -> Color {
+    let decode = |value: u8| {
+        let encoded = (value as f64) / 255.0;
+        if srgb {
+            color::srgb_to_linear(encoded)
+        } else {
+            encoded
+        }
+    };
+    Color::new(decode(rgba[0]), decode(rgba[1]), decode(rgba[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::Background;
+    use crate::color::Color;
+    use crate::settings::CropWindow;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn count_bounces(material: &Material, scatter_depth: u8) -> u32 {
+        let mut budget = DepthBudget::new(scatter_depth);
+        let mut bounces = 0;
+        while !budget.is_exhausted() {
+            budget.spend(material);
+            bounces += 1;
+        }
+        bounces
+    }
+
+    #[test]
+    fn depth_budget_test() {
+        let diffuse = Material::Diffuse {
+            albedo: Color::new(0.5, 0.5, 0.5).into(),
+        };
+        let glass = Material::Dielectric {
+            refractive_index: 1.5,
+            tint: Color::new(1.0, 1.0, 1.0),
+        };
+
+        assert_eq!(count_bounces(&diffuse, 10), 10);
+        assert!(count_bounces(&glass, 10) > count_bounces(&diffuse, 10));
+    }
+
+    #[test]
+    fn render_random_scene_test() {
+        let scene = scenegen::gen_scene_from(&mut StdRng::seed_from_u64(405));
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([13.0, 1.5, 3.0]),
+            1.0,
+            0.1,
+            4,
+            3,
+        );
+
+        let mut buffer = Vec::<u8>::new();
+        accel_render_into_file(&mut buffer, &cam, &scene, &RenderSettings::default(), 1);
+
+        let ppm = String::from_utf8(buffer).expect("ppm output should be ascii");
+        let mut lines = ppm.lines();
+        assert_eq!(lines.next(), Some("P3"));
+        assert_eq!(lines.next(), Some("4 3"));
+        assert_eq!(lines.next(), Some("255"));
+        let pixels: Vec<&str> = lines.collect();
+        assert_eq!(pixels.len(), 12);
+        assert!(pixels.iter().all(|pixel| pixel.split(' ').count() == 3));
+    }
+
+    #[test]
+    fn reused_scratch_test() {
+        let scene = scenegen::gen_scene_from(&mut StdRng::seed_from_u64(405));
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([13.0, 1.5, 3.0]),
+            1.0,
+            0.1,
+            8,
+            6,
+        );
+        // the candidates gathered into one buffer cleared between rays are
+        // those of a fresh one, and give the nearest hit of the tree walk
+        let mut scratch = Vec::new();
+        for (i, j) in Film::new(0..8, 0..6).positions() {
+            let ray = primary_ray(&cam, i, j);
+            let mut fresh = Vec::new();
+            scene.filter(&mut fresh, &ray, geometry::DEFAULT_EPSILON);
+            scratch.clear();
+            scene.filter(&mut scratch, &ray, geometry::DEFAULT_EPSILON);
+            let params = |subscene: &[(&Hittable, Option<f64>)]| -> Vec<Option<f64>> {
+                subscene.iter().map(|(_, param)| *param).collect()
+            };
+            assert_eq!(params(&scratch), params(&fresh));
+            assert_eq!(
+                nearest_of(scratch.iter().copied()).map(|(_, param)| param),
+                scene
+                    .nearest_hit(&ray, geometry::DEFAULT_EPSILON)
+                    .map(|(_, param)| param)
+            );
+        }
+        assert!(scratch.capacity() > 0);
+    }
+
+    #[test]
+    fn no_background_test() {
+        let scene = [Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(0.8, 0.8, 0.8).into(),
+            },
+        }];
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -5.0]),
+            1.0,
+            0.0,
+            4,
+            4,
+        );
+        let settings = RenderSettings {
+            background: Background::None,
+            ..Default::default()
+        };
+
+        let mut buffer = Vec::<u8>::new();
+        render_into_file(&mut buffer, &cam, &scene, &settings, 2);
+
+        let ppm = String::from_utf8(buffer).expect("ppm output should be ascii");
+        assert!(ppm.lines().skip(3).all(|pixel| pixel == "0 0 0"));
+    }
+
+    #[test]
+    fn linear_ppm_test() {
+        let half = Color::new(0.5, 0.5, 0.5);
+        assert_eq!(color_to_ppm(half, Gamma::Linear), (128, 128, 128));
+        assert_eq!(color_to_ppm(half, Gamma::Sqrt), (180, 180, 180));
+
+        let out_of_range = Color::new(-0.5, 1.0, 2.0);
+        assert_eq!(color_to_ppm(out_of_range, Gamma::Linear), (0, 255, 255));
+    }
+
+    #[test]
+    fn gamut_clamp_test() {
+        let undershoot = Color::new(-0.01, 0.25, 0.25);
+        assert!(Gamma::Sqrt.encode(undershoot.r).is_nan());
+        assert_eq!(undershoot.clamp_gamut().r, 0.0);
+        assert_eq!(color_to_ppm(undershoot, Gamma::Sqrt), (0, 128, 128));
+        assert_eq!(color_to_ppm(undershoot, Gamma::Srgb).0, 0);
+        let nan = Color::new(f64::NAN, 2.0, 1.0);
+        assert_eq!(nan.clamp_gamut().r, 0.0);
+        assert_eq!(color_to_ppm(nan, Gamma::Sqrt), (0, 255, 255));
+    }
+
+    #[test]
+    fn exposure_test() {
+        let quarter = Color::new(0.25, 0.125, 0.0);
+        let brighter = expose(quarter, 1.0);
+        assert_eq!((brighter.r, brighter.g, brighter.b), (0.5, 0.25, 0.0));
+
+        let settings = RenderSettings {
+            gamma: Gamma::Linear,
+            exposure: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(encode_pixel(quarter, &settings, (0, 0)), (128, 64, 0));
+        let (r, g, b) = color_to_ppm(quarter, Gamma::default());
+        assert_eq!(
+            encode_pixel(quarter, &RenderSettings::default(), (0, 0)),
+            (r.into(), g.into(), b.into())
+        );
+    }
+
+    #[test]
+    fn time_limit_test() {
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -4.0]),
+            0.5,
+            0.0,
+            3,
+            3,
+        );
+        // any render takes longer than no time at all, so only the first
+        // scanline is finished
+        let settings = RenderSettings {
+            background: Background::Solid(Color::new(1.0, 1.0, 1.0)),
+            time_limit: Some(std::time::Duration::ZERO),
+            ..Default::default()
+        };
+        let mut buffer = Vec::<u8>::new();
+        render_into_file(&mut buffer, &cam, &[], &settings, 4);
+
+        let ppm = String::from_utf8(buffer).expect("ppm output should be ascii");
+        let pixels: Vec<&str> = ppm.lines().skip(3).collect();
+        assert_eq!(pixels.len(), 9);
+        assert!(pixels[..3].iter().all(|pixel| *pixel == "255 255 255"));
+        assert!(pixels[3..].iter().all(|pixel| *pixel == "0 0 0"));
+    }
+
+    #[test]
+    fn time_limit_seconds_test() {
+        let settings: RenderSettings =
+            serde_json::from_str(r#"{"time_limit": 1.5}"#).expect("settings should deserialize");
+        assert_eq!(
+            settings.time_limit,
+            Some(std::time::Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn rgba_alpha_test() {
+        let scene = [Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(0.5, 0.5, 0.5).into(),
+            },
+        }];
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -4.0]),
+            1.0,
+            0.0,
+            3,
+            3,
+        );
+        let settings = RenderSettings {
+            antialiasing: false,
+            ..Default::default()
+        };
+
+        // only the centre ray lands on the sphere
+        let image = render_rgba_image(&cam, &scene, &settings, 1);
+        assert_eq!(image.dimensions(), (3, 3));
+        for (i, j, pixel) in image.enumerate_pixels() {
+            let alpha = if (i, j) == (1, 1) { 255 } else { 0 };
+            assert_eq!(pixel[3], alpha, "alpha of pixel ({}, {})", i, j);
+        }
+        // while escaped rays still carry the background color
+        assert!(image.get_pixel(0, 0)[2] > 0);
+
+        let covered = CoveredScene::new(scene.into());
+        assert_eq!(
+            accel_render_rgba_image(&cam, &covered, &settings, 1).get_pixel(1, 1)[3],
+            255
+        );
+    }
+
+    #[test]
+    fn dither_test() {
+        // exactly halfway between two levels, so undithered it always rounds up
+        let half = Color::new(0.5, 0.5, 0.5);
+        let block: Vec<u16> = (0..4)
+            .flat_map(|j| (0..4).map(move |i| (i, j)))
+            .map(|pixel| color_to_ppm_dithered(half, Gamma::Linear, 255, pixel).0)
+            .collect();
+        assert!(block.iter().all(|level| *level == 127 || *level == 128));
+        assert_eq!(block.iter().filter(|level| **level == 128).count(), 8);
+        // neighbouring pixels of a row and a column round different ways
+        assert_ne!(block[0], block[1]);
+        assert_ne!(block[0], block[4]);
+        // the pattern repeats, so renders are dithered the same every time
+        assert_eq!(
+            color_to_ppm_dithered(half, Gamma::Linear, 255, (5, 6)),
+            color_to_ppm_dithered(half, Gamma::Linear, 255, (1, 2))
+        );
+        // levels stay within the output range
+        let white = Color::new(1.0, 1.0, 1.0);
+        let settings = RenderSettings {
+            dither: true,
+            ..Default::default()
+        };
+        assert_eq!(encode_pixel(white, &settings, (3, 0)), (255, 255, 255));
+    }
+
+    #[test]
+    fn sixteen_bit_ppm_test() {
+        let half = Color::new(0.5, 0.5, 0.5);
+        assert_eq!(
+            color_to_ppm_maxval(half, Gamma::Linear, 65535),
+            (32768, 32768, 32768)
+        );
+        let out_of_range = Color::new(-0.5, 1.0, 2.0);
+        assert_eq!(
+            color_to_ppm_maxval(out_of_range, Gamma::Srgb, 65535),
+            (0, 65535, 65535)
+        );
+
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -4.0]),
+            0.5,
+            0.0,
+            2,
+            2,
+        );
+        // every pixel sees the mid-gray background
+        let settings = RenderSettings {
+            background: Background::Solid(half),
+            gamma: Gamma::Linear,
+            antialiasing: false,
+            ppm_maxval: 65535,
+            ..Default::default()
+        };
+        let mut buffer = Vec::<u8>::new();
+        render_into_file(&mut buffer, &cam, &[], &settings, 1);
+
+        let ppm = String::from_utf8(buffer).expect("ppm output should be ascii");
+        let mut lines = ppm.lines();
+        assert_eq!(lines.nth(2), Some("65535"));
+        assert!(lines.all(|pixel| pixel == "32768 32768 32768"));
+    }
+
+    #[test]
+    fn rgba_to_color_test() {
+        let gray = image::Rgba([128, 128, 128, 255]);
+        assert!((rgba_to_color(gray, true).r - 0.2158).abs() < 1.0e-3);
+        assert!((rgba_to_color(gray, false).r - 128.0 / 255.0).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn crop_window_test() {
+        let scene = [Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(0.8, 0.8, 0.8).into(),
+            },
+        }];
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -5.0]),
+            1.0,
+            0.0,
+            4,
+            4,
+        );
+        let settings = RenderSettings {
+            crop: Some(CropWindow {
+                x0: 1,
+                y0: 1,
+                x1: 3,
+                y1: 3,
+            }),
+            ..Default::default()
+        };
+
+        let mut buffer = Vec::<u8>::new();
+        render_into_file(&mut buffer, &cam, &scene, &settings, 1);
+
+        let ppm = String::from_utf8(buffer).expect("ppm output should be ascii");
+        let mut lines = ppm.lines();
+        assert_eq!(lines.nth(1), Some("2 2"));
+        assert_eq!(lines.skip(1).count(), 4);
+    }
+
+    #[test]
+    fn filter_radius_uniform_scene_test() {
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -5.0]),
+            1.0,
+            0.5,
+            5,
+            3,
+        );
+        let render = |filter_radius| {
+            let settings = RenderSettings {
+                background: Background::Solid(Color::new(0.2, 0.4, 0.6)),
+                filter_radius,
+                ..Default::default()
+            };
+            let mut buffer = Vec::<u8>::new();
+            render_into_file(&mut buffer, &cam, &[], &settings, 4);
+            String::from_utf8(buffer).unwrap()
+        };
+
+        let boxed = render(0.5);
+        assert_eq!(boxed.lines().skip(3).count(), 15);
+        for filter_radius in [0.75, 1.0, 2.5] {
+            assert_eq!(render(filter_radius), boxed);
+        }
+    }
+
+    // the pixels of a half grey sphere of radius scale seen from above, any
+    // self-intersection would darken them further
+    fn render_grey_sphere(scale: f64, epsilon: f64) -> Vec<String> {
+        let scene = [Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, -scale, 0.0]), scale)),
+            material: Material::Diffuse {
+                albedo: Color::new(0.5, 0.5, 0.5).into(),
+            },
+        }];
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.5 * scale, -scale]),
+            0.1,
+            0.0,
+            3,
+            3,
+        );
+        let settings = RenderSettings {
+            background: Background::Solid(Color::new(1.0, 1.0, 1.0)),
+            epsilon,
+            ..Default::default()
+        };
+        let mut buffer = Vec::<u8>::new();
+        render_into_file(&mut buffer, &cam, &scene, &settings, 64);
+        let ppm = String::from_utf8(buffer).unwrap();
+        ppm.lines().skip(3).map(String::from).collect()
+    }
+
+    fn grey_ppm() -> String {
+        let grey = color_to_ppm(Color::new(0.5, 0.5, 0.5), Gamma::default());
+        format!("{} {} {}", grey.0, grey.1, grey.2)
+    }
+
+    #[test]
+    fn large_scale_epsilon_test() {
+        let pixels = render_grey_sphere(1.0e10, 10.0);
+        assert!(pixels.iter().all(|pixel| *pixel == grey_ppm()));
+    }
+
+    #[test]
+    fn small_scale_epsilon_test() {
+        // the whole sphere is nearer the camera than the default epsilon
+        let pixels = render_grey_sphere(1.0e-7, geometry::DEFAULT_EPSILON);
+        assert!(pixels.iter().all(|pixel| pixel == "255 255 255"));
+
+        let pixels = render_grey_sphere(1.0e-7, 1.0e-16);
+        assert!(pixels.iter().all(|pixel| *pixel == grey_ppm()));
+    }
+
+    #[test]
+    fn diffuse_bounce_cap_test() {
+        // inside an inward facing sphere the path never escapes on its own
+        let scene = [Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, 0.0, 0.0]), -10.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(0.5, 0.5, 0.5).into(),
+            },
+        }];
+        let ray = Ray::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 0.0, 1.0]));
+        let mut settings = RenderSettings {
+            scatter_depth: 50,
+            background: Background::Solid(Color::new(0.8, 0.4, 0.2)),
+            ..Default::default()
+        };
+        let trapped = raytrace(&ray, &scene, &settings);
+        assert_eq!((trapped.r, trapped.g, trapped.b), (0.0, 0.0, 0.0));
+
+        settings.diffuse_bounce_cap = Some(3);
+        let capped = raytrace(&ray, &scene, &settings);
+        assert_eq!((capped.r, capped.g, capped.b), (0.1, 0.05, 0.025));
+    }
+
+    #[test]
+    fn trace_primary_test() {
+        let scene = vec![Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(0.5, 0.5, 0.5).into(),
+            },
+        }];
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -5.0]),
+            1.0,
+            0.0,
+            3,
+            3,
+        );
+
+        let (hittable, hit) =
+            trace_primary(&cam, &scene, 1, 1).expect("the centre is on the sphere");
+        assert!(std::ptr::eq(hittable, &scene[0]));
+        assert!((hit.param - 4.0).abs() < 1.0e-12);
+        assert!((hit.normal - Vec3([0.0, 0.0, -1.0])).norm() < 1.0e-12);
+        assert!(hit.front_face);
+        assert!(trace_primary(&cam, &scene, 0, 0).is_none());
+
+        let covered = CoveredScene::new(scene);
+        let (_, accel_hit) = accel_trace_primary(&cam, &covered, 1, 1).unwrap();
+        assert_eq!(accel_hit.position, hit.position);
+    }
+
+    #[test]
+    fn trace_bound_volume_test() {
+        let bbox = geometry::BoundBox::surround(&Shape::Sphere(geometry::Sphere::new(
+            Vec3([0.0, 0.0, 0.0]),
+            1.0,
+        )));
+        let scene = [Hittable {
+            shape: Shape::BoundVolume(bbox),
+            material: Material::Diffuse {
+                albedo: Color::new(0.5, 0.5, 0.5).into(),
+            },
+        }];
+        let settings = RenderSettings {
+            background: Background::Solid(Color::new(1.0, 1.0, 1.0)),
+            ..Default::default()
+        };
+
+        // a convex box scatters every ray back out to the background
+        let ray = Ray::new(Vec3([0.2, 0.3, -5.0]), Vec3([0.0, 0.0, 1.0]));
+        let color = raytrace(&ray, &scene, &settings);
+        assert_eq!((color.r, color.g, color.b), (0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn light_sampling_test() {
+        let make_scene = || {
+            vec![
+                Hittable {
+                    shape: Shape::Quad(geometry::Quad::new(
+                        Vec3([-5.0, 0.0, -5.0]),
+                        Vec3([0.0, 0.0, 10.0]),
+                        Vec3([10.0, 0.0, 0.0]),
+                    )),
+                    material: Material::Diffuse {
+                        albedo: Color::new(0.5, 0.5, 0.5).into(),
+                    },
+                },
+                Hittable {
+                    shape: Shape::Quad(geometry::Quad::new(
+                        Vec3([-0.5, 2.0, -0.5]),
+                        Vec3([1.0, 0.0, 0.0]),
+                        Vec3([0.0, 0.0, 1.0]),
+                    )),
+                    material: Material::Emitter {
+                        albedo: Color::new(1.0, 1.0, 1.0),
+                        intensity: 10.0,
+                    },
+                },
+            ]
+        };
+        let scene = make_scene();
+        let covered = CoveredScene::new(make_scene());
+        let ray = Ray::new(Vec3([0.0, 1.0, -3.0]), Vec3([0.0, -1.0, 3.0]));
+
+        const SAMPLES: usize = 20000;
+        let estimate = |trace: &dyn Fn(&RenderSettings) -> Color, light_sampling: bool| {
+            let settings = RenderSettings {
+                background: Background::None,
+                light_sampling,
+                ..Default::default()
+            };
+            let samples: Vec<f64> = (0..SAMPLES).map(|_| trace(&settings).r).collect();
+            let mean = samples.iter().sum::<f64>() / SAMPLES as f64;
+            let variance =
+                samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (SAMPLES - 1) as f64;
+            (mean, variance)
+        };
+        let linear = |settings: &RenderSettings| raytrace(&ray, &scene, settings);
+        let accel = |settings: &RenderSettings| accel_raytrace(&ray, &covered, settings);
+
+        let (scatter_mean, scatter_variance) = estimate(&linear, false);
+        for (mis_mean, mis_variance) in [estimate(&linear, true), estimate(&accel, true)] {
+            // the same light on average, to within five standard errors
+            let standard_error = ((scatter_variance + mis_variance) / SAMPLES as f64).sqrt();
+            assert!(
+                (scatter_mean - mis_mean).abs() < 5.0 * standard_error,
+                "{} against {}",
+                mis_mean,
+                scatter_mean
+            );
+            // with far less noise as the light is small
+            assert!(mis_variance < 0.25 * scatter_variance);
+        }
+    }
+
+    #[test]
+    fn designated_lights_test() {
+        let contents = br##"{
+            "camera": {"lookat": [0.0, 0.0, 0.0], "lookfrom": [0.0, 0.0, -4.0],
+                       "inv_focal_length": 0.5, "aperture": 0.0, "horiz_res": 4, "vert_res": 4},
+            "hittables": [
+                {"shape": {"Quad": {"corner": [-2.0, 2.0, -2.0], "edge_u": [1.0, 0.0, 0.0],
+                                    "edge_v": [0.0, 0.0, 1.0]}},
+                 "material": {"Emitter": {"albedo": "#ffffff", "intensity": 1.0}}},
+                {"shape": {"Sphere": {"centre": [0.0, 0.0, 0.0], "radius": 1.0}},
+                 "material": {"Diffuse": {"albedo": "#808080"}}},
+                {"shape": {"Quad": {"corner": [1.0, 2.0, 1.0], "edge_u": [1.0, 0.0, 0.0],
+                                    "edge_v": [0.0, 0.0, 1.0]}},
+                 "material": {"Emitter": {"albedo": "#ffffff", "intensity": 1.0}}}
+            ],
+            "lights": [2],
+            "settings": {"light_sampling": true}
+        }"##;
+        let config = crate::config::Config::from_slice(contents).expect("scene should load");
+        let scene = config.hittables;
+        let lights = sampled_lights(scene.iter().enumerate(), &config.settings);
+        assert_eq!(lights.len(), 1);
+        assert!(std::ptr::eq(lights[0], &scene[2]));
+
+        // the tree reorders the hittables but the same one is picked out
+        let covered = CoveredScene::new(scene);
+        let lights = sampled_lights(covered.hittables_with_sources(), &config.settings);
+        assert_eq!(lights.len(), 1);
+        match &lights[0].shape {
+            Shape::Quad(quad) => assert_eq!(quad.corner[0], 1.0),
+            _ => panic!("a quad should be sampled"),
+        }
+
+        // without the list both emitters are sampled
+        let undesignated = String::from_utf8_lossy(contents).replace(r#""lights": [2],"#, "");
+        let config = crate::config::Config::from_slice(undesignated.as_bytes()).unwrap();
+        assert_eq!(
+            sampled_lights(config.hittables.iter().enumerate(), &config.settings).len(),
+            2
+        );
+
+        // a light has to be an emitter
+        let diffuse = String::from_utf8_lossy(contents).replace("[2]", "[1]");
+        assert!(crate::config::Config::from_slice(diffuse.as_bytes()).is_err());
+        let missing = String::from_utf8_lossy(contents).replace("[2]", "[3]");
+        assert!(crate::config::Config::from_slice(missing.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn all_hits_test() {
+        let sphere_at = |x: f64| Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([x, 0.0, 0.0]), 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(1.0, 1.0, 1.0).into(),
+            },
+        };
+        let scene = vec![sphere_at(1.5), sphere_at(0.0)];
+        let ray = Ray::new(Vec3([-5.0, 0.0, 0.0]), Vec3([1.0, 0.0, 0.0]));
+
+        let hits = scene_all_hits(&scene, &ray, geometry::DEFAULT_EPSILON);
+        let params: Vec<f64> = hits.iter().map(|(_, param)| *param).collect();
+        assert_eq!(params, [4.0, 5.5, 6.0, 7.5]);
+        // in at the first sphere, in at the second, out of each in turn
+        let order: Vec<*const Hittable> = hits
+            .iter()
+            .map(|(hittable, _)| *hittable as *const _)
+            .collect();
+        assert_eq!(
+            order,
+            [&scene[1], &scene[0], &scene[1], &scene[0]].map(|h| h as *const _)
+        );
+
+        let covered = CoveredScene::new(vec![sphere_at(1.5), sphere_at(0.0)]);
+        let accel_params: Vec<f64> = covered
+            .all_hits(&ray, geometry::DEFAULT_EPSILON)
+            .iter()
+            .map(|(_, param)| *param)
+            .collect();
+        assert_eq!(accel_params, params);
+        // starting inside the first sphere misses where the ray went into it
+        assert_eq!(scene_all_hits(&scene, &ray, 4.5).len(), 3);
+    }
+
+    #[test]
+    fn black_surface_test() {
+        let scene = [Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(0.0, 0.0, 0.0).into(),
+            },
+        }];
+        let settings = RenderSettings::default();
+        let ray = Ray::new(Vec3([0.0, 0.0, 3.0]), Vec3([0.0, 0.0, -1.0]));
+        let traced = std::cell::Cell::new(0);
+        let (color, alpha) = trace_path(
+            &ray,
+            &settings,
+            &LightSampler::new(Vec::new()),
+            |ray| {
+                traced.set(traced.get() + 1);
+                nearest_hit(&scene, ray, settings.epsilon)
+            },
+            |_, _, _| Color::new(1.0, 1.0, 1.0),
+        );
+        assert_eq!((color.r, color.g, color.b, alpha), (0.0, 0.0, 0.0, 1.0));
+        assert_eq!(traced.get(), 1);
+    }
+
+    #[test]
+    fn one_sided_emitter_test() {
+        let scene = [Hittable {
+            shape: Shape::Disc(geometry::Disc::new(
+                Vec3([0.0, 0.0, 0.0]),
+                Vec3([0.0, 0.0, 1.0]),
+                1.0,
+            )),
+            material: Material::Emitter {
+                albedo: Color::new(1.0, 1.0, 1.0),
+                intensity: 1.0,
+            },
+        }];
+        let settings = RenderSettings {
+            background: Background::Solid(Color::new(0.0, 0.0, 1.0)),
+            ..Default::default()
+        };
+        let front = raytrace(
+            &Ray::new(Vec3([0.0, 0.0, 2.0]), Vec3([0.0, 0.0, -1.0])),
+            &scene,
+            &settings,
+        );
+        assert_eq!((front.r, front.g, front.b), (1.0, 1.0, 1.0));
+        // the disc still hides the background behind it
+        let back = raytrace(
+            &Ray::new(Vec3([0.0, 0.0, -2.0]), Vec3([0.0, 0.0, 1.0])),
+            &scene,
+            &settings,
+        );
+        assert_eq!((back.r, back.g, back.b), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn path_throughput_test() {
+        let disc = |z: f64, facing: f64, material: Material| Hittable {
+            shape: Shape::Disc(geometry::Disc::new(
+                Vec3([0.0, 0.0, z]),
+                Vec3([0.0, 0.0, facing]),
+                2.0,
+            )),
+            material,
+        };
+        let tint = Color::new(0.5, 0.25, 1.0);
+        let settings = RenderSettings {
+            background: Background::Solid(Color::new(1.0, 1.0, 1.0)),
+            ..Default::default()
+        };
+        let ray = Ray::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 0.0, 1.0]));
+
+        // a white light seen in a tinted mirror takes on the tint
+        let seen_in_mirror = [
+            disc(5.0, -1.0, Material::Mirror { tint }),
+            disc(
+                -2.0,
+                1.0,
+                Material::Emitter {
+                    albedo: Color::new(1.0, 1.0, 1.0),
+                    intensity: 1.0,
+                },
+            ),
+        ];
+        let color = raytrace(&ray, &seen_in_mirror, &settings);
+        assert_eq!((color.r, color.g, color.b), (0.5, 0.25, 1.0));
+
+        // between two facing mirrors the path runs out of depth without
+        // reaching any light, and the white background mustn't leak in
+        let facing_mirrors = [
+            disc(5.0, -1.0, Material::Mirror { tint }),
+            disc(-5.0, 1.0, Material::Mirror { tint }),
+        ];
+        let color = raytrace(&ray, &facing_mirrors, &settings);
+        assert_eq!((color.r, color.g, color.b), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn direct_only_test() {
+        let scene = [
+            Hittable {
+                shape: Shape::Quad(geometry::Quad::new(
+                    Vec3([-5.0, 0.0, -5.0]),
+                    Vec3([0.0, 0.0, 10.0]),
+                    Vec3([10.0, 0.0, 0.0]),
+                )),
+                material: Material::Diffuse {
+                    albedo: Color::new(0.5, 0.5, 0.5).into(),
+                },
+            },
+            Hittable {
+                shape: Shape::Quad(geometry::Quad::new(
+                    Vec3([-0.5, 2.0, -0.5]),
+                    Vec3([1.0, 0.0, 0.0]),
+                    Vec3([0.0, 0.0, 1.0]),
+                )),
+                material: Material::Emitter {
+                    albedo: Color::new(1.0, 1.0, 1.0),
+                    intensity: 10.0,
+                },
+            },
+        ];
+        let ray = Ray::new(Vec3([0.0, 1.0, -3.0]), Vec3([0.0, -1.0, 3.0]));
+
+        const SAMPLES: usize = 20000;
+        let estimate = |settings: RenderSettings| {
+            let settings = RenderSettings {
+                background: Background::None,
+                ..settings
+            };
+            (0..SAMPLES)
+                .map(|_| raytrace(&ray, &scene, &settings).r)
+                .sum::<f64>()
+                / SAMPLES as f64
+        };
+        let direct_only = estimate(RenderSettings {
+            direct_only: true,
+            light_sampling: true,
+            ..Default::default()
+        });
+        // a single bounce ends on the floor before anything reaches the light
+        let depth_one = estimate(RenderSettings {
+            scatter_depth: 1,
+            ..Default::default()
+        });
+        assert_eq!(depth_one, 0.0);
+        assert!(direct_only > depth_one);
+
+        // as only the floor scatters, every path of two bounces is direct
+        let depth_two = estimate(RenderSettings {
+            scatter_depth: 2,
+            ..Default::default()
+        });
+        assert!(
+            (direct_only - depth_two).abs() < 0.1 * depth_two,
+            "{} against {}",
+            direct_only,
+            depth_two
+        );
+    }
+
+    #[test]
+    fn seeded_noise_test() {
+        let emitter = |centre: Vec3, radius: f64| Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(centre, radius)),
+            material: Material::Emitter {
+                albedo: Color::new(1.0, 1.0, 1.0),
+                intensity: 1.0,
+            },
+        };
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -4.0]),
+            0.5,
+            0.2,
+            8,
+            4,
+        );
+        let settings = RenderSettings {
+            background: Background::Solid(Color::new(0.0, 0.0, 1.0)),
+            seed: Some(461),
+            ..Default::default()
+        };
+        let render = |scene: &[Hittable]| {
+            let mut buffer = Vec::<u8>::new();
+            render_into_file(&mut buffer, &cam, scene, &settings, 4);
+            let ppm = String::from_utf8(buffer).expect("ppm output should be ascii");
+            ppm.lines()
+                .skip(3)
+                .map(String::from)
+                .collect::<Vec<String>>()
+        };
+
+        // the edge of the right sphere is noisy from the lens and pixel jitter
+        let right = emitter(Vec3([0.5, 0.0, 0.0]), 0.4);
+        let before = render(&[right]);
+        let right = emitter(Vec3([0.5, 0.0, 0.0]), 0.4);
+        let after = render(&[right, emitter(Vec3([-0.6, 0.0, 0.0]), 0.3)]);
+        for row in 0..4 {
+            // only the left half of the image can see the added sphere
+            assert_eq!(
+                before[8 * row + 4..8 * row + 8],
+                after[8 * row + 4..8 * row + 8]
+            );
+        }
+        assert_ne!(before, after);
+    }
+
+    #[test]
+    fn ray_footprint_test() {
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -4.0]),
+            0.5,
+            0.0,
+            100,
+            50,
+        );
+        // the two pixels either side of the centre column
+        let (left, right) = (primary_ray(&cam, 49, 25), primary_ray(&cam, 50, 25));
+        for t in [2.0, 4.0, 8.0] {
+            let gap = (right.position_at(t) - left.position_at(t)).norm();
+            assert!((left.footprint_at(t) - gap).abs() < 1e-3 * gap);
+        }
+        // one pixel wide on the focal plane and twice that beyond it
+        assert!((left.footprint_at(4.0) - 0.5 * 4.0 / 100.0).abs() < 1e-12);
+        assert_eq!(left.footprint_at(8.0), 2.0 * left.footprint_at(4.0));
+    }
+
+    #[test]
+    fn wireframe_test() {
+        // the quad just fills the view, with pixel centres 0.1 and 0.3 of
+        // the way across it either side of the middle one
+        let scene = [Hittable {
+            shape: Shape::Quad(geometry::Quad::new(
+                Vec3([-1.0, -1.0, 0.0]),
+                Vec3([2.0, 0.0, 0.0]),
+                Vec3([0.0, 2.0, 0.0]),
+            )),
+            material: Material::UVDebug,
+        }];
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -4.0]),
+            0.5,
+            0.0,
+            5,
+            5,
+        );
+        let wireframe = render_wireframe(&cam, &scene, 0.15);
+        assert_eq!(wireframe.get_pixel(0, 2).0, [0]);
+        assert_eq!(wireframe.get_pixel(2, 4).0, [0]);
+        assert_eq!(wireframe.get_pixel(1, 2).0, [255]);
+        assert_eq!(wireframe.get_pixel(2, 2).0, [255]);
+
+        let no_scene: [Hittable; 0] = [];
+        assert_eq!(
+            render_wireframe(&cam, &no_scene, 0.15).get_pixel(2, 2).0,
+            [128]
+        );
+    }
+
+    #[test]
+    fn ambient_test() {
+        let scene = [Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(0.5, 0.5, 0.5).into(),
+            },
+        }];
+        let ray = Ray::new(Vec3([0.0, 0.0, -5.0]), Vec3([0.0, 0.0, 1.0]));
+        let unlit = RenderSettings {
+            background: Background::None,
+            ..Default::default()
+        };
+        assert_eq!(raytrace(&ray, &scene, &unlit).r, 0.0);
+
+        // a convex sphere only reflects the ambient light once
+        let ambient = RenderSettings {
+            ambient: Color::new(0.2, 0.4, 0.0),
+            ..unlit
+        };
+        let color = raytrace(&ray, &scene, &ambient);
+        assert_eq!((color.r, color.g, color.b), (0.1, 0.2, 0.0));
+    }
+
+    #[test]
+    fn shadow_through_glass_test() {
+        let glass = [Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, 2.0, 0.0]), 1.0)),
+            material: Material::Dielectric {
+                refractive_index: 1.5,
+                tint: Color::new(1.0, 1.0, 1.0),
+            },
+        }];
+        let floor = Vec3([0.0, 0.0, 0.0]);
+        let light = Vec3([0.0, 4.0, 0.0]);
+
+        // normal incidence loses 4% at each of the two surfaces
+        let through_glass = shadow_transmittance(&glass, floor, light);
+        assert!((through_glass.r - 0.96 * 0.96).abs() < 1.0e-9);
+
+        let opaque = [Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, 2.0, 0.0]), 1.0)),
+            material: Material::Diffuse {
+                albedo: Color::new(0.8, 0.8, 0.8).into(),
+            },
+        }];
+        assert_eq!(shadow_transmittance(&opaque, floor, light).r, 0.0);
+        assert_eq!(shadow_transmittance(&[], floor, light).r, 1.0);
+
+        // tinted glass takes off its tint over each unit length inside it
+        let tinted = [Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, 2.0, 0.0]), 1.0)),
+            material: Material::Dielectric {
+                refractive_index: 1.5,
+                tint: Color::new(1.0, 0.5, 0.25),
+            },
+        }];
+        let through_tint = shadow_transmittance(&tinted, floor, light);
+        assert!((through_tint.r - 0.96 * 0.96).abs() < 1.0e-9);
+        assert!((through_tint.g - 0.96 * 0.96 * 0.25).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn glass_pane_shadow_test() {
+        let make_scene = || {
+            vec![
+                Hittable {
+                    shape: Shape::Quad(geometry::Quad::new(
+                        Vec3([-5.0, 0.0, -5.0]),
+                        Vec3([0.0, 0.0, 10.0]),
+                        Vec3([10.0, 0.0, 0.0]),
+                    )),
+                    material: Material::Diffuse {
+                        albedo: Color::new(0.5, 0.5, 0.5).into(),
+                    },
+                },
+                Hittable {
+                    shape: Shape::Quad(geometry::Quad::new(
+                        Vec3([-1.0, 3.0, -1.0]),
+                        Vec3([2.0, 0.0, 0.0]),
+                        Vec3([0.0, 0.0, 2.0]),
+                    )),
+                    material: Material::Emitter {
+                        albedo: Color::new(1.0, 1.0, 1.0),
+                        intensity: 1.0,
+                    },
+                },
+                // a slab of glass a unit thick between the floor and the light
+                Hittable {
+                    shape: Shape::Cuboid(geometry::Cuboid::new([
+                        intervals::Interval::new(-2.0, 2.0),
+                        intervals::Interval::new(1.0, 2.0),
+                        intervals::Interval::new(-2.0, 2.0),
+                    ])),
+                    material: Material::Dielectric {
+                        refractive_index: 1.5,
+                        tint: Color::new(1.0, 0.5, 0.25),
+                    },
+                },
+            ]
+        };
+        let scene = make_scene();
+        let covered_scene = CoveredScene::new(make_scene());
+        // under the pane, looking at the floor right below the light
+        let ray = Ray::new(Vec3([-3.0, 0.5, 0.0]), Vec3([3.0, -0.5, 0.0]));
+        let samples = 20000;
+        let mean = |light_sampling: bool, covered: bool| {
+            sampler::seed_pixel(0, 0, 419);
+            let settings = RenderSettings {
+                background: Background::None,
+                light_sampling,
+                ..Default::default()
+            };
+            let total = (0..samples)
+                .map(|_| match covered {
+                    true => accel_raytrace(&ray, &covered_scene, &settings),
+                    false => raytrace(&ray, &scene, &settings),
+                })
+                .fold(Color::new(0.0, 0.0, 0.0), |sum, color| sum + color);
+            (1.0 / samples as f64) * total
+        };
+        // the light through the pane is brought by the paths refracting
+        // through it, whether or not the lights are sampled, and tinted
+        let unweighted = mean(false, false);
+        assert!(unweighted.r > 0.0 && unweighted.b < unweighted.r);
+        for covered in [false, true] {
+            let sampled = mean(true, covered);
+            for (sampled, unweighted) in [sampled.r, sampled.g, sampled.b].into_iter().zip([
+                unweighted.r,
+                unweighted.g,
+                unweighted.b,
+            ]) {
+                assert!(
+                    (sampled / unweighted - 1.0).abs() < 0.05,
+                    "{} against {}",
+                    sampled,
+                    unweighted
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn no_antialiasing_golden_test() {
+        let scene = [Hittable {
+            shape: Shape::Sphere(geometry::Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Emitter {
+                albedo: Color::new(1.0, 0.5, 0.25),
+                intensity: 1.0,
+            },
+        }];
+        let cam = camera::Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -4.0]),
+            0.5,
+            0.5,
+            4,
+            4,
+        );
+        let settings = RenderSettings {
+            antialiasing: false,
+            background: Background::Solid(Color::new(0.0, 0.0, 1.0)),
+            ..Default::default()
+        };
+
+        let mut buffer = Vec::<u8>::new();
+        render_into_file(&mut buffer, &cam, &scene, &settings, 16);
+
+        let golden = "P3\n4 4\n255\n\
+            0 0 255\n208 152 111\n208 152 111\n0 0 255\n\
+            208 152 111\n248 182 133\n248 182 133\n208 152 111\n\
+            208 152 111\n248 182 133\n248 182 133\n208 152 111\n\
+            0 0 255\n208 152 111\n208 152 111\n0 0 255\n";
+        assert_eq!(String::from_utf8(buffer).unwrap(), golden);
+    }
 }
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::Hittable;
+
+// picks the lights to sample with probability in proportion to the power
+// they give off, so that in a scene of many lights the few bright ones are
+// sampled more often than the many dim ones, along with that probability
+// for the estimate to be divided by
+pub struct LightSampler<'a> {
+    lights: Vec<&'a Hittable>,
+    // the running sum of the probabilities, the last being 1
+    cdf: Vec<f64>,
+    // the index of each light by its address, as every emitter the paths
+    // hit is looked up, kept as a number since a pointer isn't Sync
+    indices: HashMap<usize, usize>,
+}
+
+impl<'a> LightSampler<'a> {
+    pub fn new(lights: Vec<&'a Hittable>) -> LightSampler<'a> {
+        let powers: Vec<f64> = lights.iter().map(|light| power(light)).collect();
+        let total: f64 = powers.iter().sum();
+        // with no power to go on every light is as likely
+        let weights = if total > 0.0 && total.is_finite() {
+            powers.iter().map(|power| power / total).collect()
+        } else {
+            vec![1.0 / lights.len() as f64; lights.len()]
+        };
+        let cdf = weights
+            .iter()
+            .scan(0.0, |sum, weight| {
+                *sum += weight;
+                Some(*sum)
+            })
+            .collect();
+        let indices = lights
+            .iter()
+            .enumerate()
+            .map(|(index, light)| (address(light), index))
+            .collect();
+        LightSampler {
+            lights,
+            cdf,
+            indices,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn lights(&self) -> &[&'a Hittable] {
+        &self.lights
+    }
+
+    // a light and the probability it had of being picked, None when there
+    // are no lights to pick from
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(&'a Hittable, f64)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let u: f64 = rng.gen();
+        // rounding may leave the last sum just short of 1
+        let index = self
+            .cdf
+            .partition_point(|sum| *sum <= u)
+            .min(self.lights.len() - 1);
+        Some((self.lights[index], self.probability_of(index)))
+    }
+
+    // the probability light is picked, 0 when it isn't one of the lights
+    pub fn probability(&self, light: &Hittable) -> f64 {
+        self.indices
+            .get(&address(light))
+            .map_or(0.0, |index| self.probability_of(*index))
+    }
+
+    fn probability_of(&self, index: usize) -> f64 {
+        let below = if index == 0 { 0.0 } else { self.cdf[index - 1] };
+        self.cdf[index] - below
+    }
+}
+
+fn address(light: &Hittable) -> usize {
+    light as *const Hittable as usize
+}
+
+// the power given off by a lambertian light, up to the factor of pi
+// shared by them all, from both faces when it has two
+fn power(light: &Hittable) -> f64 {
+    let sides = if light.shape.is_one_sided() { 1.0 } else { 2.0 };
+    let area = light.shape.area().unwrap_or(0.0);
+    sides * area * light.material.radiance().luminance()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::geometry::{Quad, Shape};
+    use crate::materials::Material;
+    use crate::vector::Vec3;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn power_proportional_test() {
+        let panel = |x: f64, intensity: f64| Hittable {
+            shape: Shape::Quad(Quad::new(
+                Vec3([x, 2.0, 0.0]),
+                Vec3([1.0, 0.0, 0.0]),
+                Vec3([0.0, 0.0, 1.0]),
+            )),
+            material: Material::Emitter {
+                albedo: Color::new(1.0, 1.0, 1.0),
+                intensity,
+            },
+        };
+        let (dim, bright) = (panel(0.0, 1.0), panel(2.0, 10.0));
+        let sampler = LightSampler::new(vec![&dim, &bright]);
+        assert!((sampler.probability(&bright) - 10.0 / 11.0).abs() < 1.0e-12);
+        assert!((sampler.probability(&dim) - 1.0 / 11.0).abs() < 1.0e-12);
+        assert_eq!(sampler.probability(&panel(0.0, 1.0)), 0.0);
+
+        const SAMPLES: usize = 110_000;
+        let mut rng = StdRng::seed_from_u64(499);
+        let (mut bright_picks, mut lights_estimate) = (0, 0.0);
+        for _ in 0..SAMPLES {
+            let (light, probability) = sampler.sample(&mut rng).unwrap();
+            assert_eq!(probability, sampler.probability(light));
+            if std::ptr::eq(light, &bright) {
+                bright_picks += 1;
+            }
+            lights_estimate += 1.0 / probability;
+        }
+        let ratio = bright_picks as f64 / (SAMPLES - bright_picks) as f64;
+        assert!((ratio - 10.0).abs() < 0.5, "{}", ratio);
+        // dividing by the probability keeps the estimate fair, here of the
+        // number of lights
+        let lights_estimate = lights_estimate / SAMPLES as f64;
+        assert!((lights_estimate - 2.0).abs() < 0.05, "{}", lights_estimate);
+
+        // lights giving off nothing are picked evenly
+        let (off, also_off) = (panel(0.0, 0.0), panel(2.0, 0.0));
+        let dark = LightSampler::new(vec![&off, &also_off]);
+        assert_eq!(dark.probability_of(1), 0.5);
+
+        // and without any there is nothing to pick
+        let unlit = LightSampler::new(Vec::new());
+        assert!(unlit.sample(&mut rng).is_none());
+        assert_eq!(unlit.probability(&dim), 0.0);
+    }
+}
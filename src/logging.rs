@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// messages are shown when their level is at or below the configured one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Warn,
+    Info,
+    Debug,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+impl Level {
+    // the level for the -v/--verbose command line flag
+    pub fn from_verbose(verbose: bool) -> Level {
+        if verbose {
+            Level::Debug
+        } else {
+            Level::Info
+        }
+    }
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Warn,
+        1 => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= self::level()
+}
+
+#[macro_export]
+macro_rules! warn {
+    ( $($arg:tt)* ) => {
+        if $crate::logging::enabled($crate::logging::Level::Warn) {
+            eprintln!("warning: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ( $($arg:tt)* ) => {
+        if $crate::logging::enabled($crate::logging::Level::Info) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ( $($arg:tt)* ) => {
+        if $crate::logging::enabled($crate::logging::Level::Debug) {
+            eprintln!($($arg)*);
+        }
+    };
+}
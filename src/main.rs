@@ -1,62 +1,236 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::fs;
 use std::fs::OpenOptions;
-use std::io::prelude::*;
+use std::io::Write;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
+use raytracer::background::Background;
+use raytracer::boundingvolume::{CoveredScene, CoveringTree};
+use raytracer::camera::Camera;
+use raytracer::color::Color;
 use raytracer::config::Config;
+use raytracer::film::PixelAccumulator;
+use raytracer::logging::{self, Level};
+use raytracer::materials::Material;
 use raytracer::scenegen;
+use raytracer::settings::RenderSettings;
+use raytracer::summary::{self, SceneSummary};
+use raytracer::Hittable;
+use raytracer::{debug, info};
 
 enum SceneType {
-    Scene(Box<[raytracer::Hittable]>),
-    Tree(Box<raytracer::boundingvolume::CoveringTree>),
+    Scene(Box<[Hittable]>),
+    Tree(CoveredScene),
 }
 
+// scenes with fewer hittables than this are traced without the covering
+// tree by default, as walking it costs more than testing a handful directly
+const DEFAULT_BVH_THRESHOLD: usize = 8;
+
+// covers the scene unless it is smaller than bvh_threshold, None never covers
+fn select_scene(
+    hittables: Vec<Hittable>,
+    bvh_threshold: Option<usize>,
+    scene_scale: f64,
+) -> SceneType {
+    match bvh_threshold {
+        Some(threshold) if hittables.len() >= threshold => {
+            SceneType::Tree(CoveredScene::with_scale(hittables, scene_scale))
+        }
+        _ => SceneType::Scene(hittables.into()),
+    }
+}
+
+fn render_ppm<W: Write>(
+    file: &mut W,
+    scene: &SceneType,
+    cam: &Camera,
+    settings: &RenderSettings,
+    spp: u32,
+) {
+    match scene {
+        SceneType::Scene(scene) => raytracer::render_into_file(file, cam, scene, settings, spp),
+        SceneType::Tree(covered) => {
+            raytracer::accel_render_into_file(file, cam, covered, settings, spp)
+        }
+    }
+}
+
+// the scenes that can be rendered without a scene.json
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum BuiltinScene {
+    File,
+    Random,
+    Debug,
+    Cornell,
+}
+
+fn load_scene(name: BuiltinScene) -> (Vec<Hittable>, Camera, RenderSettings) {
+    match name {
+        BuiltinScene::File => {
+            let de_config =
+                Config::from_path("./scene.json").expect("unable to deserialize scene information");
+
+            (
+                de_config.hittables,
+                de_config.camera.setup(),
+                de_config.settings,
+            )
+        }
+        BuiltinScene::Random => (
+            scenegen::gen_hittables_from(&mut rand::thread_rng()),
+            scenegen::default_camera(),
+            RenderSettings::default(),
+        ),
+        BuiltinScene::Debug => (
+            scenegen::debug_hittables(),
+            scenegen::debug_camera(),
+            RenderSettings::default(),
+        ),
+        BuiltinScene::Cornell => {
+            let (hittables, cam) = scenegen::cornell_box();
+            // the box is only lit from its ceiling
+            let settings = RenderSettings {
+                background: Background::None,
+                ..Default::default()
+            };
+            (hittables, cam, settings)
+        }
+    }
+}
+
+// where the rendered ppm is written
+const OUTPUT_PPM: &str = "./image.ppm";
+
 fn main() {
     let cli_args = Cli::parse();
-    let spp: u32 = cli_args.samples_per_pixel; // samples per pixel, default set at 10
+    logging::set_level(Level::from_verbose(cli_args.verbose));
+    run(&cli_args, Path::new(OUTPUT_PPM));
+}
 
-    let scene: SceneType;
-    let cam: raytracer::camera::Camera;
+// the work a render would do, from the loaded and covered scene
+fn dry_run_report(
+    summary: &SceneSummary,
+    scene: &SceneType,
+    cam: &Camera,
+    settings: &RenderSettings,
+    spp: u32,
+) -> String {
+    let (tree, tree_nodes) = match scene {
+        SceneType::Tree(CoveredScene {
+            tree: Some(tree), ..
+        }) => (
+            format!(
+                "Covering tree of depth {} with {} nodes",
+                tree.depth(),
+                tree.node_count()
+            ),
+            tree.node_count(),
+        ),
+        _ => (String::from("No covering tree"), 0),
+    };
+    let (columns, rows) = settings.region(cam);
+    let pixels = columns.len() * rows.len();
+    let samples_per_pixel = if settings.antialiasing { spp } else { 1 };
+    // textures are left out, they depend on the image files
+    let memory = summary.hittables * mem::size_of::<Hittable>()
+        + tree_nodes * mem::size_of::<CoveringTree>()
+        + pixels * mem::size_of::<PixelAccumulator>();
+    format!(
+        "{}\n{}\n{} pixels at {} samples, {} camera rays\nAbout {:.1} MiB without textures",
+        summary,
+        tree,
+        pixels,
+        samples_per_pixel,
+        pixels as u64 * u64::from(samples_per_pixel),
+        memory as f64 / (1024.0 * 1024.0)
+    )
+}
 
-    if cli_args.random_scene {
-        scene = SceneType::Tree(scenegen::gen_scene());
-        cam = scenegen::default_camera();
-    } else {
-        let config_contents = fs::read("./scene.json").expect("unable to read scene file");
+// renders as the command line asks, writing the ppm to ppm_path
+fn run(cli_args: &Cli, ppm_path: &Path) {
+    let spp: u32 = cli_args.samples_per_pixel; // samples per pixel, default set at 10
 
-        let de_config = serde_json::from_slice::<Config>(&config_contents)
-            .expect("unable to deserialize scene information");
+    let load_timer = Instant::now();
+    let (hittables, cam, mut settings) = match &cli_args.swatch {
+        Some(material_file) => load_swatch(material_file),
+        None => load_scene(cli_args.scene_name()),
+    };
 
-        scene = SceneType::Scene(de_config.hittables.into());
-        cam = de_config.camera.setup();
+    if cli_args.stats {
+        info!("{}", summary::scene_summary(&hittables));
+        return;
     }
 
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open("./image.ppm")
-        .expect("Unable to open file to write");
+    let summary = cli_args.dry_run.then(|| summary::scene_summary(&hittables));
+    let scene = select_scene(hittables, cli_args.bvh_threshold(), settings.scene_scale);
+    match &scene {
+        SceneType::Scene(scene) => debug!("Loaded {} hittables", scene.len()),
+        SceneType::Tree(covered) => debug!(
+            "Built covering tree of depth {}",
+            covered.tree.as_ref().map_or(0, |tree| tree.depth())
+        ),
+    }
+    debug!("Scene set up in {}ms", load_timer.elapsed().as_millis());
+    if let Some(summary) = summary {
+        info!("{}", dry_run_report(&summary, &scene, &cam, &settings, spp));
+        return;
+    }
 
-    let header = format!("P3\n{} {}\n255\n", &cam.horiz_res, &cam.vert_res);
-    write!(file, "{}", header).expect("Unable to write header to ppm");
+    if settings.caustics.is_some() {
+        let prepass_timer = Instant::now();
+        match &scene {
+            SceneType::Scene(scene) => raytracer::caustics_prepass(scene, &mut settings),
+            SceneType::Tree(covered) => raytracer::accel_caustics_prepass(covered, &mut settings),
+        }
+        debug!(
+            "Caustic photons traced in {}ms",
+            prepass_timer.elapsed().as_millis()
+        );
+    }
 
     // Render
-    println!("Starting render...");
-    println!(
+    info!("Starting render...");
+    info!(
         "Computing with {} samples",
         &cam.horiz_res * &cam.vert_res * spp
     );
     let timer = Instant::now();
-    match scene {
-        SceneType::Scene(scene) => {
-            raytracer::render_into_file(&mut file, &cam, &*scene, spp);
-        }
-        SceneType::Tree(tree) => {
-            raytracer::accel_render_into_file(&mut file, &cam, tree, spp);
-        }
+    if let Some(png_file) = &cli_args.png {
+        let image = match scene {
+            SceneType::Scene(scene) => raytracer::render_rgba_image(&cam, &scene, &settings, spp),
+            SceneType::Tree(covered) => {
+                raytracer::accel_render_rgba_image(&cam, &covered, &settings, spp)
+            }
+        };
+        image.save(png_file).expect("Unable to write png");
+        info!("Render finished in {}s", timer.elapsed().as_secs());
+        return;
     }
-    println!("Render finished in {}s", timer.elapsed().as_secs());
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(ppm_path)
+        .expect("Unable to open file to write");
+    render_ppm(&mut file, &scene, &cam, &settings, spp);
+    info!("Render finished in {}s", timer.elapsed().as_secs());
+}
+
+// the material preview for a json file holding a single material
+fn load_swatch(material_file: &Path) -> (Vec<Hittable>, Camera, RenderSettings) {
+    let contents = fs::read(material_file).expect("unable to read material file");
+    let material: Material =
+        serde_json::from_slice(&contents).expect("unable to deserialize material");
+    let (hittables, cam) = scenegen::material_swatch(material);
+    // the studio is dark apart from its two lights
+    let settings = RenderSettings {
+        background: Background::Solid(Color::new(0.05, 0.05, 0.05)),
+        ..Default::default()
+    };
+    (hittables, cam, settings)
 }
 
 #[derive(Parser)]
@@ -64,6 +238,152 @@ fn main() {
 pub struct Cli {
     #[arg(short = 's', long = "samples", default_value_t = 10)]
     pub samples_per_pixel: u32,
+    // shorthand for --scene random
     #[arg(short = 'r', long = "random")]
     pub random_scene: bool,
+    #[arg(long = "scene", value_name = "NAME", value_enum, default_value_t = BuiltinScene::File)]
+    pub scene: BuiltinScene,
+    // renders a preview of the material in the file instead of a scene
+    #[arg(long = "swatch", value_name = "MATERIAL_FILE")]
+    pub swatch: Option<PathBuf>,
+    // prints the make up of the scene instead of rendering it
+    #[arg(long = "stats")]
+    pub stats: bool,
+    // loads and covers the scene, then reports what rendering it would take
+    // without tracing anything
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+    // writes an rgba png there instead of image.ppm, transparent wherever
+    // the camera rays escaped
+    #[arg(long = "png", value_name = "FILE")]
+    pub png: Option<PathBuf>,
+    // scenes with fewer hittables are traced without the covering tree
+    #[arg(long = "bvh-threshold", value_name = "COUNT", default_value_t = DEFAULT_BVH_THRESHOLD)]
+    pub bvh_threshold: usize,
+    // never build the covering tree, whatever the size of the scene
+    #[arg(long = "no-bvh")]
+    pub no_bvh: bool,
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+impl Cli {
+    fn bvh_threshold(&self) -> Option<usize> {
+        if self.no_bvh {
+            None
+        } else {
+            Some(self.bvh_threshold)
+        }
+    }
+
+    fn scene_name(&self) -> BuiltinScene {
+        if self.random_scene {
+            BuiltinScene::Random
+        } else {
+            self.scene
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbose_flag_test() {
+        let quiet = Cli::parse_from(["raytracer"]);
+        assert_eq!(Level::from_verbose(quiet.verbose), Level::Info);
+
+        let verbose = Cli::parse_from(["raytracer", "-v"]);
+        logging::set_level(Level::from_verbose(verbose.verbose));
+        assert_eq!(logging::level(), Level::Debug);
+        assert!(logging::enabled(Level::Debug));
+    }
+
+    #[test]
+    fn scene_selector_test() {
+        assert_eq!(
+            Cli::parse_from(["raytracer"]).scene_name(),
+            BuiltinScene::File
+        );
+        assert_eq!(
+            Cli::parse_from(["raytracer", "-r"]).scene_name(),
+            BuiltinScene::Random
+        );
+        assert!(Cli::try_parse_from(["raytracer", "--scene", "nonsense"]).is_err());
+
+        let cli = Cli::parse_from(["raytracer", "--scene", "debug", "--bvh-threshold", "1"]);
+        let (hittables, _, _) = load_scene(cli.scene_name());
+        let SceneType::Tree(covered) = select_scene(hittables, cli.bvh_threshold(), 1.0) else {
+            panic!("the debug scene is covered")
+        };
+        let tree = covered.tree.expect("the debug sphere has a bounding box");
+        assert_eq!(tree.depth(), 1);
+        assert!(tree.cover.is_some());
+        assert!(covered.uncovered.is_empty());
+    }
+
+    #[test]
+    fn dry_run_test() {
+        let ppm_path = std::env::temp_dir().join("raytracer_dry_run_test.ppm");
+        let _ = fs::remove_file(&ppm_path);
+        let cli = Cli::parse_from(["raytracer", "--scene", "debug", "--dry-run"]);
+        run(&cli, &ppm_path);
+        assert!(!ppm_path.exists());
+
+        let (hittables, cam, settings) = load_scene(cli.scene_name());
+        let summary = summary::scene_summary(&hittables);
+        let scene = select_scene(hittables, Some(1), 1.0);
+        let report = dry_run_report(&summary, &scene, &cam, &settings, 4);
+        assert!(report.contains("Covering tree of depth 1 with 1 nodes"));
+        let rays = cam.horiz_res * cam.vert_res * 4;
+        assert!(report.contains(&format!("{} camera rays", rays)));
+    }
+
+    #[test]
+    fn bvh_threshold_test() {
+        let make_scene = || {
+            let hittables = br##"[
+                {"shape": {"Sphere": {"centre": [-2.0, 0.0, 0.0], "radius": 0.8}},
+                 "material": {"Emitter": {"albedo": "#ff8040", "intensity": 1.0}}},
+                {"shape": {"Sphere": {"centre": [0.0, 0.0, 0.0], "radius": 0.8}},
+                 "material": {"Emitter": {"albedo": "#ff8040", "intensity": 1.0}}},
+                {"shape": {"Sphere": {"centre": [2.0, 0.0, 0.0], "radius": 0.8}},
+                 "material": {"Emitter": {"albedo": "#ff8040", "intensity": 1.0}}}
+            ]"##;
+            serde_json::from_slice::<Vec<Hittable>>(hittables)
+                .expect("hittables should deserialize")
+        };
+        let default = Cli::parse_from(["raytracer"]);
+        assert!(matches!(
+            select_scene(make_scene(), default.bvh_threshold(), 1.0),
+            SceneType::Scene(_)
+        ));
+        let low = Cli::parse_from(["raytracer", "--bvh-threshold", "3"]);
+        let covered = select_scene(make_scene(), low.bvh_threshold(), 1.0);
+        assert!(matches!(covered, SceneType::Tree(_)));
+        let never = Cli::parse_from(["raytracer", "--bvh-threshold", "0", "--no-bvh"]);
+        let linear = select_scene(make_scene(), never.bvh_threshold(), 1.0);
+        assert!(matches!(linear, SceneType::Scene(_)));
+
+        // without antialiasing both trace exactly the same rays
+        let cam = Camera::build(
+            raytracer::vector::Vec3([0.0, 0.0, 0.0]),
+            raytracer::vector::Vec3([0.0, 0.0, -6.0]),
+            0.5,
+            0.0,
+            6,
+            4,
+        );
+        let settings = RenderSettings {
+            antialiasing: false,
+            ..Default::default()
+        };
+        let render = |scene: &SceneType| {
+            let mut buffer = Vec::<u8>::new();
+            render_ppm(&mut buffer, scene, &cam, &settings, 1);
+            String::from_utf8(buffer).expect("ppm output should be ascii")
+        };
+        assert_eq!(render(&linear), render(&covered));
+    }
 }
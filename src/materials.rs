@@ -3,124 +3,343 @@ use serde::{Deserialize, Serialize};
 use crate::color::Color;
 use crate::ray::Ray;
 use crate::rgba_to_color;
+use crate::sampler;
 use crate::{geometry::Shape, vector::Vec3};
 use image::{DynamicImage, GenericImageView};
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use std::f64::consts::PI;
 
 use image::Rgba;
 
 #[serde_with::serde_as]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Material {
     Diffuse {
-        albedo: Color,
+        albedo: Albedo,
     },
     Metal {
         albedo: Color,
+        // 0 for a polished metal
+        #[serde(default)]
         fuzz: f64,
     },
     Dielectric {
+        #[serde(default = "glass_index")]
         refractive_index: f64,
+        // the color white light takes on through a unit length of the glass,
+        // white for clear glass
+        #[serde(default = "no_tint")]
+        tint: Color,
     },
     TextureMap {
         #[serde_as(as = "TextureMapFilePath")]
         map: DynamicImage,
+        #[serde(default = "y_axis")]
         orient_up: Vec3,
+        #[serde(default = "x_axis")]
         orient_around: Vec3,
+        // for maps that are already linear rather than sRGB encoded
+        #[serde(default)]
+        linear: bool,
     },
     Emitter {
         albedo: Color,
+        #[serde(default = "unit_intensity")]
+        intensity: f64,
+    },
+    // an emitter lighting only the cone about direction, at full intensity
+    // within inner_cos of it and fading smoothly to nothing at outer_cos
+    Spot {
+        albedo: Color,
+        #[serde(default = "unit_intensity")]
+        intensity: f64,
+        direction: Vec3,
+        inner_cos: f64,
+        outer_cos: f64,
+    },
+    // a perfect mirror, reflecting without drawing any random numbers
+    Mirror {
+        #[serde(default = "no_tint")]
+        tint: Color,
+    },
+    // diffuse with red = longitude / 2pi and green = latitude / pi about the y axis
+    UVDebug,
+    // diffuse like velvet, the albedo going from base seen face-on to sheen
+    // at grazing angles, the larger the exponent the thinner the rim
+    Sheen {
+        base: Color,
+        sheen: Color,
+        #[serde(default = "schlick_exponent")]
+        exponent: f64,
     },
 }
 
-fn load_image(path_to_file: &str) -> image::DynamicImage {
-    image::open(path_to_file).expect("cannot open file")
+// a diffuse albedo, either one color or a texture wrapped around like the
+// TextureMap, written in a scene file as a color or as the texture fields
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Albedo {
+    Color(Color),
+    Texture {
+        #[serde_as(as = "TextureMapFilePath")]
+        map: DynamicImage,
+        #[serde(default = "y_axis")]
+        orient_up: Vec3,
+        #[serde(default = "x_axis")]
+        orient_around: Vec3,
+        #[serde(default)]
+        linear: bool,
+    },
+}
+
+impl Albedo {
+    pub fn at(&self, location: &Vec3) -> Color {
+        match self {
+            Albedo::Color(color) => *color,
+            Albedo::Texture {
+                map,
+                orient_up,
+                orient_around,
+                linear,
+            } => texture_color(map, orient_up, orient_around, *linear, location),
+        }
+    }
+}
+
+impl From<Color> for Albedo {
+    fn from(color: Color) -> Self {
+        Albedo::Color(color)
+    }
+}
+
+fn unit_intensity() -> f64 {
+    1.0
+}
+
+fn no_tint() -> Color {
+    Color::new(1.0, 1.0, 1.0)
+}
+
+fn glass_index() -> f64 {
+    1.5
+}
+
+// the rim falls off as the Fresnel reflectance does in Schlick's approximation
+fn schlick_exponent() -> f64 {
+    5.0
+}
+
+// textures are wrapped with their poles on the y axis by default, as the
+// UVDebug material is
+fn y_axis() -> Vec3 {
+    Vec3([0.0, 1.0, 0.0])
+}
+
+fn x_axis() -> Vec3 {
+    Vec3([1.0, 0.0, 0.0])
+}
+
+// a missing or unreadable texture fails the scene deserialization with its path
+fn load_image(path_to_file: &str) -> Result<image::DynamicImage, String> {
+    image::open(path_to_file)
+        .map_err(|err| format!("cannot open texture map {}: {}", path_to_file, err))
+}
+
+// a scattered ray with the density of its direction in solid angle, the
+// specular materials pick the one direction and report a pdf of 0
+pub struct ScatterRecord {
+    pub ray: Ray,
+    pub pdf: f64,
 }
 
 serde_with::serde_conv!(
     TextureMapFilePath,
     DynamicImage,
     |_map: &DynamicImage| "texturemap.jpeg",
-    |path_to_file: &str| -> Result<_, std::convert::Infallible> { Ok(load_image(path_to_file)) }
+    |path_to_file: &str| -> Result<_, String> { load_image(path_to_file) }
 );
 
 impl Material {
-    pub fn albedo(&self, location: &Vec3) -> Color {
+    // the albedo at the object relative location seen from outgoing, which
+    // points away from the surface, with normal on the same side as it
+    pub fn albedo(&self, location: &Vec3, outgoing: Vec3, normal: Vec3) -> Color {
         match self {
-            Material::Diffuse { albedo: color } => *color,
+            Material::Diffuse { albedo } => albedo.at(location),
             Material::Metal {
                 albedo: color,
                 fuzz: _,
             } => *color,
-            Material::Dielectric {
-                refractive_index: _,
-            } => Color::new(1.0, 1.0, 1.0),
+            Material::Dielectric { .. } => Color::new(1.0, 1.0, 1.0),
             Material::TextureMap {
                 map: img,
                 orient_up,
                 orient_around,
+                linear,
+            } => texture_color(img, orient_up, orient_around, *linear, location),
+            Material::Emitter { albedo: color, .. } | Material::Spot { albedo: color, .. } => {
+                *color
+            }
+            Material::Mirror { tint } => *tint,
+            Material::UVDebug => {
+                let (longitude, latitude) =
+                    spherical_coords(location, &Vec3([0.0, 1.0, 0.0]), &Vec3([1.0, 0.0, 0.0]));
+                Color::new(0.5 * longitude / PI, latitude / PI, 0.0)
+            }
+            Material::Sheen {
+                base,
+                sheen,
+                exponent,
+            } => {
+                let cosine = outgoing.normalize().dotprod(&normal.normalize()).abs();
+                let rim = (1.0 - cosine.min(1.0)).powf(*exponent);
+                (1.0 - rim) * *base + rim * *sheen
+            }
+        }
+    }
+
+    // the light given off by the material facing the viewer, the most a
+    // spot gives off in any direction
+    pub fn radiance(&self) -> Color {
+        match self {
+            Material::Emitter { albedo, intensity }
+            | Material::Spot {
+                albedo, intensity, ..
+            } => *intensity * *albedo,
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // the light given off along the outgoing direction
+    pub fn radiance_towards(&self, outgoing: Vec3) -> Color {
+        match self {
+            Material::Spot {
+                direction,
+                inner_cos,
+                outer_cos,
+                ..
             } => {
-                let latitude: f64 = orient_up.normalize().dotprod(&location).acos();
-                let orient_axes: (Vec3, Vec3) = (
-                    orient_around.normalize(),
-                    orient_up.normalize().cross(&orient_around.normalize()),
-                );
-                let longitude: f64 = orient_axes
-                    .0
-                    .dotprod(&location)
-                    .atan2(orient_axes.1.dotprod(&location))
-                    + PI;
-                let texture_color: Rgba<u8> = get_texture_rgba(&img, longitude, latitude);
-                rgba_to_color(texture_color)
+                let cosine = direction.normalize().dotprod(&outgoing.normalize());
+                spot_falloff(cosine, *inner_cos, *outer_cos) * self.radiance()
             }
-            Material::Emitter { albedo: color } => *color,
+            _ => self.radiance(),
+        }
+    }
+
+    pub fn is_emitter(&self) -> bool {
+        matches!(self, Material::Emitter { .. } | Material::Spot { .. })
+    }
+
+    // the variant name, as written in a scene file
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Material::Diffuse { .. } => "Diffuse",
+            Material::Metal { .. } => "Metal",
+            Material::Dielectric { .. } => "Dielectric",
+            Material::TextureMap { .. } => "TextureMap",
+            Material::Emitter { .. } => "Emitter",
+            Material::Spot { .. } => "Spot",
+            Material::Mirror { .. } => "Mirror",
+            Material::UVDebug => "UVDebug",
+            Material::Sheen { .. } => "Sheen",
+        }
+    }
+
+    // whether the material scatters in a cosine lobe about the normal
+    pub fn is_diffuse(&self) -> bool {
+        matches!(
+            self,
+            Material::Diffuse { .. }
+                | Material::TextureMap { .. }
+                | Material::UVDebug
+                | Material::Sheen { .. }
+        )
+    }
+
+    // the cost of a bounce off this material in quarters of the scatter depth
+    pub fn depth_cost(&self) -> u32 {
+        match self {
+            Material::Dielectric { .. } => 1,
+            Material::Metal { .. } | Material::Mirror { .. } => 2,
+            _ => 4,
         }
     }
-    pub fn scatter(&self, inc_ray: &Ray, shape: &Shape, scatter_loc: Vec3) -> Ray {
+
+    // surfaces scatter on the side the ray came from, so the normal is
+    // oriented against it, except for dielectrics which tell entering from
+    // leaving by the side of the outward normal the ray is on
+    pub fn scatter(&self, inc_ray: &Ray, shape: &Shape, scatter_loc: Vec3) -> ScatterRecord {
+        let (facing_normal, _) = shape.oriented_normal_at(scatter_loc, inc_ray.dir);
+        let ray = self.scatter_ray(inc_ray, shape, scatter_loc, facing_normal);
+        let pdf = self.scatter_pdf(facing_normal, ray.dir);
+        ScatterRecord { ray, pdf }
+    }
+
+    // the density of scattering in direction, with the normal on the side
+    // the ray arrived from, diffuse materials follow the cosine about it
+    pub fn scatter_pdf(&self, normal: Vec3, direction: Vec3) -> f64 {
+        if self.is_diffuse() {
+            normal.dotprod(&direction).max(0.0) / PI
+        } else {
+            0.0
+        }
+    }
+
+    // the fraction of the light arriving from incoming that is sent out along
+    // outgoing per unit solid angle, both pointing away from the surface, at
+    // the object relative location, specular materials are left to their
+    // scattered rays and give black
+    pub fn brdf_value(
+        &self,
+        location: &Vec3,
+        incoming: Vec3,
+        outgoing: Vec3,
+        normal: Vec3,
+    ) -> Color {
+        let same_side = normal.dotprod(&incoming) * normal.dotprod(&outgoing) > 0.0;
+        if self.is_diffuse() && same_side {
+            (1.0 / PI) * self.albedo(location, outgoing, normal)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn scatter_ray(
+        &self,
+        inc_ray: &Ray,
+        shape: &Shape,
+        scatter_loc: Vec3,
+        facing_normal: Vec3,
+    ) -> Ray {
         match *self {
             Material::Diffuse { albedo: _ } => {
-                let scatter_dir = shape.normal_at(scatter_loc) + random_vec3();
+                let scatter_dir = facing_normal + random_vec3();
                 return Ray::new(scatter_loc, scatter_dir);
             }
             Material::Metal {
                 albedo: _,
                 fuzz: fuzziness,
             } => {
-                let scatter_normal = shape.normal_at(scatter_loc);
-                let scatter_dir: Vec3 =
-                    inc_ray.dir - 2.0 * scatter_normal.dotprod(&inc_ray.dir) * scatter_normal;
+                let scatter_normal = facing_normal;
+                let scatter_dir: Vec3 = reflect_direction(inc_ray.dir, scatter_normal);
                 let fuzzified_dir = fuzzify(fuzziness, scatter_dir, scatter_normal);
                 return Ray::new(scatter_loc, fuzzified_dir);
             }
+            Material::Mirror { .. } => {
+                let scatter_dir = reflect_direction(inc_ray.dir, facing_normal);
+                Ray::new(scatter_loc, scatter_dir)
+            }
             Material::Dielectric {
                 refractive_index: r_idx,
+                ..
             } => {
                 let scatter_normal = shape.normal_at(scatter_loc);
-                let inc_cos = scatter_normal.dotprod(&inc_ray.dir); // -ve the usual for most ray-tracers
-                let inc_dir_perp: Vec3 = inc_ray.dir - inc_cos * scatter_normal;
-                let mut refract_ratio = r_idx; // default ray going from inside to outside so fewer divisions
-                let sign_inc = inc_cos.signum(); // needed for determining scattered ray parallel direction
-                if sign_inc < 0.0 {
-                    refract_ratio = 1.0 / r_idx
-                }; // refract from the outside
-                let scatter_dir_perp = refract_ratio * inc_dir_perp;
-                let scatter_sin2: f64 = scatter_dir_perp.dotprod(&scatter_dir_perp); // no sqrt needed
-
-                if scatter_sin2 > 1.0 || schlick(inc_cos, refract_ratio) {
-                    // total internal reflection
-                    let scatter_dir: Vec3 = inc_dir_perp - inc_cos * scatter_normal;
-                    return Ray::new(scatter_loc, scatter_dir);
-                } else {
-                    // refraction
-                    // refracted ray goes in the same direction as inc ray so sign of cos is the same
-                    let scatter_cos: f64 = sign_inc * (1.0 - scatter_sin2).sqrt();
-                    let scatter_dir = scatter_dir_perp + scatter_cos * scatter_normal;
-                    return Ray::new(scatter_loc, scatter_dir);
-                };
+                let scatter_dir =
+                    dielectric_scatter_dir(inc_ray.dir, scatter_normal, r_idx, schlick);
+                return Ray::new(scatter_loc, scatter_dir);
             }
-            Material::TextureMap { .. } => {
-                let scatter_dir = shape.normal_at(scatter_loc) + random_vec3();
+            Material::TextureMap { .. } | Material::UVDebug | Material::Sheen { .. } => {
+                let scatter_dir = facing_normal + random_vec3();
                 return Ray::new(scatter_loc, scatter_dir);
             }
             _ => {
@@ -130,15 +349,83 @@ impl Material {
     }
 }
 
-fn schlick(cosine: f64, r_idx: f64) -> bool {
-    let mut r0 = (1.0 - r_idx) / (1.0 + r_idx);
+// smoothstep from 0 at outer_cos up to 1 at inner_cos
+fn spot_falloff(cosine: f64, inner_cos: f64, outer_cos: f64) -> f64 {
+    if cosine >= inner_cos {
+        return 1.0;
+    }
+    if cosine <= outer_cos {
+        return 0.0;
+    }
+    let x = (cosine - outer_cos) / (inner_cos - outer_cos);
+    x * x * (3.0 - 2.0 * x)
+}
+
+// combines the refraction with the reflection, choose_reflection is given
+// (inc_cos, refract_ratio) and decides between the two when both are possible
+fn dielectric_scatter_dir<F>(
+    inc_dir: Vec3,
+    scatter_normal: Vec3,
+    r_idx: f64,
+    choose_reflection: F,
+) -> Vec3
+where
+    F: FnOnce(f64, f64) -> bool,
+{
+    let inc_cos = scatter_normal.dotprod(&inc_dir); // -ve the usual for most ray-tracers
+    let refract_ratio = if inc_cos < 0.0 { 1.0 / r_idx } else { r_idx };
+
+    match refract_direction(inc_dir, scatter_normal, refract_ratio) {
+        Some(scatter_dir) if !choose_reflection(inc_cos, refract_ratio) => scatter_dir,
+        _ => reflect_direction(inc_dir, scatter_normal),
+    }
+}
+
+// Snell's law with ratio = n_incident / n_transmitted, the normal may face
+// either way and None is returned for total internal reflection
+pub fn refract_direction(inc_dir: Vec3, normal: Vec3, ratio: f64) -> Option<Vec3> {
+    let inc_cos = normal.dotprod(&inc_dir);
+    let inc_dir_perp: Vec3 = inc_dir - inc_cos * normal;
+    let scatter_dir_perp = ratio * inc_dir_perp;
+    let scatter_sin2: f64 = scatter_dir_perp.dotprod(&scatter_dir_perp); // no sqrt needed
+    if scatter_sin2 > 1.0 {
+        return None;
+    }
+    // refracted ray goes in the same direction as inc ray so sign of cos is the same
+    let scatter_cos: f64 = inc_cos.signum() * (1.0 - scatter_sin2).sqrt();
+    Some(scatter_dir_perp + scatter_cos * normal)
+}
+
+pub fn reflect_direction(inc_dir: Vec3, normal: Vec3) -> Vec3 {
+    inc_dir - 2.0 * normal.dotprod(&inc_dir) * normal
+}
+
+// the fraction of the light left after going distance through glass of
+// the tint, by the Beer-Lambert law
+pub fn absorption(tint: Color, distance: f64) -> Color {
+    Color::new(
+        tint.r.powf(distance),
+        tint.g.powf(distance),
+        tint.b.powf(distance),
+    )
+}
+
+// Schlick's approximation to the Fresnel reflectance
+pub fn reflectance(cosine: f64, ratio: f64) -> f64 {
+    let mut r0 = (1.0 - ratio) / (1.0 + ratio);
     r0 = r0 * r0;
-    let reflectance: f64 = r0 + (1.0 - r0) * (1.0 - cosine.abs()).powi(5);
-    let drawn_prob = thread_rng().gen_range(0.0..1.0);
-    drawn_prob < reflectance
+    r0 + (1.0 - r0) * (1.0 - cosine.abs()).powi(5)
+}
+
+fn schlick(cosine: f64, ratio: f64) -> bool {
+    let drawn_prob = sampler::rng().gen_range(0.0..1.0);
+    drawn_prob < reflectance(cosine, ratio)
 }
 
 fn fuzzify(fuzziness: f64, scatter_dir: Vec3, scatter_normal: Vec3) -> Vec3 {
+    if fuzziness == 0.0 {
+        return scatter_dir; // no random draw for a polished metal
+    }
     let fuzzy_dir = scatter_dir + (fuzziness * random_vec3());
     if fuzzy_dir.dotprod(&scatter_normal) > 0.0 {
         fuzzy_dir
@@ -147,15 +434,43 @@ fn fuzzify(fuzziness: f64, scatter_dir: Vec3, scatter_normal: Vec3) -> Vec3 {
     }
 }
 
-fn random_vec3() -> Vec3 {
-    let v: (f64, f64, f64) = thread_rng().gen();
+pub(crate) fn random_vec3() -> Vec3 {
+    let v: (f64, f64, f64) = sampler::rng().gen();
     let rand_vec3 = 2.0 * Vec3([v.0 - 0.5, v.1 - 0.5, v.2 - 0.5]);
-    if rand_vec3.norm() > 1.0 {
+    if rand_vec3.norm_squared() > 1.0 {
         return random_vec3();
     };
     return rand_vec3.normalize();
 }
 
+// (longitude in [0, 2pi], latitude in [0, pi]) of a unit location, with
+// latitude measured from orient_up and longitude about it from orient_around
+fn spherical_coords(location: &Vec3, orient_up: &Vec3, orient_around: &Vec3) -> (f64, f64) {
+    let latitude: f64 = orient_up.normalize().dotprod(location).acos();
+    let orient_axes: (Vec3, Vec3) = (
+        orient_around.normalize(),
+        orient_up.normalize().cross(&orient_around.normalize()),
+    );
+    let longitude: f64 = orient_axes
+        .0
+        .dotprod(location)
+        .atan2(orient_axes.1.dotprod(location))
+        + PI;
+    (longitude, latitude)
+}
+
+fn texture_color(
+    map: &DynamicImage,
+    orient_up: &Vec3,
+    orient_around: &Vec3,
+    linear: bool,
+    location: &Vec3,
+) -> Color {
+    let (longitude, latitude) = spherical_coords(location, orient_up, orient_around);
+    let texture_rgba: Rgba<u8> = get_texture_rgba(map, longitude, latitude);
+    rgba_to_color(texture_rgba, !linear)
+}
+
 fn get_texture_rgba(image: &DynamicImage, longitude_rad: f64, latitude_rad: f64) -> Rgba<u8> {
     let dimensions: (u32, u32) = image.dimensions();
 
@@ -171,4 +486,322 @@ fn get_texture_rgba(image: &DynamicImage, longitude_rad: f64, latitude_rad: f64)
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    fn never_reflect(_cos: f64, _ratio: f64) -> bool {
+        false
+    }
+
+    #[test]
+    fn refraction_entering_test() {
+        let normal = Vec3([0.0, 1.0, 0.0]);
+        let sin_inc = 0.6;
+        let inc_dir = Vec3([sin_inc, -0.8, 0.0]);
+
+        let out_dir = dielectric_scatter_dir(inc_dir, normal, 1.5, never_reflect);
+
+        assert!((out_dir.norm() - 1.0).abs() < 1.0e-12);
+        assert!((1.5 * out_dir[0] - sin_inc).abs() < 1.0e-12); // Snell's law
+        assert!(
+            out_dir[1] < 0.0,
+            "refracted ray should continue into the glass"
+        );
+    }
+
+    #[test]
+    fn refraction_exiting_test() {
+        let normal = Vec3([0.0, 1.0, 0.0]); // the outward normal seen from inside
+        let sin_inc = 0.5;
+        let inc_dir = Vec3([sin_inc, 0.75_f64.sqrt(), 0.0]);
+
+        let out_dir = dielectric_scatter_dir(inc_dir, normal, 1.5, never_reflect);
+
+        assert!((out_dir.norm() - 1.0).abs() < 1.0e-12);
+        assert!((out_dir[0] - 1.5 * sin_inc).abs() < 1.0e-12); // Snell's law
+        assert!(out_dir[1] > 0.0, "refracted ray should leave the glass");
+    }
+
+    #[test]
+    fn total_internal_reflection_test() {
+        let normal = Vec3([0.0, 1.0, 0.0]);
+        let inc_dir = Vec3([0.8, 0.6, 0.0]); // beyond the critical angle for 1.5
+
+        let out_dir = dielectric_scatter_dir(inc_dir, normal, 1.5, never_reflect);
+
+        assert!((out_dir - Vec3([0.8, -0.6, 0.0])).norm() < 1.0e-12);
+    }
+
+    #[test]
+    fn refract_direction_test() {
+        let normal = Vec3([0.0, 1.0, 0.0]);
+        let straight = refract_direction(Vec3([0.0, -1.0, 0.0]), normal, 1.0 / 1.5);
+        assert_eq!(straight, Some(Vec3([0.0, -1.0, 0.0])));
+
+        assert!(refract_direction(Vec3([0.8, 0.6, 0.0]), normal, 1.5).is_none());
+    }
+
+    #[test]
+    fn reflectance_test() {
+        assert!((reflectance(1.0, 1.5) - 0.04).abs() < 1.0e-12);
+        assert!((reflectance(1.0, 1.0 / 1.5) - 0.04).abs() < 1.0e-12);
+        assert!((reflectance(0.0, 1.5) - 1.0).abs() < 1.0e-12);
+        assert!(reflectance(0.3, 1.5) > reflectance(0.9, 1.5));
+    }
+
+    #[test]
+    fn fresnel_split_test() {
+        let normal = Vec3([0.0, 1.0, 0.0]);
+        let inc_cos: f64 = 0.3;
+        let inc_dir = Vec3([(1.0 - inc_cos * inc_cos).sqrt(), -inc_cos, 0.0]);
+
+        let samples = 20000;
+        let reflected = (0..samples)
+            .map(|_| dielectric_scatter_dir(inc_dir, normal, 1.5, schlick))
+            .filter(|scatter_dir| scatter_dir[1] > 0.0)
+            .count();
+
+        let expected = reflectance(inc_cos, 1.0 / 1.5);
+        let observed = reflected as f64 / samples as f64;
+        assert!(
+            (observed - expected).abs() < 0.015,
+            "{} vs {}",
+            observed,
+            expected
+        );
+    }
+
+    #[test]
+    fn uv_debug_test() {
+        let facing = Vec3([0.0, 0.0, 1.0]);
+        let north_pole = Material::UVDebug.albedo(&Vec3([0.0, 1.0, 0.0]), facing, facing);
+        assert_eq!(north_pole.g, 0.0);
+
+        let equator = Material::UVDebug.albedo(&Vec3([1.0, 0.0, 0.0]), facing, facing);
+        assert!((equator.g - 0.5).abs() < 1.0e-12);
+        assert!((equator.r - 0.75).abs() < 1.0e-12);
+
+        let equator_opposite = Material::UVDebug.albedo(&Vec3([-1.0, 0.0, 0.0]), facing, facing);
+        assert!((equator_opposite.r - 0.25).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn textured_diffuse_test() {
+        let map = DynamicImage::ImageRgb8(image::RgbImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                image::Rgb([255, 0, 0])
+            } else {
+                image::Rgb([0, 255, 0])
+            }
+        }));
+        let textured = Material::Diffuse {
+            albedo: Albedo::Texture {
+                map,
+                orient_up: Vec3([0.0, 1.0, 0.0]),
+                orient_around: Vec3([1.0, 0.0, 0.0]),
+                linear: true,
+            },
+        };
+        let (front_loc, back_loc) = (Vec3([0.0, 0.0, 1.0]), Vec3([0.0, 0.0, -1.0]));
+        let front = textured.albedo(&front_loc, front_loc, front_loc);
+        let back = textured.albedo(&back_loc, back_loc, back_loc);
+        assert_eq!((front.r, front.g), (1.0, 0.0));
+        assert_eq!((back.r, back.g), (0.0, 1.0));
+
+        let sphere = Shape::Sphere(crate::geometry::Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0));
+        let hit = Vec3([0.0, 0.0, 1.0]);
+        let inc_ray = Ray::new(Vec3([0.0, 0.0, 2.0]), Vec3([0.0, 0.0, -1.0]));
+        for _ in 0..100 {
+            let scattered = textured.scatter(&inc_ray, &sphere, hit).ray;
+            assert!(scattered.dir.dotprod(&sphere.normal_at(hit)) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn lambertian_pdf_test() {
+        let diffuse = Material::Diffuse {
+            albedo: Color::new(0.5, 0.5, 0.5).into(),
+        };
+        let normal = Vec3([0.0, 1.0, 0.0]);
+
+        // uniform directions over the sphere have a density of 1 / 4pi, the
+        // lower half contributing nothing to the integral
+        let samples = 100000;
+        let integral: f64 = (0..samples)
+            .map(|_| 4.0 * PI * diffuse.scatter_pdf(normal, random_vec3()))
+            .sum::<f64>()
+            / samples as f64;
+        assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+
+        let disc = Shape::Disc(crate::geometry::Disc::new(
+            Vec3([0.0, 0.0, 0.0]),
+            normal,
+            1.0,
+        ));
+        let inc_ray = Ray::new(Vec3([0.0, 1.0, 0.0]), Vec3([0.0, -1.0, 0.0]));
+        let hit = Vec3([0.0, 0.0, 0.0]);
+        for _ in 0..100 {
+            let scattered = diffuse.scatter(&inc_ray, &disc, hit);
+            assert_eq!(scattered.pdf, scattered.ray.dir[1] / PI);
+            let brdf = diffuse.brdf_value(&hit, -inc_ray.dir, scattered.ray.dir, normal);
+            assert_eq!(brdf.r, 0.5 / PI);
+        }
+        let mirror = Material::Mirror { tint: no_tint() };
+        assert_eq!(mirror.scatter(&inc_ray, &disc, hit).pdf, 0.0);
+    }
+
+    #[test]
+    fn sheen_test() {
+        let velvet: Material = serde_json::from_str(
+            r#"{"Sheen": {"base": [0.2, 0.0, 0.0], "sheen": [1.0, 1.0, 1.0]}}"#,
+        )
+        .unwrap();
+        let (location, normal) = (Vec3([0.0, 1.0, 0.0]), Vec3([0.0, 1.0, 0.0]));
+
+        let face_on = velvet.albedo(&location, Vec3([0.0, 2.0, 0.0]), normal);
+        assert_eq!((face_on.r, face_on.g, face_on.b), (0.2, 0.0, 0.0));
+        let grazing = velvet.albedo(&location, Vec3([1.0, 0.01, 0.0]), normal);
+        assert!((grazing.r - 1.0).abs() < 0.05 && (grazing.g - 1.0).abs() < 0.06);
+        let oblique = velvet.albedo(&location, Vec3([1.0, 1.0, 0.0]), normal);
+        assert!(oblique.g > 0.0 && oblique.g < 0.5 * grazing.g);
+        assert!(velvet.is_diffuse());
+    }
+
+    #[test]
+    fn missing_texture_test() {
+        let missing = serde_json::from_str::<Material>(
+            r#"{"TextureMap": {"map": "no/such/texture.png",
+                "orient_up": [0.0, 1.0, 0.0], "orient_around": [1.0, 0.0, 0.0]}}"#,
+        );
+        let err = missing.expect_err("a missing texture should not deserialize");
+        assert!(err.to_string().contains("no/such/texture.png"));
+    }
+
+    #[test]
+    fn mirror_test() {
+        let mirror: Material = serde_json::from_str(r#"{"Mirror": {}}"#).unwrap();
+        let up = Vec3([0.0, 1.0, 0.0]);
+        let tint = mirror.albedo(&up, up, up);
+        assert_eq!((tint.r, tint.g, tint.b), (1.0, 1.0, 1.0));
+
+        let disc = Shape::Disc(crate::geometry::Disc::new(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 1.0, 0.0]),
+            1.0,
+        ));
+        let inc_ray = Ray::new(Vec3([-1.0, 1.0, 0.0]), Vec3([3.0, -4.0, 0.0]));
+        let hit = Vec3([0.0, 0.0, 0.0]);
+        let scattered = mirror.scatter(&inc_ray, &disc, hit).ray;
+        assert_eq!(scattered.dir, Vec3([0.6, 0.8, 0.0]));
+        assert_eq!(mirror.scatter(&inc_ray, &disc, hit).ray.dir, scattered.dir);
+    }
+
+    #[test]
+    fn back_face_scatter_test() {
+        let diffuse = Material::Diffuse {
+            albedo: Color::new(0.5, 0.5, 0.5).into(),
+        };
+        let disc = Shape::Disc(crate::geometry::Disc::new(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 1.0, 0.0]),
+            1.0,
+        ));
+        // seen from below the disc scatters back down rather than through it
+        let inc_ray = Ray::new(Vec3([0.0, -1.0, 0.0]), Vec3([0.0, 1.0, 0.0]));
+        let hit = Vec3([0.0, 0.0, 0.0]);
+        for _ in 0..100 {
+            assert!(diffuse.scatter(&inc_ray, &disc, hit).ray.dir[1] <= 0.0);
+        }
+
+        // and a fuzzy metal reflects off the inside of a sphere
+        let metal = Material::Metal {
+            albedo: Color::new(0.9, 0.9, 0.9),
+            fuzz: 0.5,
+        };
+        let sphere = Shape::Sphere(crate::geometry::Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0));
+        let inc_ray = Ray::new(Vec3([0.0, 0.0, 0.0]), Vec3([0.0, 1.0, 0.0]));
+        let hit = Vec3([0.0, 1.0, 0.0]);
+        for _ in 0..100 {
+            assert!(metal.scatter(&inc_ray, &sphere, hit).ray.dir[1] < 0.0);
+        }
+    }
+
+    #[test]
+    fn zero_fuzz_metal_test() {
+        let metal = Material::Metal {
+            albedo: Color::new(0.9, 0.9, 0.9),
+            fuzz: 0.0,
+        };
+        let sphere = Shape::Sphere(crate::geometry::Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0));
+        let hit = Vec3([0.0, 1.0, 0.0]);
+        let inc_ray = Ray::new(Vec3([-1.0, 2.0, 0.0]), Vec3([1.0, -1.0, 0.0]));
+
+        let first = metal.scatter(&inc_ray, &sphere, hit).ray;
+        let second = metal.scatter(&inc_ray, &sphere, hit).ray;
+        assert_eq!(first.dir, second.dir);
+        assert_eq!(
+            first.dir,
+            reflect_direction(inc_ray.dir, sphere.normal_at(hit)).normalize()
+        );
+    }
+
+    #[test]
+    fn emitter_intensity_test() {
+        let bright: Material = serde_json::from_str(
+            r#"{"Emitter": {"albedo": {"r": 1.0, "g": 0.5, "b": 0.25}, "intensity": 8.0}}"#,
+        )
+        .expect("emitter should deserialize");
+        let radiance = bright.radiance();
+        assert_eq!((radiance.r, radiance.g, radiance.b), (8.0, 4.0, 2.0));
+
+        let plain: Material =
+            serde_json::from_str(r#"{"Emitter": {"albedo": {"r": 1.0, "g": 0.5, "b": 0.25}}}"#)
+                .expect("emitter should deserialize");
+        let radiance = plain.radiance();
+        assert_eq!((radiance.r, radiance.g, radiance.b), (1.0, 0.5, 0.25));
+    }
+
+    #[test]
+    fn optional_fields_test() {
+        let emitter: Material =
+            serde_json::from_str(r#"{"Emitter": {"albedo": [1.0, 0.5, 0.25]}}"#)
+                .expect("emitter should deserialize");
+        assert!(matches!(emitter, Material::Emitter { intensity, .. } if intensity == 1.0));
+
+        let metal: Material = serde_json::from_str(r#"{"Metal": {"albedo": [0.9, 0.9, 0.9]}}"#)
+            .expect("metal should deserialize");
+        assert!(matches!(metal, Material::Metal { fuzz, .. } if fuzz == 0.0));
+
+        let glass: Material =
+            serde_json::from_str(r#"{"Dielectric": {}}"#).expect("glass should deserialize");
+        assert!(
+            matches!(glass, Material::Dielectric { refractive_index, tint } if refractive_index == 1.5 && tint.b == 1.0)
+        );
+
+        let mirror: Material =
+            serde_json::from_str(r#"{"Mirror": {}}"#).expect("mirror should deserialize");
+        assert!(matches!(mirror, Material::Mirror { tint } if tint.r == 1.0));
+    }
+
+    #[test]
+    fn spot_falloff_test() {
+        let spot: Material = serde_json::from_str(
+            r#"{"Spot": {"albedo": {"r": 1.0, "g": 1.0, "b": 1.0}, "intensity": 4.0,
+                "direction": [0.0, -1.0, 0.0], "inner_cos": 0.9, "outer_cos": 0.7}}"#,
+        )
+        .expect("spot should deserialize");
+        assert!(spot.is_emitter());
+
+        let towards = |x: f64| spot.radiance_towards(Vec3([x, -1.0, 0.0])).r;
+        // straight down and within the inner cone the light is full
+        assert_eq!(towards(0.0), 4.0);
+        assert_eq!(towards(0.4), 4.0);
+        // past the outer cone at 50 degrees and behind the light it is dark
+        assert_eq!(towards(1.2), 0.0);
+        assert_eq!(spot.radiance_towards(Vec3([0.0, 1.0, 0.0])).r, 0.0);
+        // and in between it fades
+        let between = towards(0.8);
+        assert!(between > 0.0 && between < 4.0);
+        assert!(towards(0.7) > between);
+    }
+}
@@ -0,0 +1,246 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use crate::color::Color;
+use crate::materials;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vector::Vec3;
+use crate::Hittable;
+
+// how many photons the caustics prepass traces out of the lights and how
+// far around a diffuse hit they are gathered from, a wider radius trading
+// blur for less noise
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhotonSettings {
+    pub count: u32,
+    pub radius: f64,
+    // the most specular and transmissive bounces followed per photon
+    pub max_bounces: u8,
+}
+
+impl Default for PhotonSettings {
+    fn default() -> Self {
+        PhotonSettings {
+            count: 100_000,
+            radius: 0.1,
+            max_bounces: 8,
+        }
+    }
+}
+
+// a share of the power of a light landing on a diffuse surface
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+    pub position: Vec3,
+    pub power: Color,
+}
+
+// photons binned into cubes the size of the gather radius, so a gather
+// only looks through the 27 cubes around the point
+#[derive(Debug)]
+pub struct PhotonMap {
+    radius: f64,
+    cells: HashMap<[i64; 3], Vec<Photon>>,
+    count: usize,
+}
+
+impl PhotonMap {
+    pub fn new(radius: f64) -> PhotonMap {
+        PhotonMap {
+            radius,
+            cells: HashMap::new(),
+            count: 0,
+        }
+    }
+
+    fn cell(&self, position: Vec3) -> [i64; 3] {
+        position.0.map(|coord| (coord / self.radius).floor() as i64)
+    }
+
+    pub fn store(&mut self, photon: Photon) {
+        let cell = self.cell(photon.position);
+        self.cells.entry(cell).or_default().push(photon);
+        self.count += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    // the irradiance at position, the power of the photons within the
+    // radius spread over the disc they were gathered from
+    pub fn gather(&self, position: Vec3) -> Color {
+        let [x, y, z] = self.cell(position);
+        let mut power = Color::new(0.0, 0.0, 0.0);
+        for cell in (x - 1..=x + 1).flat_map(|i| {
+            (y - 1..=y + 1).flat_map(move |j| (z - 1..=z + 1).map(move |k| [i, j, k]))
+        }) {
+            for photon in self.cells.get(&cell).into_iter().flatten() {
+                if (photon.position - position).norm_squared() <= self.radius * self.radius {
+                    power += photon.power;
+                }
+            }
+        }
+        (1.0 / (PI * self.radius * self.radius)) * power
+    }
+}
+
+// traces settings.count photons out of the lights, each carrying an equal
+// share of their power, storing them where they first land on a diffuse
+// surface, with caustics_only just those that got there through specular or
+// transmissive surfaces, the light paths tracing from the camera finds so
+// hard to sample, nearest finding the closest hit as for trace_path
+pub fn trace_photons<'a, F>(
+    lights: &[&Hittable],
+    settings: &PhotonSettings,
+    caustics_only: bool,
+    nearest: F,
+) -> PhotonMap
+where
+    F: Fn(&Ray) -> Option<(&'a Hittable, f64)>,
+{
+    let mut map = PhotonMap::new(settings.radius);
+    if lights.is_empty() || settings.count == 0 {
+        return map;
+    }
+    let mut rng = sampler::rng();
+    // lights are picked evenly, so each photon stands for all of them
+    let share = lights.len() as f64 / f64::from(settings.count);
+    for _ in 0..settings.count {
+        let light = lights[rng.gen_range(0..lights.len())];
+        let (Some(origin), Some(area)) = (light.shape.sample_surface(&mut rng), light.shape.area())
+        else {
+            continue;
+        };
+        let mut normal = light.shape.normal_at(origin).normalize();
+        let mut sides = 1.0;
+        if !light.shape.is_one_sided() {
+            sides = 2.0;
+            if rng.gen::<bool>() {
+                normal = -1.0 * normal;
+            }
+        }
+        // cosine weighted about the normal, which the lambertian light
+        // gives off in so the power of each photon is the same
+        let dir = normal + materials::random_vec3();
+        let power = (share * sides * PI * area) * light.material.radiance_towards(dir.normalize());
+        trace_photon(
+            Ray::new(origin, dir),
+            power,
+            settings,
+            caustics_only,
+            &nearest,
+            &mut map,
+        );
+    }
+    map
+}
+
+fn trace_photon<'a, F>(
+    mut ray: Ray,
+    mut power: Color,
+    settings: &PhotonSettings,
+    caustics_only: bool,
+    nearest: &F,
+    map: &mut PhotonMap,
+) where
+    F: Fn(&Ray) -> Option<(&'a Hittable, f64)>,
+{
+    let mut specular = false;
+    for _ in 0..=settings.max_bounces {
+        let Some((hit_obj, param)) = nearest(&ray) else {
+            return;
+        };
+        if hit_obj.material.is_emitter() {
+            return;
+        }
+        let position = ray.position_at(param);
+        if hit_obj.material.is_diffuse() {
+            if specular || !caustics_only {
+                map.store(Photon { position, power });
+            }
+            return;
+        }
+        let (facing_normal, _) = hit_obj.shape.oriented_normal_at(position, ray.dir);
+        let location = hit_obj.shape.local_coords(position);
+        power = power
+            * hit_obj
+                .material
+                .albedo(&location, -1.0 * ray.dir, facing_normal);
+        ray = hit_obj.material.scatter(&ray, &hit_obj.shape, position).ray;
+        specular = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Disc, Quad, Shape};
+    use crate::materials::Material;
+
+    #[test]
+    fn photons_reach_floor_test() {
+        // a small light facing down onto a wide floor
+        let scene = [
+            Hittable {
+                shape: Shape::Quad(Quad::new(
+                    Vec3([-50.0, 0.0, -50.0]),
+                    Vec3([0.0, 0.0, 100.0]),
+                    Vec3([100.0, 0.0, 0.0]),
+                )),
+                material: Material::Diffuse {
+                    albedo: Color::new(0.5, 0.5, 0.5).into(),
+                },
+            },
+            Hittable {
+                shape: Shape::Disc(Disc::new(
+                    Vec3([0.0, 2.0, 0.0]),
+                    Vec3([0.0, -1.0, 0.0]),
+                    0.1,
+                )),
+                material: Material::Emitter {
+                    albedo: Color::new(1.0, 1.0, 1.0),
+                    intensity: 10.0,
+                },
+            },
+        ];
+        sampler::seed_pixel(0, 0, 496);
+        let settings = PhotonSettings {
+            count: 200_000,
+            radius: 0.25,
+            ..Default::default()
+        };
+        let nearest = |ray: &Ray| crate::nearest_hit(&scene, ray, crate::geometry::DEFAULT_EPSILON);
+        let map = trace_photons(&[&scene[1]], &settings, false, nearest);
+        // the floor catches all but the photons leaving at a grazing angle
+        assert!(map.len() > 195_000);
+
+        // straight below, the light of area a and radiance l at height h
+        // gives an irradiance of about l a / h^2
+        let below = map.gather(Vec3([0.0, 0.0, 0.0])).r;
+        let expected = 10.0 * PI * 0.01 / 4.0;
+        assert!(
+            (below - expected).abs() < 0.05 * expected,
+            "{} against {}",
+            below,
+            expected
+        );
+        // and falling off as cos^4 out to the side, a quarter at 45 degrees
+        let aside = map.gather(Vec3([2.0, 0.0, 0.0])).r;
+        assert!(
+            (aside - 0.25 * expected).abs() < 0.1 * 0.25 * expected,
+            "{}",
+            aside
+        );
+        // and a map of caustics keeps none, as nothing specular was in the way
+        assert!(trace_photons(&[&scene[1]], &settings, true, nearest).is_empty());
+    }
+}
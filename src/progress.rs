@@ -0,0 +1,123 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::logging;
+
+// how long the reporter sleeps at a time, so it notices soon after the
+// work is finished however long it waits between reports
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// the number of items of work, pixels or tiles, finished so far by any of
+// the threads sharing it, against the total to be done
+pub struct Progress {
+    completed: AtomicU32,
+    total: u32,
+    started: Instant,
+    finished: AtomicBool,
+}
+
+impl Progress {
+    pub fn new(total: u32) -> Progress {
+        Progress {
+            completed: AtomicU32::new(0),
+            total,
+            started: Instant::now(),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    pub fn complete(&self, items: u32) {
+        self.completed.fetch_add(items, Ordering::Relaxed);
+    }
+
+    pub fn completed(&self) -> u32 {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    // between 0 and 1, with nothing to do counting as done
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        (f64::from(self.completed()) / f64::from(self.total)).min(1.0)
+    }
+
+    // the time left at the pace so far, None until anything is completed
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction();
+        if fraction <= 0.0 {
+            return None;
+        }
+        Some(self.started.elapsed().mul_f64((1.0 - fraction) / fraction))
+    }
+
+    // stops the reporter even if not everything was completed, as when a
+    // render thread panics or hits the time limit
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed) || self.completed() >= self.total
+    }
+
+    // prints the overall percentage and eta every interval until finished,
+    // meant for a thread of its own alongside the ones doing the work
+    pub fn report(&self, interval: Duration) {
+        while !self.is_finished() {
+            if logging::enabled(logging::Level::Info) {
+                eprint!("\r{}", self.status());
+            }
+            let waited = Instant::now();
+            while !self.is_finished() && waited.elapsed() < interval {
+                thread::sleep(POLL_INTERVAL.min(interval));
+            }
+        }
+        info!("\r{}", self.status());
+    }
+
+    fn status(&self) -> String {
+        let eta = self
+            .eta()
+            .map_or(String::from("unknown"), |eta| format!("{}s", eta.as_secs()));
+        format!(
+            "Progress: {:5.1}% ({} of {}), eta {}",
+            100.0 * self.fraction(),
+            self.completed(),
+            self.total,
+            eta
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_progress_test() {
+        let progress = Progress::new(100);
+        assert_eq!(progress.eta(), None);
+        thread::scope(|scope| {
+            let reporter = scope.spawn(|| progress.report(Duration::from_secs(60)));
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..25 {
+                        progress.complete(1);
+                    }
+                });
+            }
+            // the reporter stops by itself once everything is completed
+            reporter.join().unwrap();
+        });
+        assert_eq!(progress.completed(), progress.total());
+        assert_eq!(progress.fraction(), 1.0);
+        assert_eq!(progress.eta(), Some(Duration::ZERO));
+        assert!(progress.is_finished());
+    }
+}
@@ -3,15 +3,24 @@ use crate::vector::Vec3;
 pub struct Ray {
     pub orig: Vec3,
     pub dir: Vec3,
+    pub spread: f64, // cone angle in radians of the footprint around dir
 }
 
 impl Ray {
     pub fn new(position: Vec3, point_to: Vec3) -> Ray {
-        Ray {orig: position, dir: point_to.normalize()}
+        Ray {orig: position, dir: point_to.normalize(), spread: 0.0}
+    }
+    // as the ray widened into a cone, for primary rays covering a pixel
+    pub fn with_spread(self, spread: f64) -> Ray {
+        Ray {spread, ..self}
     }
     pub fn position_at(&self, t: f64) -> Vec3 {
         self.orig + t * self.dir
     }
+    // the approximate width of the cone at distance t, for picking a texture lod
+    pub fn footprint_at(&self, t: f64) -> f64 {
+        self.spread * t
+    }
 }
 
 #[cfg(test)]
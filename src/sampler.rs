@@ -0,0 +1,219 @@
+use rand::rngs::StdRng;
+use rand::{Error, Rng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::f64::consts::FRAC_PI_4;
+
+use crate::camera::ApertureShape;
+
+// every random draw made while rendering comes from this thread's rng so
+// that reseeding it per pixel fixes the noise pattern of the image
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+// a handle on the current thread's rng, used in place of thread_rng
+#[derive(Debug, Clone, Copy)]
+pub struct SamplerRng;
+
+pub fn rng() -> SamplerRng {
+    SamplerRng
+}
+
+// pixel (i, j) then draws the same sequence in every render with the same
+// frame_seed, whatever happens in the other pixels
+pub fn seed_pixel(i: u32, j: u32, frame_seed: u64) {
+    let pixel = (u64::from(j) << 32) | u64::from(i);
+    let seed = splitmix64(frame_seed ^ splitmix64(pixel));
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+// scrambles neighbouring integers into unrelated seeds
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// how the subpixel offsets and lens positions of the camera rays are picked,
+// the low discrepancy sequences cover the pixel more evenly than random
+// jitter for the same number of samples
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum Sampler {
+    #[default]
+    Random,
+    // bases 2 and 3 for the subpixel offset and 5 and 7 for the lens
+    Halton,
+    // the first two Sobol dimensions for the subpixel offset, there being
+    // only those here the lens takes bases 5 and 7 of the Halton sequence
+    Sobol,
+}
+
+impl Sampler {
+    // the random shift applied to every point of one pixel, drawn before
+    // its samples so that neighbouring pixels don't repeat the same pattern,
+    // the random sampler has no need of one
+    pub fn pixel_shift(&self) -> [f64; 4] {
+        match self {
+            Sampler::Random => [0.0; 4],
+            Sampler::Halton | Sampler::Sobol => rng().gen(),
+        }
+    }
+
+    // the subpixel offset in the unit square and the lens position in the
+    // aperture of sample index of a pixel
+    pub fn camera_sample(
+        &self,
+        index: u32,
+        shift: [f64; 4],
+        aperture: ApertureShape,
+    ) -> ([f64; 2], [f64; 2]) {
+        let shifted = |value: f64, by: f64| (value + by).fract();
+        let lens = || {
+            aperture.sample_square([
+                shifted(radical_inverse(index, 5), shift[2]),
+                shifted(radical_inverse(index, 7), shift[3]),
+            ])
+        };
+        match self {
+            Sampler::Random => (rng().gen(), aperture.sample(&mut rng())),
+            Sampler::Halton => (
+                [
+                    shifted(radical_inverse(index, 2), shift[0]),
+                    shifted(radical_inverse(index, 3), shift[1]),
+                ],
+                lens(),
+            ),
+            Sampler::Sobol => {
+                let [u, v] = sobol_2d(index);
+                ([shifted(u, shift[0]), shifted(v, shift[1])], lens())
+            }
+        }
+    }
+}
+
+// the digits of index in base mirrored about the point, the nth point of
+// the van der Corput sequence, which the Halton sequence pairs in prime bases
+pub fn radical_inverse(mut index: u32, base: u32) -> f64 {
+    let inv_base = 1.0 / f64::from(base);
+    let (mut reversed, mut scale) = (0.0, inv_base);
+    while index > 0 {
+        reversed += f64::from(index % base) * scale;
+        index /= base;
+        scale *= inv_base;
+    }
+    reversed
+}
+
+// the point index of the first two dimensions of the Sobol sequence, in
+// index rather than gray code order, the first being the base 2 radical
+// inverse and the second taking its direction numbers from x + 1
+pub fn sobol_2d(index: u32) -> [f64; 2] {
+    let first = index.reverse_bits();
+    let (mut second, mut direction, mut bits) = (0u32, 1u32 << 31, index);
+    while bits > 0 {
+        if bits & 1 == 1 {
+            second ^= direction;
+        }
+        direction ^= direction >> 1;
+        bits >>= 1;
+    }
+    let to_unit = |value: u32| f64::from(value) / 4294967296.0;
+    [to_unit(first), to_unit(second)]
+}
+
+// Shirley's concentric map of the unit square onto the unit disc, keeping
+// points that are evenly spread in the square evenly spread in the disc
+pub fn square_to_disc(point: [f64; 2]) -> [f64; 2] {
+    let (a, b) = (2.0 * point[0] - 1.0, 2.0 * point[1] - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return [0.0, 0.0];
+    }
+    let (radius, angle) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, 2.0 * FRAC_PI_4 - FRAC_PI_4 * (a / b))
+    };
+    [radius * angle.cos(), radius * angle.sin()]
+}
+
+impl RngCore for SamplerRng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_pixel_test() {
+        let draw = |i: u32, j: u32, frame_seed: u64| {
+            seed_pixel(i, j, frame_seed);
+            (0..4).map(|_| rng().gen::<f64>()).collect::<Vec<f64>>()
+        };
+        let first = draw(3, 5, 1);
+        assert_eq!(draw(3, 5, 1), first);
+        assert_ne!(draw(5, 3, 1), first);
+        assert_ne!(draw(3, 5, 2), first);
+    }
+
+    #[test]
+    fn low_discrepancy_test() {
+        let halton: Vec<(f64, f64)> = (1..5)
+            .map(|index| (radical_inverse(index, 2), radical_inverse(index, 3)))
+            .collect();
+        let expected = [
+            (0.5, 1.0 / 3.0),
+            (0.25, 2.0 / 3.0),
+            (0.75, 1.0 / 9.0),
+            (0.125, 4.0 / 9.0),
+        ];
+        for (point, reference) in halton.iter().zip(expected) {
+            assert_eq!(point.0, reference.0);
+            assert!((point.1 - reference.1).abs() < 1.0e-12);
+        }
+        let sobol: Vec<[f64; 2]> = (0..5).map(sobol_2d).collect();
+        assert_eq!(
+            sobol,
+            [
+                [0.0, 0.0],
+                [0.5, 0.5],
+                [0.25, 0.75],
+                [0.75, 0.25],
+                [0.125, 0.625]
+            ]
+        );
+
+        // the same pixel seed gives the same shifted points every time
+        let draw = |sampler: Sampler| {
+            seed_pixel(1, 2, 3);
+            let shift = sampler.pixel_shift();
+            (0..8)
+                .map(|index| sampler.camera_sample(index, shift, ApertureShape::Circle))
+                .collect::<Vec<_>>()
+        };
+        for sampler in [Sampler::Halton, Sampler::Sobol] {
+            let samples = draw(sampler);
+            assert_eq!(draw(sampler), samples);
+            for (offset, lens) in samples {
+                assert!(offset.iter().all(|coord| (0.0..1.0).contains(coord)));
+                assert!(lens[0] * lens[0] + lens[1] * lens[1] <= 1.0 + 1.0e-12);
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::boundingvolume::CoveredScene;
+use crate::camera::Camera;
+use crate::config::Config;
+use crate::settings::RenderSettings;
+
+// a scene file parsed and covered, ready to render
+pub struct CachedScene {
+    pub scene: CoveredScene,
+    pub camera: Camera,
+    pub settings: RenderSettings,
+}
+
+// keeps the last scene loaded along with the hash of its file contents, so
+// rendering the same file again skips parsing it and building the tree
+#[derive(Default)]
+pub struct SceneCache {
+    hash: Option<u64>,
+    cached: Option<CachedScene>,
+    builds: u32,
+}
+
+impl SceneCache {
+    pub fn new() -> SceneCache {
+        SceneCache::default()
+    }
+
+    // the scene for the contents of a scene file, only parsed and covered
+    // when they differ from the last contents loaded
+    pub fn load(&mut self, contents: &[u8]) -> serde_json::Result<&CachedScene> {
+        let hash = contents_hash(contents);
+        if self.hash != Some(hash) || self.cached.is_none() {
+            let config = Config::from_slice(contents)?;
+            self.cached = Some(CachedScene {
+                scene: CoveredScene::with_scale(config.hittables, config.settings.scene_scale),
+                camera: config.camera.setup(),
+                settings: config.settings,
+            });
+            self.hash = Some(hash);
+            self.builds += 1;
+        } else {
+            debug!("Scene unchanged, reusing its covering tree");
+        }
+        Ok(self.cached.as_ref().expect("the scene was just cached"))
+    }
+
+    // how many times a covering tree has been built
+    pub fn builds(&self) -> u32 {
+        self.builds
+    }
+}
+
+fn contents_hash(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scene_cache_test() {
+        let contents = br##"{
+            "camera": {"lookat": [0.0, 0.0, 0.0], "lookfrom": [0.0, 0.0, -4.0],
+                       "inv_focal_length": 0.5, "aperture": 0.0, "horiz_res": 4, "vert_res": 4},
+            "hittables": [
+                {"shape": {"Sphere": {"centre": [0.0, 0.0, 0.0], "radius": 1.0}},
+                 "material": {"Emitter": {"albedo": "#ff8040", "intensity": 1.0}}}
+            ]
+        }"##;
+        let mut cache = SceneCache::new();
+        cache.load(contents).expect("scene should load");
+        let cached = cache.load(contents).expect("scene should load");
+        assert_eq!(cached.scene.hittables().len(), 1);
+        assert_eq!(cache.builds(), 1);
+
+        let moved = String::from_utf8_lossy(contents).replace("-4.0", "-5.0");
+        let cached = cache.load(moved.as_bytes()).expect("scene should load");
+        assert_eq!(cached.camera.lookfrom[2], -5.0);
+        assert_eq!(cache.builds(), 2);
+
+        // a file that no longer parses keeps the last good scene
+        assert!(cache.load(b"{").is_err());
+        assert!(cache.load(moved.as_bytes()).is_ok());
+        assert_eq!(cache.builds(), 2);
+    }
+}
@@ -1,43 +1,52 @@
-use crate::boundingvolume::{make_coveringtree, BoundingBox, Cover, CoveringTree};
+use crate::boundingvolume::CoveredScene;
 use crate::camera::Camera;
-use crate::geometry::{Shape, Sphere};
+use crate::geometry::{Quad, Shape, Sphere, Triangle};
 use crate::materials::Material;
 use crate::vector::Vec3;
 use crate::Color;
 use crate::Hittable;
 
-use rand::rngs::ThreadRng;
 use rand::Rng;
+use std::collections::HashMap;
 
-pub fn gen_scene() -> Box<CoveringTree> {
-    let mut rng = rand::thread_rng();
-    let mut scene: Vec<BoundingBox> = Vec::new();
+pub fn gen_scene() -> CoveredScene {
+    gen_scene_from(&mut rand::thread_rng())
+}
+
+// the random scene with the placement and materials drawn from rng
+pub fn gen_scene_from<R: Rng + ?Sized>(rng: &mut R) -> CoveredScene {
+    CoveredScene::new(gen_hittables_from(rng))
+}
+
+pub fn gen_hittables_from<R: Rng + ?Sized>(rng: &mut R) -> Vec<Hittable> {
+    let mut scene: Vec<Hittable> = Vec::new();
 
     let ground_sphere = Sphere::new(Vec3([0.0, -1000.0, 0.0]), 1000.0);
     let ground = Hittable {
         shape: Shape::Sphere(ground_sphere),
         material: Material::Diffuse {
-            albedo: Color::new(0.5, 0.5, 0.5),
+            albedo: Color::new(0.5, 0.5, 0.5).into(),
         },
     };
-    scene.push(ground.make_covering());
+    scene.push(ground);
 
     let big_sphere1 = Sphere::new(Vec3([0.0, 1.0, 0.0]), 1.0);
     let glass_sphere = Hittable {
         shape: Shape::Sphere(big_sphere1),
         material: Material::Dielectric {
             refractive_index: 1.5,
+            tint: Color::new(1.0, 1.0, 1.0),
         },
     };
-    scene.push(glass_sphere.make_covering());
+    scene.push(glass_sphere);
     let big_sphere2 = Sphere::new(Vec3([-4.0, 1.0, 0.0]), 1.0);
     let matte_sphere = Hittable {
         shape: Shape::Sphere(big_sphere2),
         material: Material::Diffuse {
-            albedo: Color::new(0.4, 0.2, 0.1),
+            albedo: Color::new(0.4, 0.2, 0.1).into(),
         },
     };
-    scene.push(matte_sphere.make_covering());
+    scene.push(matte_sphere);
     let big_sphere3 = Sphere::new(Vec3([4.0, 1.0, 0.0]), 1.0);
     let metal_sphere = Hittable {
         shape: Shape::Sphere(big_sphere3),
@@ -46,7 +55,7 @@ pub fn gen_scene() -> Box<CoveringTree> {
             fuzz: 0.0,
         },
     };
-    scene.push(metal_sphere.make_covering());
+    scene.push(metal_sphere);
 
     for x in -11..11 {
         for z in -11..11 {
@@ -55,16 +64,12 @@ pub fn gen_scene() -> Box<CoveringTree> {
                 0.2,
                 z as f64 + 0.9 * rng.gen::<f64>(),
             ]);
-            let hittable = gen_hittable(&mut rng, location);
-            scene.push(hittable.make_covering());
+            let hittable = gen_hittable(rng, location);
+            scene.push(hittable);
         }
     }
 
-    let mut bboxed = scene.into_boxed_slice();
-
-    println!("number of BoundingBox: {}", bboxed.len());
-
-    make_coveringtree(&mut bboxed)
+    scene
 }
 
 pub fn default_camera() -> Camera {
@@ -78,7 +83,7 @@ pub fn default_camera() -> Camera {
     )
 }
 
-fn gen_hittable(rng: &mut ThreadRng, location: Vec3) -> Hittable {
+fn gen_hittable<R: Rng + ?Sized>(rng: &mut R, location: Vec3) -> Hittable {
     let small_sphere = Sphere::new(location, 0.2);
     let material: Material;
 
@@ -87,7 +92,9 @@ fn gen_hittable(rng: &mut ThreadRng, location: Vec3) -> Hittable {
     if probability < 0.8 {
         let (r, g, b) = rng.gen::<(f64, f64, f64)>();
         let albedo = Color::new(r, g, b);
-        material = Material::Diffuse { albedo };
+        material = Material::Diffuse {
+            albedo: albedo.into(),
+        };
     } else if probability < 0.95 {
         let albedo = Color::new(
             rng.gen_range(0.5..1.0),
@@ -99,6 +106,7 @@ fn gen_hittable(rng: &mut ThreadRng, location: Vec3) -> Hittable {
     } else {
         material = Material::Dielectric {
             refractive_index: rng.gen_range(1.0..2.0),
+            tint: Color::new(1.0, 1.0, 1.0),
         };
     }
 
@@ -108,22 +116,277 @@ fn gen_hittable(rng: &mut ThreadRng, location: Vec3) -> Hittable {
     }
 }
 
-pub fn debug_scene() -> Box<CoveringTree> {
-    let mut scene: Vec<BoundingBox> = Vec::new();
+// a hollow glass shell, the inner sphere has a negative radius so its normal
+// points inward and the dielectric treats it as the glass to air boundary
+pub fn glass_bubble(centre: Vec3, outer_r: f64, thickness: f64) -> Vec<Hittable> {
+    let glass = || Material::Dielectric {
+        refractive_index: 1.5,
+        tint: Color::new(1.0, 1.0, 1.0),
+    };
+    vec![
+        Hittable {
+            shape: Shape::Sphere(Sphere::new(centre, outer_r)),
+            material: glass(),
+        },
+        Hittable {
+            shape: Shape::Sphere(Sphere::new(centre, -(outer_r - thickness))),
+            material: glass(),
+        },
+    ]
+}
+
+// the six faces of the parallelepiped spanned by edges from corner, facing outward
+pub fn cuboid(corner: Vec3, edges: [Vec3; 3], material: Material) -> Vec<Hittable> {
+    let [mut a, mut b, c] = edges;
+    if a.cross(&b).dotprod(&c) < 0.0 {
+        (a, b) = (b, a); // left-handed edges would turn every face inward
+    }
+    let far = corner + a + b + c;
+    let faces = [
+        Quad::new(corner, b, a),
+        Quad::new(corner, c, b),
+        Quad::new(corner, a, c),
+        Quad::new(far - a - b, a, b),
+        Quad::new(far - b - c, b, c),
+        Quad::new(far - c - a, c, a),
+    ];
+    faces
+        .into_iter()
+        .map(|quad| Hittable {
+            shape: Shape::Quad(quad),
+            material: material.clone(),
+        })
+        .collect()
+}
+
+// a triangle mesh approximating the sphere, made by splitting each face
+// of an icosahedron into four subdivisions times and pushing the new
+// vertices out onto the sphere, giving 20 * 4^subdivisions triangles
+pub fn tessellated_sphere(
+    centre: Vec3,
+    radius: f64,
+    subdivisions: u32,
+    material: Material,
+) -> Vec<Hittable> {
+    let (mut vertices, mut faces) = icosahedron();
+    for _ in 0..subdivisions {
+        // the vertex made on each edge, shared by the faces either side
+        let mut midpoints = HashMap::<(usize, usize), usize>::new();
+        let mut midpoint = |i: usize, j: usize| {
+            *midpoints.entry((i.min(j), i.max(j))).or_insert_with(|| {
+                vertices.push((0.5 * (vertices[i] + vertices[j])).normalize());
+                vertices.len() - 1
+            })
+        };
+        faces = faces
+            .into_iter()
+            .flat_map(|[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+    faces
+        .into_iter()
+        .map(|face| {
+            let [a, b, c] = face.map(|i| centre + radius * vertices[i]);
+            Hittable {
+                shape: Shape::Triangle(Triangle::new(a, b, c)),
+                material: material.clone(),
+            }
+        })
+        .collect()
+}
+
+// the unit vertices and the faces, anticlockwise seen from outside
+fn icosahedron() -> (Vec<Vec3>, Vec<[usize; 3]>) {
+    let t = 0.5 * (1.0 + 5.0_f64.sqrt());
+    let vertices = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .into_iter()
+    .map(|vertex| Vec3(vertex).normalize())
+    .collect();
+    let faces = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+    (vertices, faces)
+}
+
+// the canonical Cornell box, 555 units to a side with the open face towards the camera
+pub fn cornell_box() -> (Vec<Hittable>, Camera) {
+    let side = 555.0;
+    let diffuse = |r, g, b| Material::Diffuse {
+        albedo: Color::new(r, g, b).into(),
+    };
+    let red = diffuse(0.65, 0.05, 0.05);
+    let green = diffuse(0.12, 0.45, 0.15);
+    let white = || diffuse(0.73, 0.73, 0.73);
+
+    let x = Vec3([side, 0.0, 0.0]);
+    let y = Vec3([0.0, side, 0.0]);
+    let z = Vec3([0.0, 0.0, side]);
+    let origin = Vec3([0.0, 0.0, 0.0]);
+    // every wall faces into the box
+    let walls = [
+        (Quad::new(x, z, y), red),
+        (Quad::new(origin, y, z), green),
+        (Quad::new(origin, z, x), white()),
+        (Quad::new(y, x, z), white()),
+        (Quad::new(z, y, x), white()),
+    ];
+    let mut scene: Vec<Hittable> = walls
+        .into_iter()
+        .map(|(quad, material)| Hittable {
+            shape: Shape::Quad(quad),
+            material,
+        })
+        .collect();
+
+    scene.push(Hittable {
+        shape: Shape::Quad(Quad::new(
+            Vec3([213.0, 554.0, 227.0]),
+            Vec3([130.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, 105.0]),
+        )),
+        material: Material::Emitter {
+            albedo: Color::new(1.0, 1.0, 1.0),
+            intensity: 15.0,
+        },
+    });
+
+    let up = Vec3([0.0, 1.0, 0.0]);
+    let block = |height: f64, angle: f64| {
+        [
+            Vec3([165.0, 0.0, 0.0]).rotate_axis_angle(up, angle),
+            Vec3([0.0, height, 0.0]),
+            Vec3([0.0, 0.0, 165.0]).rotate_axis_angle(up, angle),
+        ]
+    };
+    let short_angle = -18.0_f64.to_radians();
+    let tall_angle = 15.0_f64.to_radians();
+    scene.extend(cuboid(
+        Vec3([130.0, 0.0, 65.0]),
+        block(165.0, short_angle),
+        white(),
+    ));
+    scene.extend(cuboid(
+        Vec3([265.0, 0.0, 295.0]),
+        block(330.0, tall_angle),
+        white(),
+    ));
+
+    // a 40 degree field of view from in front of the open face
+    let camera = Camera::build(
+        Vec3([278.0, 278.0, 0.0]),
+        Vec3([278.0, 278.0, -800.0]),
+        2.0 * 20.0_f64.to_radians().tan(),
+        0.0,
+        512,
+        512,
+    );
+    (scene, camera)
+}
+
+// a unit sphere of the material on a grey floor under a key and a fill
+// light, framed as a small thumbnail for previewing materials
+pub fn material_swatch(material: Material) -> (Vec<Hittable>, Camera) {
+    let light = |intensity| Material::Emitter {
+        albedo: Color::new(1.0, 1.0, 1.0),
+        intensity,
+    };
+    let scene = vec![
+        Hittable {
+            shape: Shape::Sphere(Sphere::new(Vec3([0.0, 1.0, 0.0]), 1.0)),
+            material,
+        },
+        Hittable {
+            shape: Shape::Quad(Quad::new(
+                Vec3([-5.0, 0.0, -5.0]),
+                Vec3([0.0, 0.0, 10.0]),
+                Vec3([10.0, 0.0, 0.0]),
+            )),
+            material: Material::Diffuse {
+                albedo: Color::new(0.5, 0.5, 0.5).into(),
+            },
+        },
+        // the key light above and in front to the left
+        Hittable {
+            shape: Shape::Quad(Quad::new(
+                Vec3([-3.0, 5.0, -4.0]),
+                Vec3([2.0, 0.0, 0.0]),
+                Vec3([0.0, 0.0, 2.0]),
+            )),
+            material: light(6.0),
+        },
+        // a dimmer fill to the right
+        Hittable {
+            shape: Shape::Quad(Quad::new(
+                Vec3([4.0, 1.0, -2.0]),
+                Vec3([0.0, 2.0, 0.0]),
+                Vec3([0.0, 0.0, 2.0]),
+            )),
+            material: light(2.0),
+        },
+    ];
+
+    let camera = Camera::build(
+        Vec3([0.0, 1.0, 0.0]),
+        Vec3([0.0, 2.0, -5.0]),
+        0.6,
+        0.0,
+        128,
+        128,
+    );
+    (scene, camera)
+}
+
+pub fn debug_scene() -> CoveredScene {
+    CoveredScene::new(debug_hittables())
+}
+
+pub fn debug_hittables() -> Vec<Hittable> {
+    let mut scene: Vec<Hittable> = Vec::new();
     let big_sphere2 = Sphere::new(Vec3([0.0, 0.0, 0.0]), 5.0);
     let matte_sphere = Hittable {
         shape: Shape::Sphere(big_sphere2),
         material: Material::Diffuse {
-            albedo: Color::new(0.4, 0.2, 0.1),
+            albedo: Color::new(0.4, 0.2, 0.1).into(),
         },
     };
-    scene.push(matte_sphere.make_covering());
-
-    let mut bboxed = scene.into_boxed_slice();
-
-    println!("number of BoundingBox: {}", bboxed.len());
+    scene.push(matte_sphere);
 
-    make_coveringtree(&mut bboxed)
+    scene
 }
 
 pub fn debug_camera() -> Camera {
@@ -136,3 +399,124 @@ pub fn debug_camera() -> Camera {
         512,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glass_bubble_test() {
+        let centre = Vec3([1.0, 2.0, 3.0]);
+        let bubble = glass_bubble(centre, 1.0, 0.1);
+        assert_eq!(bubble.len(), 2);
+
+        let inner_surface = centre + Vec3([0.9, 0.0, 0.0]);
+        let inner_normal = bubble[1].shape.normal_at(inner_surface);
+        assert!(inner_normal.dotprod(&(inner_surface - centre)) < 0.0);
+        assert!((inner_normal.norm() - 1.0).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn tessellated_sphere_test() {
+        let centre = Vec3([1.0, 2.0, 3.0]);
+        let counts: Vec<usize> = (0..4)
+            .map(|subdivisions| {
+                tessellated_sphere(centre, 2.0, subdivisions, Material::UVDebug).len()
+            })
+            .collect();
+        assert_eq!(counts, [20, 80, 320, 1280]);
+
+        for hittable in tessellated_sphere(centre, 2.0, 2, Material::UVDebug) {
+            let Shape::Triangle(triangle) = &hittable.shape else {
+                panic!("the mesh is made of triangles")
+            };
+            for vertex in triangle.vertices {
+                assert!(((vertex - centre).norm() - 2.0).abs() < 1.0e-12);
+            }
+            let face_centre =
+                (1.0 / 3.0) * (triangle.vertices[0] + triangle.vertices[1] + triangle.vertices[2]);
+            assert!(
+                hittable
+                    .shape
+                    .normal_at(face_centre)
+                    .dotprod(&(face_centre - centre))
+                    > 0.0
+            );
+        }
+
+        // a ray through the centre hits the mesh a little inside the sphere
+        let mesh = tessellated_sphere(centre, 2.0, 3, Material::UVDebug);
+        let ray = crate::ray::Ray::new(centre + Vec3([0.3, 0.2, 5.0]), Vec3([0.0, 0.0, -1.0]));
+        let nearest = mesh
+            .iter()
+            .filter_map(|hittable| hittable.shape.intersect(&ray))
+            .fold(f64::INFINITY, f64::min);
+        let exact = 5.0 - (4.0_f64 - 0.13).sqrt();
+        assert!(
+            nearest > exact && nearest < exact + 0.05,
+            "{} against {}",
+            nearest,
+            exact
+        );
+    }
+
+    #[test]
+    fn cuboid_faces_outward_test() {
+        let corner = Vec3([1.0, 1.0, 1.0]);
+        let edges = [
+            Vec3([0.0, 0.0, 2.0]),
+            Vec3([0.0, 2.0, 0.0]),
+            Vec3([2.0, 0.0, 0.0]),
+        ];
+        let faces = cuboid(corner, edges, Material::UVDebug);
+        assert_eq!(faces.len(), 6);
+
+        let centre = Vec3([2.0, 2.0, 2.0]);
+        for face in &faces {
+            let Shape::Quad(quad) = &face.shape else {
+                panic!("cuboid faces are quads")
+            };
+            let face_centre = quad.corner + 0.5 * (quad.edge_u + quad.edge_v);
+            let normal = face.shape.normal_at(face_centre);
+            assert_eq!((face_centre - centre).dotprod(&normal), 1.0);
+        }
+    }
+
+    #[test]
+    fn material_swatch_test() {
+        let (scene, cam) = material_swatch(Material::Metal {
+            albedo: Color::new(0.9, 0.8, 0.7),
+            fuzz: 0.25,
+        });
+        let spheres: Vec<&Hittable> = scene
+            .iter()
+            .filter(|hittable| matches!(hittable.shape, Shape::Sphere(_)))
+            .collect();
+        assert_eq!(spheres.len(), 1);
+        assert!(matches!(
+            spheres[0].material,
+            Material::Metal { fuzz, .. } if fuzz == 0.25
+        ));
+        assert_eq!((cam.horiz_res, cam.vert_res), (128, 128));
+    }
+
+    #[test]
+    fn cornell_box_test() {
+        let (scene, _) = cornell_box();
+        let emitters = scene
+            .iter()
+            .filter(|hittable| matches!(hittable.material, Material::Emitter { .. }))
+            .count();
+        assert_eq!(emitters, 1);
+
+        let walls = scene
+            .iter()
+            .filter(|hittable| match &hittable.shape {
+                Shape::Quad(quad) => quad.edge_u.norm() == 555.0 && quad.edge_v.norm() == 555.0,
+                _ => false,
+            })
+            .count();
+        assert_eq!(walls, 5);
+        assert_eq!(scene.len(), 5 + 1 + 2 * 6);
+    }
+}
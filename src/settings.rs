@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use serde_with::DurationSecondsWithFrac;
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::color::{Color, Gamma, Tonemap};
+use crate::geometry::DEFAULT_EPSILON;
+use crate::integrator::IntegratorKind;
+use crate::photons::{PhotonMap, PhotonSettings};
+use crate::sampler::Sampler;
+
+// how a scene is rendered, as opposed to what is in it
+#[serde_with::serde_as]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    // how each camera ray is turned into a color, the path tracer unless
+    // one of the views for inspecting the scene is wanted
+    pub integrator: IntegratorKind,
+    pub scatter_depth: u8,
+    pub background: Background,
+    pub gamma: Gamma,
+    // applied to the exposed linear colors ahead of the gamma
+    pub tonemap: Tonemap,
+    pub crop: Option<CropWindow>,
+    // false traces one ray through each pixel centre with no lens or
+    // subpixel sampling, making the camera rays exactly reproducible
+    pub antialiasing: bool,
+    // half-width in pixels of the box filter each sample is spread with,
+    // 0.5 keeps every sample within its own pixel and is the least allowed
+    pub filter_radius: f64,
+    // the pattern the subpixel offsets and lens positions are picked in
+    pub sampler: Sampler,
+    // hits closer than this along a ray are ignored, it should grow with
+    // the scale of the scene as the rounding error on hit points does
+    pub epsilon: f64,
+    // after this many diffuse bounces the path ends with the background as
+    // its light instead of tracing on, trading bias for less noise, a scene
+    // file may not set it to 0
+    pub diffuse_bounce_cap: Option<u8>,
+    // in stops, the linear pixel colors are scaled by 2^exposure
+    pub exposure: f64,
+    // at each diffuse bounce also sample a point on an emitting disc or
+    // quad, combined with the scattered ray by multiple importance sampling
+    pub light_sampling: bool,
+    // the largest value written to the ppm, 255 for 8-bit channels and up
+    // to 65535 for 16-bit ones keeping smooth gradients for post-processing
+    pub ppm_maxval: u16,
+    // in seconds, the render stops at the first scanline finished after
+    // this long and writes out what it has, leaving the rest black
+    #[serde_as(as = "Option<DurationSecondsWithFrac<f64>>")]
+    pub time_limit: Option<Duration>,
+    // a flat fill light every diffuse hit reflects whatever can see it, not
+    // physical but keeps previews of unlit scenes from being black
+    pub ambient: Color,
+    // seeds the samples of each pixel from its position and this, so the
+    // noise stays put between renders of slightly different scenes
+    pub seed: Option<u64>,
+    // ends each path at the second surface it hits, keeping only the light
+    // the first one gets straight from the emitters and the background
+    pub direct_only: bool,
+    // offsets each pixel by an ordered dither before it is rounded to the
+    // output levels, breaking up banding across smooth gradients
+    pub dither: bool,
+    // the number of threads tile ranges are rendered on
+    pub threads: usize,
+    // the positions among the hittables of the emitters light sampling
+    // picks from, set from the lights of the scene file, all of them if none
+    #[serde(skip)]
+    pub lights: Option<Vec<usize>>,
+    // set from the scene_scale of the scene file, for sizing the flat boxes
+    // of the covering tree
+    #[serde(skip)]
+    pub scene_scale: f64,
+    // traces photons out of the lights through specular and transmissive
+    // surfaces before rendering, lighting the caustics paths from the
+    // camera rarely find by gathering them wherever those paths go diffuse
+    pub caustics: Option<PhotonSettings>,
+    // the photons the caustics prepass traced
+    #[serde(skip)]
+    pub caustics_map: Option<PhotonMap>,
+}
+
+// a sub-rectangle of the image in pixels, from (x0, y0) up to but not
+// including (x1, y1), only this part is rendered and written out
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CropWindow {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            integrator: IntegratorKind::default(),
+            scatter_depth: 10,
+            background: Background::default(),
+            gamma: Gamma::default(),
+            tonemap: Tonemap::default(),
+            crop: None,
+            antialiasing: true,
+            filter_radius: 0.5,
+            sampler: Sampler::default(),
+            epsilon: DEFAULT_EPSILON,
+            diffuse_bounce_cap: None,
+            exposure: 0.0,
+            light_sampling: false,
+            ppm_maxval: 255,
+            time_limit: None,
+            ambient: Color::new(0.0, 0.0, 0.0),
+            seed: None,
+            direct_only: false,
+            dither: false,
+            threads: 1,
+            lights: None,
+            scene_scale: 1.0,
+            caustics: None,
+            caustics_map: None,
+        }
+    }
+}
+
+// the epsilon for a scene whose objects are about scene_scale units across,
+// the rounding error on hit points growing in proportion to their size
+pub fn epsilon_for_scale(scene_scale: f64) -> f64 {
+    DEFAULT_EPSILON * scene_scale
+}
+
+impl RenderSettings {
+    // the (column, row) pixel ranges to render, clamped to the image
+    pub fn region(&self, cam: &Camera) -> (Range<u32>, Range<u32>) {
+        match self.crop {
+            Some(crop) => (
+                crop.x0.min(cam.horiz_res)..crop.x1.min(cam.horiz_res),
+                crop.y0.min(cam.vert_res)..crop.y1.min(cam.vert_res),
+            ),
+            None => (0..cam.horiz_res, 0..cam.vert_res),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use crate::geometry::{self, BoundBox, Shape};
+use crate::Hittable;
+
+// what a scene is made of, for auditing large scene files
+pub struct SceneSummary {
+    pub hittables: usize,
+    pub materials: BTreeMap<&'static str, usize>,
+    pub shapes: BTreeMap<&'static str, usize>,
+    // the box covering every hittable, None for an empty scene
+    pub bounds: Option<BoundBox>,
+}
+
+pub fn scene_summary(scene: &[Hittable]) -> SceneSummary {
+    let mut materials = BTreeMap::new();
+    let mut shapes = BTreeMap::new();
+    let mut bounds: Option<BoundBox> = None;
+    for hittable in scene {
+        *materials.entry(hittable.material.kind()).or_insert(0) += 1;
+        *shapes.entry(hittable.shape.kind()).or_insert(0) += 1;
+        // bounding volumes are only made internally and never part of a
+        // scene file, and planes have no bounds to cover
+        if matches!(hittable.shape, Shape::BoundVolume(_)) || !hittable.shape.is_bounded() {
+            continue;
+        }
+        let bbox = BoundBox::surround(&hittable.shape);
+        bounds = Some(match bounds {
+            Some(covered) => geometry::cover(&covered, &bbox),
+            None => bbox,
+        });
+    }
+    SceneSummary {
+        hittables: scene.len(),
+        materials,
+        shapes,
+        bounds,
+    }
+}
+
+impl Display for SceneSummary {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let counts = |counts: &BTreeMap<&str, usize>| {
+            counts
+                .iter()
+                .map(|(kind, count)| format!("{} {}", kind, count))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        writeln!(f, "hittables: {}", self.hittables)?;
+        writeln!(f, "materials: {}", counts(&self.materials))?;
+        writeln!(f, "shapes: {}", counts(&self.shapes))?;
+        match &self.bounds {
+            Some(bounds) => write!(
+                f,
+                "bounds: [{}, {}] x [{}, {}] x [{}, {}]",
+                bounds[0].start,
+                bounds[0].end,
+                bounds[1].start,
+                bounds[1].end,
+                bounds[2].start,
+                bounds[2].end
+            ),
+            None => write!(f, "bounds: none"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::geometry::{Disc, Sphere};
+    use crate::intervals::Interval;
+    use crate::materials::Material;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn mixed_scene_summary_test() {
+        let diffuse = || Material::Diffuse {
+            albedo: Color::new(0.5, 0.5, 0.5).into(),
+        };
+        let scene = vec![
+            Hittable {
+                shape: Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+                material: diffuse(),
+            },
+            Hittable {
+                shape: Shape::Sphere(Sphere::new(Vec3([4.0, 0.0, 0.0]), 2.0)),
+                material: Material::Dielectric {
+                    refractive_index: 1.5,
+                    tint: Color::new(1.0, 1.0, 1.0),
+                },
+            },
+            Hittable {
+                shape: Shape::Disc(Disc::new(
+                    Vec3([0.0, -1.0, 0.0]),
+                    Vec3([0.0, 1.0, 0.0]),
+                    3.0,
+                )),
+                material: diffuse(),
+            },
+        ];
+
+        let summary = scene_summary(&scene);
+        assert_eq!(summary.hittables, 3);
+        assert_eq!(summary.materials["Diffuse"], 2);
+        assert_eq!(summary.materials["Dielectric"], 1);
+        assert_eq!(summary.shapes["Sphere"], 2);
+        assert_eq!(summary.shapes["Disc"], 1);
+
+        let bounds = summary.bounds.unwrap();
+        assert_eq!(bounds[0], Interval::new(-3.0, 6.0));
+        assert_eq!(bounds[1], Interval::new(-2.0, 2.0));
+        assert_eq!(bounds[2], Interval::new(-3.0, 3.0));
+
+        assert!(scene_summary(&[]).bounds.is_none());
+    }
+}
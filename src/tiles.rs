@@ -0,0 +1,264 @@
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::boundingvolume::CoveredScene;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::integrator;
+use crate::progress::Progress;
+use crate::ray::Ray;
+use crate::settings::RenderSettings;
+use crate::{render_film_reporting, write_ppm_header, Hittable};
+
+// how often the overall progress is printed while tiles are rendered
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+// tiles are numbered row by row from the top left of the image, so that a
+// range of them can be handed to each machine and merged back afterwards
+pub type TileId = u32;
+
+// the number of (columns, rows) of tiles covering the image, the tiles on
+// the right and bottom edges may be cut short
+pub fn tile_grid(width: u32, height: u32, tile_size: u32) -> (u32, u32) {
+    (width.div_ceil(tile_size), height.div_ceil(tile_size))
+}
+
+// the (column, row) pixel ranges of a tile
+pub fn tile_bounds(cam: &Camera, tile_size: u32, tile: TileId) -> (Range<u32>, Range<u32>) {
+    let (tile_columns, _) = tile_grid(cam.horiz_res, cam.vert_res, tile_size);
+    let (x0, y0) = (
+        (tile % tile_columns) * tile_size,
+        (tile / tile_columns) * tile_size,
+    );
+    (
+        x0..(x0 + tile_size).min(cam.horiz_res),
+        y0..(y0 + tile_size).min(cam.vert_res),
+    )
+}
+
+// renders tiles start..end, each as its encoded rgb channels row by row,
+// handed out one at a time to settings.threads threads
+pub fn render_tile_range(
+    cam: &Camera,
+    scene: &[Hittable],
+    settings: &RenderSettings,
+    spp: u32,
+    tile_size: u32,
+    start: TileId,
+    end: TileId,
+) -> Vec<(TileId, Vec<u16>)> {
+    let integrator = integrator::select(settings, &scene);
+    render_tiles(cam, settings, spp, tile_size, start..end, |ray| {
+        integrator.radiance_with_alpha(ray)
+    })
+}
+
+pub fn accel_render_tile_range(
+    cam: &Camera,
+    scene: &CoveredScene,
+    settings: &RenderSettings,
+    spp: u32,
+    tile_size: u32,
+    start: TileId,
+    end: TileId,
+) -> Vec<(TileId, Vec<u16>)> {
+    let integrator = integrator::select(settings, scene);
+    render_tiles(cam, settings, spp, tile_size, start..end, |ray| {
+        integrator.radiance_with_alpha(ray)
+    })
+}
+
+fn render_tiles<F>(
+    cam: &Camera,
+    settings: &RenderSettings,
+    spp: u32,
+    tile_size: u32,
+    tiles: Range<TileId>,
+    trace: F,
+) -> Vec<(TileId, Vec<u16>)>
+where
+    F: Fn(&Ray) -> (Color, f64) + Sync,
+{
+    let (tile_columns, tile_rows) = tile_grid(cam.horiz_res, cam.vert_res, tile_size);
+    let tiles: Vec<TileId> = (tiles.start..tiles.end.min(tile_columns * tile_rows)).collect();
+    let progress = Progress::new(tiles.len() as u32);
+    let next_tile = AtomicUsize::new(0);
+
+    let render_tile = |tile: TileId| {
+        let (columns, rows) = tile_bounds(cam, tile_size, tile);
+        // the scanlines of a tile say little about the whole, so only the
+        // shared count of finished tiles is reported
+        let film = render_film_reporting(cam, settings, spp, columns, rows, &trace, |_| {});
+        let channels = film
+            .encoded(settings)
+            .flat_map(|(r, g, b)| [r, g, b])
+            .collect();
+        progress.complete(1);
+        (tile, channels)
+    };
+
+    let mut rendered: Vec<(TileId, Vec<u16>)> = thread::scope(|scope| {
+        let reporter = scope.spawn(|| progress.report(REPORT_INTERVAL));
+        let workers: Vec<_> = (0..settings.threads.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut rendered = Vec::new();
+                    while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                        rendered.push(render_tile(*tile));
+                    }
+                    rendered
+                })
+            })
+            .collect();
+        let joined: Vec<_> = workers.into_iter().map(|worker| worker.join()).collect();
+        // let the reporter go before passing on any panic, or the scope
+        // would wait on it forever
+        progress.finish();
+        reporter.join().expect("the progress reporter panicked");
+        joined
+            .into_iter()
+            .flat_map(|worker| worker.expect("a render thread panicked"))
+            .collect()
+    });
+    rendered.sort_unstable_by_key(|(tile, _)| *tile);
+    rendered
+}
+
+// writes the full image as a ppm from tiles rendered with the same camera,
+// tile size and ppm_maxval, any tile not given is left black
+pub fn merge_tiles<W: Write>(
+    file: &mut W,
+    cam: &Camera,
+    tile_size: u32,
+    maxval: u16,
+    tiles: &[(TileId, Vec<u16>)],
+) {
+    let (width, height) = (cam.horiz_res as usize, cam.vert_res as usize);
+    let mut image = vec![0u16; 3 * width * height];
+    for (tile, channels) in tiles {
+        let (columns, rows) = tile_bounds(cam, tile_size, *tile);
+        let tile_width = 3 * columns.len();
+        for (row, tile_row) in rows.zip(channels.chunks(tile_width)) {
+            let offset = 3 * (row as usize * width + columns.start as usize);
+            image[offset..offset + tile_row.len()].copy_from_slice(tile_row);
+        }
+    }
+
+    let mut vis_stream = BufWriter::new(file);
+    write_ppm_header(&mut vis_stream, cam.horiz_res, cam.vert_res, maxval);
+    for pixel in image.chunks(3) {
+        writeln!(vis_stream, "{} {} {}", pixel[0], pixel[1], pixel[2])
+            .expect("Unable to write colors.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::Background;
+    use crate::geometry::{Shape, Sphere};
+    use crate::materials::Material;
+    use crate::render_into_file;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn tile_bounds_test() {
+        let cam = Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -4.0]),
+            0.5,
+            0.0,
+            5,
+            3,
+        );
+        assert_eq!(tile_grid(5, 3, 2), (3, 2));
+        assert_eq!(tile_bounds(&cam, 2, 0), (0..2, 0..2));
+        assert_eq!(tile_bounds(&cam, 2, 2), (4..5, 0..2));
+        assert_eq!(tile_bounds(&cam, 2, 4), (2..4, 2..3));
+    }
+
+    #[test]
+    fn merged_tiles_match_full_render_test() {
+        let scene = [Hittable {
+            shape: Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Emitter {
+                albedo: Color::new(1.0, 0.5, 0.25),
+                intensity: 1.0,
+            },
+        }];
+        let cam = Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -4.0]),
+            0.5,
+            0.0,
+            7,
+            5,
+        );
+        // without antialiasing the camera rays and so the pixels are exact
+        let settings = RenderSettings {
+            antialiasing: false,
+            background: Background::Solid(Color::new(0.0, 0.0, 1.0)),
+            ..Default::default()
+        };
+
+        let mut full = Vec::<u8>::new();
+        render_into_file(&mut full, &cam, &scene, &settings, 1);
+
+        let (tile_columns, tile_rows) = tile_grid(cam.horiz_res, cam.vert_res, 3);
+        assert_eq!(tile_columns * tile_rows, 6);
+        let tiles: Vec<(TileId, Vec<u16>)> = [(0, 1), (1, 3), (3, 4), (4, 6)]
+            .into_iter()
+            .flat_map(|(start, end)| render_tile_range(&cam, &scene, &settings, 1, 3, start, end))
+            .collect();
+        assert_eq!(tiles.len(), 6);
+
+        let mut merged = Vec::<u8>::new();
+        merge_tiles(&mut merged, &cam, 3, settings.ppm_maxval, &tiles);
+        assert_eq!(String::from_utf8(merged), String::from_utf8(full));
+
+        // more threads than tiles leaves some idle but changes nothing
+        let threaded = RenderSettings {
+            threads: 8,
+            ..settings
+        };
+        let threaded_tiles = render_tile_range(&cam, &scene, &threaded, 1, 3, 0, 6);
+        assert_eq!(threaded_tiles, tiles);
+    }
+
+    #[test]
+    fn wide_filter_tiles_match_full_render_test() {
+        let scene = [Hittable {
+            shape: Shape::Sphere(Sphere::new(Vec3([0.0, 0.0, 0.0]), 1.0)),
+            material: Material::Emitter {
+                albedo: Color::new(1.0, 0.5, 0.25),
+                intensity: 1.0,
+            },
+        }];
+        let cam = Camera::build(
+            Vec3([0.0, 0.0, 0.0]),
+            Vec3([0.0, 0.0, -4.0]),
+            0.5,
+            0.0,
+            7,
+            5,
+        );
+        // seeded per pixel, so each pixel traces the same samples however
+        // the image is cut up, and wide enough to spread them two pixels
+        let settings = RenderSettings {
+            seed: Some(433),
+            filter_radius: 1.75,
+            background: Background::Solid(Color::new(0.0, 0.0, 1.0)),
+            ..Default::default()
+        };
+
+        let mut full = Vec::<u8>::new();
+        render_into_file(&mut full, &cam, &scene, &settings, 4);
+        let tiles = render_tile_range(&cam, &scene, &settings, 4, 3, 0, 6);
+        let mut merged = Vec::<u8>::new();
+        merge_tiles(&mut merged, &cam, 3, settings.ppm_maxval, &tiles);
+        assert_eq!(String::from_utf8(merged), String::from_utf8(full));
+    }
+}
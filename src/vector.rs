@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::cmp::PartialEq;
+use std::f64::consts::PI;
 use std::fmt::{self, Display, Formatter};
 use std::ops;
 use std::ops::{Deref, DerefMut};
@@ -95,15 +96,32 @@ impl ops::AddAssign for Vec3 {
     }
 }
 
+// the products are unrolled over the fixed array and branch-free, inlined
+// as they sit in the innermost intersection loops
 impl Vec3 {
+    #[inline]
     pub fn norm(&self) -> f64 {
-        (self[0] * self[0] + self[1] * self[1] + self[2] * self[2]).sqrt()
+        self.norm_squared().sqrt()
     }
 
+    // for comparisons, which need no sqrt
+    #[inline]
+    pub fn norm_squared(&self) -> f64 {
+        self[0] * self[0] + self[1] * self[1] + self[2] * self[2]
+    }
+
+    #[inline]
     pub fn dotprod(&self, other: &Vec3) -> f64 {
         self[0] * other[0] + self[1] * other[1] + self[2] * other[2]
     }
 
+    // the Hadamard product, scaling each axis by the matching one of other
+    #[inline]
+    pub fn mul_componentwise(&self, other: &Vec3) -> Vec3 {
+        Vec3([self[0] * other[0], self[1] * other[1], self[2] * other[2]])
+    }
+
+    #[inline]
     pub fn cross(&self, other: &Vec3) -> Vec3 {
         Vec3([
             self[1] * other[2] - self[2] * other[1],
@@ -112,9 +130,30 @@ impl Vec3 {
         ])
     }
 
-    // Changes the input Vec3 to be a normalized Vec3
-    pub fn normalize(self) -> Vec3 {
-        self / self.norm()
+    // returns a new unit Vec3, leaving the borrowed one untouched
+    pub fn normalize(&self) -> Vec3 {
+        *self / self.norm()
+    }
+
+    // two unit vectors square to self and each other, with (u, v, self)
+    // right-handed, by Duff et al.'s branchless method which stays accurate
+    // all the way to the poles, self need not be normalized
+    pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        let n = self.normalize();
+        let sign = 1.0_f64.copysign(n[2]);
+        let a = -1.0 / (sign + n[2]);
+        let b = n[0] * n[1] * a;
+        (
+            Vec3([1.0 + sign * n[0] * n[0] * a, sign * b, -sign * n[0]]),
+            Vec3([b, sign + n[1] * n[1] * a, -n[1]]),
+        )
+    }
+
+    // Rodrigues' rotation formula, the axis does not need to be normalized
+    pub fn rotate_axis_angle(&self, axis: Vec3, angle: f64) -> Vec3 {
+        let k = axis.normalize();
+        let (sin, cos) = angle.sin_cos();
+        cos * *self + sin * k.cross(self) + (k.dotprod(self) * (1.0 - cos)) * k
     }
 }
 
@@ -122,6 +161,32 @@ pub fn lerp_vec3(p: Vec3, q: Vec3, t: f64) -> Vec3 {
     ((1.0 - t) * p) + (t * q)
 }
 
+// interpolates between unit vectors along the great circle joining them,
+// falling back to a normalized lerp when they are nearly parallel
+pub fn slerp_vec3(p: Vec3, q: Vec3, t: f64) -> Vec3 {
+    slerp_vec3_around(p, q, t, p.orthonormal_basis().0)
+}
+
+// as slerp_vec3, but turning about the part of axis square to p when p and
+// q are nearly opposite, as then no one great circle joins them
+pub fn slerp_vec3_around(p: Vec3, q: Vec3, t: f64, axis: Vec3) -> Vec3 {
+    let angle = p.dotprod(&q).clamp(-1.0, 1.0).acos();
+    if angle < 1.0e-6 {
+        return lerp_vec3(p, q, t).normalize();
+    }
+    if PI - angle < 1.0e-6 {
+        let square = axis - axis.dotprod(&p) * p;
+        let axis = if square.norm() > 1.0e-6 {
+            square
+        } else {
+            p.orthonormal_basis().0
+        };
+        return p.rotate_axis_angle(axis, t * angle);
+    }
+    let sin_angle = angle.sin();
+    (((1.0 - t) * angle).sin() / sin_angle) * p + ((t * angle).sin() / sin_angle) * q
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -132,6 +197,48 @@ mod tests {
         assert_eq!(u.norm(), 5.0)
     }
 
+    #[test]
+    fn orthonormal_basis_test() {
+        let normals = [
+            Vec3([0.0, 0.0, 1.0]),
+            Vec3([0.0, 0.0, -1.0]),
+            Vec3([1.0, 0.0, 0.0]),
+            Vec3([0.0, -3.0, 0.0]),
+            Vec3([1.0, 2.0, -3.0]),
+            // just off the south pole, where the simpler methods lose precision
+            Vec3([1.0e-9, -1.0e-9, -1.0]),
+        ];
+        for normal in normals {
+            let n = normal.normalize();
+            let (u, v) = normal.orthonormal_basis();
+            for (x, y) in [(u, v), (v, n), (n, u)] {
+                assert!((x.norm() - 1.0).abs() < 1.0e-12);
+                assert!(x.dotprod(&y).abs() < 1.0e-12);
+            }
+            // right-handed, u x v = n
+            assert!(
+                (u.cross(&v) - n).norm() < 1.0e-12,
+                "{} for {}",
+                u.cross(&v),
+                n
+            );
+        }
+    }
+
+    #[test]
+    fn norm_squared_test() {
+        let u = Vec3([3.0, 4.0, 12.0]);
+        assert_eq!(u.norm_squared(), 169.0);
+        assert_eq!(u.norm_squared(), u.norm() * u.norm());
+
+        // the unit ball rejection test gives the same verdict either way
+        for k in 0..1000 {
+            let t = k as f64 * 0.001;
+            let v = Vec3([t, 1.0 - t, (2.0 * t - 1.0) * 0.9]);
+            assert_eq!(v.norm_squared() > 1.0, v.norm() > 1.0);
+        }
+    }
+
     #[test]
     fn dotprod_test() {
         let u = Vec3([1.0, 0.0, 0.0]);
@@ -145,4 +252,45 @@ mod tests {
         let v = Vec3([0.0, 1.0, 0.0]);
         assert_eq!(u.cross(&v), Vec3([0.0, 0.0, 1.0]))
     }
+
+    #[test]
+    fn mul_componentwise_test() {
+        let u = Vec3([1.0, -2.0, 3.0]);
+        let v = Vec3([4.0, 0.5, -1.0]);
+        assert_eq!(u.mul_componentwise(&v), Vec3([4.0, -1.0, -3.0]));
+        assert_eq!(u.mul_componentwise(&v), v.mul_componentwise(&u));
+    }
+
+    #[test]
+    fn slerp_test() {
+        let u = Vec3([1.0, 0.0, 0.0]);
+        let v = Vec3([0.0, 1.0, 0.0]);
+        let halfway = slerp_vec3(u, v, 0.5);
+        assert!((halfway - Vec3([0.5_f64.sqrt(), 0.5_f64.sqrt(), 0.0])).norm() < 1.0e-12);
+        assert!((slerp_vec3(u, v, 1.0 / 3.0).norm() - 1.0).abs() < 1.0e-12);
+        assert_eq!(slerp_vec3(u, u, 0.5), u);
+
+        // opposite vectors are turned between about a square axis
+        let opposite = slerp_vec3(u, -1.0 * u, 0.5);
+        assert!((opposite.norm() - 1.0).abs() < 1.0e-12 && opposite.dotprod(&u).abs() < 1.0e-12);
+        assert!((slerp_vec3(u, -1.0 * u, 1.0) + u).norm() < 1.0e-12);
+        let around_y = slerp_vec3_around(u, -1.0 * u, 0.5, v);
+        assert!(around_y.dotprod(&v).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn borrowed_normalize_test() {
+        let u = Vec3([2.0, 0.0, 0.0]);
+        let v = Vec3([0.0, 3.0, 0.0]);
+        assert_eq!(u.cross(&v).normalize(), Vec3([0.0, 0.0, 1.0]));
+        assert_eq!(u.normalize(), Vec3([1.0, 0.0, 0.0]));
+        assert_eq!(u, Vec3([2.0, 0.0, 0.0]))
+    }
+
+    #[test]
+    fn rotate_axis_angle_test() {
+        let u = Vec3([1.0, 0.0, 0.0]);
+        let rotated = u.rotate_axis_angle(Vec3([0.0, 0.0, 1.0]), std::f64::consts::FRAC_PI_2);
+        assert!((rotated - Vec3([0.0, 1.0, 0.0])).norm() < 1.0e-12)
+    }
 }
@@ -0,0 +1,83 @@
+// renders the scenes under tests/golden with the seed of their settings
+// fixing every sample, and compares them to the reference png beside each
+// to catch changes to the rendered image the unit tests miss, running with
+// UPDATE_GOLDEN set writes the renders out as the new references instead
+use std::path::{Path, PathBuf};
+
+use raytracer::boundingvolume::CoveredScene;
+use raytracer::config::Config;
+
+const SAMPLES_PER_PIXEL: u32 = 16;
+// the most any channel of any pixel may be off by, in 8-bit levels, enough
+// for the rounding of the maths functions to differ between platforms
+const TOLERANCE: u8 = 3;
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+}
+
+fn check_golden(name: &str) {
+    let config = Config::from_path(golden_path(&format!("{}.json", name)))
+        .expect("the golden scene should load");
+    assert!(
+        config.settings.seed.is_some(),
+        "golden scenes need a seed to be reproducible"
+    );
+    let cam = config.camera.setup();
+    let settings = config.settings;
+    let render =
+        raytracer::render_rgba_image(&cam, &config.hittables, &settings, SAMPLES_PER_PIXEL);
+    let reference_path = golden_path(&format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        render
+            .save(&reference_path)
+            .expect("unable to write the reference image");
+        return;
+    }
+
+    // the covering tree has to see the same scene as the plain list
+    let covered = CoveredScene::with_scale(config.hittables, settings.scene_scale);
+    let accel_render =
+        raytracer::accel_render_rgba_image(&cam, &covered, &settings, SAMPLES_PER_PIXEL);
+    let reference = image::open(&reference_path)
+        .expect("unable to read the reference image, run with UPDATE_GOLDEN to write it")
+        .to_rgba8();
+    for (label, image) in [("", &render), ("accel_", &accel_render)] {
+        assert_eq!(image.dimensions(), reference.dimensions());
+        let drifted: Vec<(u32, u32, u8)> = image
+            .enumerate_pixels()
+            .zip(reference.pixels())
+            .filter_map(|((x, y, pixel), expected)| {
+                let diff = (pixel.0.iter().zip(expected.0))
+                    .map(|(channel, expected)| channel.abs_diff(expected))
+                    .max()
+                    .unwrap_or(0);
+                (diff > TOLERANCE).then_some((x, y, diff))
+            })
+            .collect();
+        if !drifted.is_empty() {
+            // kept for comparing against the reference by eye
+            let failed_path = std::env::temp_dir().join(format!("golden_{}{}.png", label, name));
+            let _ = image.save(&failed_path);
+            let worst = drifted.iter().max_by_key(|(_, _, diff)| *diff).unwrap();
+            panic!(
+                "{}{}: {} pixels drifted beyond {} from the reference, the worst by {} at ({}, {}), render written to {}",
+                label,
+                name,
+                drifted.len(),
+                TOLERANCE,
+                worst.2,
+                worst.0,
+                worst.1,
+                failed_path.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn spheres_golden_test() {
+    check_golden("spheres");
+}